@@ -1,4 +1,5 @@
 use anyhow::{Context, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::process::Command;
@@ -31,11 +32,13 @@ pub struct AgentProcessInfo {
 }
 
 /// Configuration for monitoring agents
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AgentMonitorConfig {
     /// Only show agents spawned by our system
+    #[serde(default)]
     pub only_our_agents: bool,
     /// Only show agents waiting for input
+    #[serde(default)]
     pub only_waiting_agents: bool,
     /// Filter by specific agent types (e.g., ["cursor-cli", "code"])
     pub agent_types: Option<Vec<String>>,
@@ -130,6 +133,25 @@ impl AgentMonitor {
         Ok(agents)
     }
 
+    /// Get all agent processes whose working directory is inside the given directory
+    pub async fn get_agents_in_directory(&mut self, dir: &std::path::Path) -> Result<Vec<AgentProcessInfo>> {
+        self.refresh().await?;
+
+        let mut agents = Vec::new();
+
+        for (pid, process) in self.system.processes() {
+            let in_dir = process.cwd().is_some_and(|cwd| cwd.starts_with(dir));
+            if in_dir && self.is_agent_process(process) {
+                agents.push(self.create_agent_info(pid.as_u32(), process)?);
+            }
+        }
+
+        agents.sort_by_key(|a| a.pid);
+
+        debug!("Found {} agent processes in {}", agents.len(), dir.display());
+        Ok(agents)
+    }
+
     /// Check if a process is likely an agent (editor/IDE)
     fn is_agent_process(&self, process: &sysinfo::Process) -> bool {
         let name = process.name().to_lowercase();
@@ -179,7 +201,6 @@ impl AgentMonitor {
         #[cfg(unix)]
         {
             use std::fs;
-            use std::io::Read;
             
             // Check if stdin is a terminal (TTY)
             let stdin_path = format!("/proc/{}/fd/0", pid);
@@ -199,7 +220,7 @@ impl AgentMonitor {
     /// Determine if a process was spawned by our system
     fn is_spawned_by_us(&self, cmd: &[String], cwd: &str) -> bool {
         // Check if the working directory is a worktree of our repository
-        if let Some(worktree_path) = self.find_associated_worktree(cwd) {
+        if self.find_associated_worktree(cwd).is_some() {
             // Check if the command contains our typical agent spawning patterns
             let cmd_str = cmd.join(" ");
             
@@ -323,3 +344,13 @@ pub struct AgentSummary {
     /// Count of agents by type
     pub agent_types: HashMap<String, usize>,
 }
+
+/// Whether a process with the given pid is running
+///
+/// Exited processes that have not been reaped yet count as not running.
+pub fn is_process_running(pid: u32) -> bool {
+    let pid = sysinfo::Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_process(pid)
+        && system.process(pid).is_some_and(|process| process.status() != sysinfo::ProcessStatus::Zombie)
+}
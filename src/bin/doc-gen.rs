@@ -1,5 +1,6 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use std::path::Path;
 
 use subagent_worktree_mcp::doc_generator::{DocGenerator, run_doc_generator};
 
@@ -44,73 +45,34 @@ enum Commands {
     List,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let cli = Cli::parse();
     
     match cli.command {
         Commands::Update { readme, report } => {
             println!("🔄 Updating documentation from schema...");
-            
-            let generator = DocGenerator::new();
-            
-            // Validate implementation first
-            generator.validate_implementation()?;
-            
-            // Update README
-            generator.update_readme(std::path::Path::new(&readme))?;
+            run_doc_generator("update", Path::new(&readme))?;
             
             if report {
-                let report_content = generator.generate_schema_report();
-                std::fs::write("SCHEMA_REPORT.md", report_content)?;
+                std::fs::write("SCHEMA_REPORT.md", DocGenerator::generate_tools_documentation())?;
                 println!("📊 Generated SCHEMA_REPORT.md");
             }
-            
-            println!("✅ Documentation update complete!");
         }
         
         Commands::Validate => {
             println!("🔍 Validating documentation against implementation...");
-            
-            let generator = DocGenerator::new();
-            generator.validate_implementation()?;
-            
-            println!("✅ All documentation is valid!");
+            run_doc_generator("validate", Path::new("README.md"))?;
         }
         
         Commands::Report { output } => {
             println!("📊 Generating schema report...");
-            
-            let generator = DocGenerator::new();
-            let report_content = generator.generate_schema_report();
-            
-            std::fs::write(&output, report_content)?;
+            std::fs::write(&output, DocGenerator::generate_tools_documentation())?;
             println!("✅ Schema report generated: {}", output);
         }
         
         Commands::List => {
             println!("📋 Current tool definitions:");
-            
-            let generator = DocGenerator::new();
-            
-            for tool in &generator.tools {
-                println!("\n🔧 {}", tool.name);
-                println!("   Description: {}", tool.description);
-                println!("   Destructive: {}", tool.is_destructive);
-                println!("   Parameters: {} total", tool.parameters.len());
-                
-                for param in &tool.parameters {
-                    let required = if param.required { "required" } else { "optional" };
-                    println!("     - {}: {} ({})", param.name, required, param.param_type);
-                }
-                
-                if !tool.warnings.is_empty() {
-                    println!("   Warnings:");
-                    for warning in &tool.warnings {
-                        println!("     - {}", warning);
-                    }
-                }
-            }
+            DocGenerator::list_tools();
         }
     }
     
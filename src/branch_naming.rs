@@ -0,0 +1,136 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Default template for generated branch names
+pub const DEFAULT_BRANCH_TEMPLATE: &str = "subagent/{agent}/{slug(prompt)}-{short_id}";
+
+/// Maximum number of prompt words used in a slug
+const SLUG_MAX_WORDS: usize = 6;
+
+/// Maximum length of a slug before policy limits are applied
+const SLUG_MAX_LENGTH: usize = 40;
+
+/// Team policy for subagent branch names
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BranchNamingPolicy {
+    /// Template for generated branch names; supports `{agent}`, `{slug(prompt)}` and `{short_id}`
+    pub template: String,
+    /// Prefix every branch name must start with
+    pub required_prefix: Option<String>,
+    /// Maximum length of a branch name
+    pub max_length: Option<usize>,
+    /// Refuse to spawn on a branch that already exists, instead of reusing it
+    pub forbid_existing: bool,
+}
+
+impl Default for BranchNamingPolicy {
+    fn default() -> Self {
+        Self {
+            template: DEFAULT_BRANCH_TEMPLATE.to_string(),
+            required_prefix: None,
+            max_length: None,
+            forbid_existing: false,
+        }
+    }
+}
+
+impl BranchNamingPolicy {
+    /// Generate a branch name from the template
+    ///
+    /// If the name would exceed `max_length`, the prompt slug is shortened to fit.
+    pub fn generate(&self, agent: &str, prompt: &str, short_id: &str) -> Result<String> {
+        let slug = slugify(prompt);
+        let name = self.render(agent, &slug, short_id)?;
+
+        let name = match self.max_length {
+            Some(max_length) if name.len() > max_length => {
+                // Measure with a one-character slug, since an empty one also drops its separator
+                let fixed_length = self.render(agent, "x", short_id)?.len() - 1;
+                let slug_length = max_length.saturating_sub(fixed_length);
+                let shortened = slug[..slug_length.min(slug.len())].trim_end_matches('-');
+                self.render(agent, shortened, short_id)?
+            }
+            _ => name,
+        };
+
+        self.validate(&name)?;
+        Ok(name)
+    }
+
+    /// Check a branch name against git's refname rules and the policy
+    pub fn validate(&self, name: &str) -> Result<()> {
+        // Names starting with '-' are valid refnames but would be taken for options by git
+        if name.starts_with('-') || !git2::Reference::is_valid_name(&format!("refs/heads/{}", name)) {
+            return Err(anyhow::anyhow!("'{}' is not a valid git branch name", name));
+        }
+
+        if let Some(prefix) = &self.required_prefix {
+            if !name.starts_with(prefix.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "Branch name '{}' does not start with the required prefix '{}'",
+                    name,
+                    prefix
+                ));
+            }
+        }
+
+        if let Some(max_length) = self.max_length {
+            if name.len() > max_length {
+                return Err(anyhow::anyhow!(
+                    "Branch name '{}' is {} characters long, the maximum is {}",
+                    name,
+                    name.len(),
+                    max_length
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Substitute the template placeholders
+    fn render(&self, agent: &str, slug: &str, short_id: &str) -> Result<String> {
+        let mut name = String::new();
+        let mut rest = self.template.as_str();
+
+        while let Some(start) = rest.find('{') {
+            name.push_str(&rest[..start]);
+            let end = rest[start..].find('}')
+                .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in branch template '{}'", self.template))?;
+            let value = match &rest[start + 1..start + end] {
+                "agent" => slugify(agent),
+                "slug(prompt)" => slug.to_string(),
+                "short_id" => short_id.to_string(),
+                other => return Err(anyhow::anyhow!(
+                    "Unknown placeholder '{{{}}}' in branch template '{}'",
+                    other,
+                    self.template
+                )),
+            };
+            name.push_str(&value);
+            rest = &rest[start + end + 1..];
+        }
+        name.push_str(rest);
+
+        // An empty slug can leave doubled or dangling separators behind
+        let name = name.replace("/-", "/").replace("--", "-");
+        Ok(name.trim_matches(|c| c == '-' || c == '/').to_string())
+    }
+}
+
+/// Turn free text into a short, lowercase, dash-separated branch name component
+pub fn slugify(text: &str) -> String {
+    let slug = text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .take(SLUG_MAX_WORDS)
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.len() <= SLUG_MAX_LENGTH {
+        return slug;
+    }
+    slug[..SLUG_MAX_LENGTH].trim_end_matches('-').to_string()
+}
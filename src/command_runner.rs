@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command as TokioCommand;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Maximum number of bytes of stdout/stderr kept for each command
+pub const MAX_CAPTURED_OUTPUT: usize = 64 * 1024;
+
+/// Default timeout for commands run in worktrees and merge verification commands, in seconds
+pub const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 600;
+
+/// Result of running a shell command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandOutput {
+    /// The command that was run
+    pub command: String,
+    /// Exit code, or `None` if the command was killed or timed out
+    pub exit_code: Option<i32>,
+    /// Captured standard output (the tail, if it exceeded the capture limit)
+    pub stdout: String,
+    /// Captured standard error (the tail, if it exceeded the capture limit)
+    pub stderr: String,
+    /// Whether the command was killed for exceeding its timeout
+    pub timed_out: bool,
+    /// How long the command ran, in milliseconds
+    pub duration_ms: u64,
+}
+
+impl CommandOutput {
+    /// Whether the command exited successfully
+    pub fn success(&self) -> bool {
+        !self.timed_out && self.exit_code == Some(0)
+    }
+
+    /// Short description of how the command finished
+    pub fn status_description(&self) -> String {
+        match (self.timed_out, self.exit_code) {
+            (true, _) => format!("timed out after {}ms", self.duration_ms),
+            (false, Some(code)) => format!("exited with code {}", code),
+            (false, None) => "terminated by signal".to_string(),
+        }
+    }
+}
+
+/// Run a command through the shell in the given directory, killing it if it exceeds the timeout
+///
+/// The command is run with `sh -c` (`cmd /C` on Windows) and stdin closed, so it
+/// cannot block waiting for input. Output is captured rather than inherited, since
+/// stdout is the MCP transport. On Unix the command gets its own process group, so a
+/// timeout kills everything it started, not just the shell; the output captured up to
+/// the timeout is returned with `timed_out` set.
+pub async fn run_shell_command(command: &str, cwd: &Path, timeout: Duration) -> Result<CommandOutput> {
+    debug!("Running '{}' in {}", command, cwd.display());
+
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = TokioCommand::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = TokioCommand::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let mut child = cmd
+        .arg(command)
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context(format!("Failed to start command '{}'", command))?;
+
+    let started = Instant::now();
+    // Kills the group if this future is dropped before the command finishes
+    let mut group = ProcessGroup::new(&child);
+    let mut stdout = Captured::read(child.stdout.take());
+    let mut stderr = Captured::read(child.stderr.take());
+
+    // Output is complete once the pipes close, which may be after the shell exits
+    let finished = async {
+        let status = child.wait().await;
+        stdout.finish().await;
+        stderr.finish().await;
+        status
+    };
+    let result = tokio::time::timeout(timeout, finished).await;
+
+    let (exit_code, timed_out) = match result {
+        Ok(status) => {
+            let status = status.context(format!("Failed to wait for command '{}'", command))?;
+            group.disarm();
+            (status.code(), false)
+        }
+        Err(_) => {
+            warn!("Command '{}' timed out after {:?}", command, timeout);
+            group.kill();
+            let _ = child.start_kill();
+            let _ = child.wait().await;
+            // Children outside the group may still hold the pipes; keep what arrived so far
+            let _ = tokio::time::timeout(OUTPUT_GRACE_PERIOD, async {
+                stdout.finish().await;
+                stderr.finish().await;
+            }).await;
+            (None, true)
+        }
+    };
+    let duration_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+    Ok(CommandOutput {
+        command: command.to_string(),
+        exit_code,
+        stdout: capture_tail(&stdout.bytes()),
+        stderr: capture_tail(&stderr.bytes()),
+        timed_out,
+        duration_ms,
+    })
+}
+
+/// How long to wait for the rest of the output once a timed out command is killed
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Output of a command collected as it arrives, so it is kept if the command times out
+struct Captured {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Captured {
+    fn read<R: AsyncRead + Unpin + Send + 'static>(pipe: Option<R>) -> Self {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let reader = pipe.map(|mut pipe| {
+            let buffer = Arc::clone(&buffer);
+            tokio::spawn(async move {
+                let mut chunk = [0u8; 8192];
+                while let Ok(read) = pipe.read(&mut chunk).await {
+                    if read == 0 {
+                        break;
+                    }
+                    if let Ok(mut buffer) = buffer.lock() {
+                        buffer.extend_from_slice(&chunk[..read]);
+                        // Only the tail is reported, so there is no need to keep more
+                        let excess = buffer.len().saturating_sub(2 * MAX_CAPTURED_OUTPUT);
+                        if excess > 0 {
+                            buffer.drain(..excess);
+                        }
+                    }
+                }
+            })
+        });
+        Self { buffer, reader }
+    }
+
+    /// Wait until the pipe is closed
+    async fn finish(&mut self) {
+        if let Some(reader) = self.reader.as_mut() {
+            let _ = reader.await;
+            self.reader = None;
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.buffer.lock().map(|buffer| buffer.clone()).unwrap_or_default()
+    }
+}
+
+impl Drop for Captured {
+    fn drop(&mut self) {
+        if let Some(reader) = &self.reader {
+            reader.abort();
+        }
+    }
+}
+
+/// The process group a command runs in, killed unless the command finishes
+struct ProcessGroup {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    id: Option<u32>,
+}
+
+impl ProcessGroup {
+    fn new(child: &tokio::process::Child) -> Self {
+        Self { id: child.id() }
+    }
+
+    fn disarm(&mut self) {
+        self.id = None;
+    }
+
+    /// Kill every process in the group
+    ///
+    /// Elsewhere than Unix the command has no group of its own and only the shell is
+    /// killed, by `kill_on_drop`.
+    fn kill(&mut self) {
+        #[cfg(unix)]
+        if let Some(id) = self.id.take().and_then(|id| libc::pid_t::try_from(id).ok()) {
+            // SAFETY: killpg only sends a signal; the group was created for this command
+            // with process_group(0), so its id is the shell's pid
+            unsafe {
+                libc::killpg(id, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// Keep the last `MAX_CAPTURED_OUTPUT` bytes of output, where errors usually are
+fn capture_tail(bytes: &[u8]) -> String {
+    let start = bytes.len().saturating_sub(MAX_CAPTURED_OUTPUT);
+    let tail = String::from_utf8_lossy(&bytes[start..]);
+    if start > 0 {
+        format!("[... {} bytes truncated ...]\n{}", start, tail)
+    } else {
+        tail.into_owned()
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::branch_naming::BranchNamingPolicy;
+use crate::gc::GcPolicy;
+use crate::git_operations::{GitIdentity, SyncStrategy};
+use crate::hooks::BootstrapConfig;
+use crate::subagent_spawner::AgentOptions;
+
+/// Name of the configuration file, looked up in the repository root
+pub const CONFIG_FILE_NAME: &str = ".subagent-worktree-mcp.json";
+
+/// Repository-level server configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Garbage collection of stale and orphaned worktrees
+    pub gc: GcSettings,
+    /// Setup run in every new worktree before its agent starts
+    pub bootstrap: BootstrapConfig,
+    /// Syncing subagent worktrees with their base branch
+    pub sync: SyncSettings,
+    /// Naming and validation of subagent branches
+    pub branch_naming: BranchNamingPolicy,
+    /// Checkpoint commits of subagent work
+    pub checkpoint: CheckpointSettings,
+    /// Directory new worktrees are created in, relative to the repository root
+    /// (defaults to the repository's parent directory)
+    pub worktree_root: Option<PathBuf>,
+    /// Agent used when a spawn request does not name one
+    pub agent_defaults: AgentDefaults,
+    /// Initialize submodules in new worktrees unless a spawn request says otherwise
+    pub submodules: bool,
+    /// Additional repositories managed by the server; only read from the launch directory
+    pub repositories: Vec<RepositoryEntry>,
+    /// Integration of subagent branches through the merge queue
+    pub merge_queue: MergeQueueSettings,
+    /// Commands `run_in_worktree` may run
+    pub commands: CommandSettings,
+    /// Directory of additional prompt templates, relative to the repository root
+    pub prompts_dir: Option<PathBuf>,
+}
+
+impl ServerConfig {
+    /// Load the configuration from the repository root, falling back to defaults if absent
+    pub fn load(repo_path: &Path) -> Result<Self> {
+        let path = repo_path.join(CONFIG_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .context(format!("Failed to read config file {}", path.display()))?;
+        let config = serde_json::from_str(&content)
+            .context(format!("Failed to parse config file {}", path.display()))?;
+
+        info!("Loaded configuration from {}", path.display());
+        Ok(config)
+    }
+}
+
+/// Settings for worktree garbage collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GcSettings {
+    /// What to do with collected worktrees
+    pub policy: GcPolicy,
+    /// Subagent worktrees idle for longer than this many seconds are collected
+    pub idle_ttl_secs: Option<u64>,
+    /// Run garbage collection in the background every this many seconds (disabled if unset)
+    pub interval_secs: Option<u64>,
+}
+
+impl Default for GcSettings {
+    fn default() -> Self {
+        Self {
+            policy: GcPolicy::ReportOnly,
+            idle_ttl_secs: Some(7 * 24 * 60 * 60),
+            interval_secs: None,
+        }
+    }
+}
+
+/// Settings for syncing subagent worktrees with their base branch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncSettings {
+    /// Name of a configured remote to fetch the base branch from (the local branch is used if unset)
+    pub remote: Option<String>,
+    /// Whether to rebase onto or merge the base branch
+    pub strategy: SyncStrategy,
+}
+
+/// Settings for checkpoint commits in subagent worktrees
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheckpointSettings {
+    /// Commit identities by agent type; agents without an entry commit as git's configured user
+    pub identities: IndexMap<String, CheckpointIdentity>,
+    /// Add a `Subagent-Id` trailer to checkpoints of registered subagents
+    pub subagent_trailer: bool,
+    /// Checkpoint a subagent's worktree when its agent exits
+    pub on_agent_exit: bool,
+}
+
+impl Default for CheckpointSettings {
+    fn default() -> Self {
+        Self {
+            identities: IndexMap::new(),
+            subagent_trailer: true,
+            on_agent_exit: false,
+        }
+    }
+}
+
+/// Author and committer of checkpoint commits made for one agent type
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheckpointIdentity {
+    /// Author of checkpoint commits
+    pub author: Option<GitIdentity>,
+    /// Committer of checkpoint commits (defaults to the author)
+    pub committer: Option<GitIdentity>,
+}
+
+/// Agent used for spawn requests that do not specify one
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentDefaults {
+    /// Agent type (defaults to "cursor-cli")
+    pub agent_type: Option<String>,
+    /// Agent-specific options
+    pub agent_options: Option<AgentOptions>,
+}
+
+/// A repository managed by the server alongside the one it was started in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepositoryEntry {
+    /// Path of the repository
+    pub path: PathBuf,
+    /// Name used to refer to the repository in tool calls (defaults to its directory name)
+    pub name: Option<String>,
+    /// Overrides the repository's own `worktree_root`
+    pub worktree_root: Option<PathBuf>,
+    /// Overrides the repository's own `agent_defaults`
+    pub agent_defaults: Option<AgentDefaults>,
+}
+
+impl RepositoryEntry {
+    /// Entry for the repository at `path`, with no overrides
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            name: None,
+            worktree_root: None,
+            agent_defaults: None,
+        }
+    }
+}
+
+/// Settings for the merge queue
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MergeQueueSettings {
+    /// Branch queued branches are merged into (defaults to the branch checked out in the main checkout)
+    pub target_branch: Option<String>,
+    /// Shell command run in the integration worktree after each merge, such as `cargo test`
+    pub verify_command: Option<String>,
+    /// Timeout for the verification command in seconds
+    /// (default: [`DEFAULT_COMMAND_TIMEOUT_SECS`](crate::command_runner::DEFAULT_COMMAND_TIMEOUT_SECS), 600)
+    pub verify_timeout_secs: Option<u64>,
+    /// Directory name of the integration worktree, created in the worktree root
+    /// (defaults to `<repository>-merge-queue`)
+    pub integration_dir: Option<String>,
+}
+
+/// Commands that may be run in worktrees through `run_in_worktree`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandSettings {
+    /// Named tasks, such as `"test": "cargo test"`
+    pub tasks: IndexMap<String, String>,
+    /// Further commands that may be run verbatim
+    pub allowed_commands: Vec<String>,
+    /// Timeout for each command in seconds
+    /// (default: [`DEFAULT_COMMAND_TIMEOUT_SECS`](crate::command_runner::DEFAULT_COMMAND_TIMEOUT_SECS), 600)
+    pub timeout_secs: Option<u64>,
+}
@@ -0,0 +1,370 @@
+use anyhow::{Context, Result};
+use git2::{DiffOptions, Oid, Patch, Repository, Tree};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use tokio::task;
+use tracing::{debug, info, warn};
+
+use crate::git_operations::GitWorktreeManager;
+use crate::registry::SubagentRegistry;
+
+/// Options for a conflict prediction run
+#[derive(Debug, Clone, Default)]
+pub struct ConflictOptions {
+    /// Base branch for subagents spawned without a recorded base
+    pub base_branch: Option<String>,
+}
+
+/// Changes of one subagent relative to the commit it forked from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubagentChanges {
+    /// Subagent ID
+    pub id: String,
+    /// Branch of the subagent
+    pub branch: String,
+    /// Base the subagent is compared against
+    pub base: String,
+    /// Paths changed since the fork point, including uncommitted changes
+    pub changed_files: Vec<String>,
+    /// Whether the worktree has changes that are not committed yet
+    pub uncommitted: bool,
+}
+
+/// A file changed on both sides of a comparison
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileOverlap {
+    /// Path of the file
+    pub path: String,
+    /// Whether the changed line ranges overlap or touch, or `None` if the two
+    /// sides forked from different commits and their line numbers cannot be compared
+    pub hunks_overlap: Option<bool>,
+}
+
+/// Predicted integration problems between two lines of work
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictPrediction {
+    /// First side: a subagent branch
+    pub left: String,
+    /// Second side: another subagent branch, or the base
+    pub right: String,
+    /// Files changed on both sides
+    pub overlapping_files: Vec<FileOverlap>,
+    /// Files that conflict when the two sides are merged
+    pub conflicting_files: Vec<String>,
+}
+
+/// Result of predicting conflicts between active subagents
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConflictReport {
+    /// Subagents that were compared
+    pub subagents: Vec<SubagentChanges>,
+    /// Subagents, or pairs of sides, that could not be compared, with the reason
+    pub skipped: Vec<String>,
+    /// Overlaps between pairs of subagents
+    pub between_subagents: Vec<ConflictPrediction>,
+    /// Overlaps between subagents and the current state of their base
+    pub with_base: Vec<ConflictPrediction>,
+}
+
+impl ConflictReport {
+    /// Human-readable summary of the report
+    pub fn summary(&self) -> String {
+        let mut result = format!("Compared {} active subagents\n", self.subagents.len());
+        for skipped in &self.skipped {
+            result.push_str(&format!("  - skipped {}\n", skipped));
+        }
+
+        if self.between_subagents.is_empty() && self.with_base.is_empty() {
+            result.push_str("No overlapping changes found\n");
+            return result;
+        }
+
+        for (title, predictions) in [("Between subagents", &self.between_subagents), ("With base", &self.with_base)] {
+            if predictions.is_empty() {
+                continue;
+            }
+            result.push_str(&format!("{}:\n", title));
+            for prediction in predictions {
+                let status = if prediction.conflicting_files.is_empty() { "merges cleanly" } else { "CONFLICTS" };
+                result.push_str(&format!("  - {} <-> {}: {}\n", prediction.left, prediction.right, status));
+                for overlap in &prediction.overlapping_files {
+                    let hunks = match overlap.hunks_overlap {
+                        Some(true) => "overlapping lines",
+                        Some(false) => "separate lines",
+                        None => "different fork points",
+                    };
+                    let conflict = if prediction.conflicting_files.contains(&overlap.path) { ", conflict" } else { "" };
+                    result.push_str(&format!("      {} ({}{})\n", overlap.path, hunks, conflict));
+                }
+                // Conflicts can also arise on files only one side changed, e.g. modify/delete
+                for path in &prediction.conflicting_files {
+                    if !prediction.overlapping_files.iter().any(|overlap| &overlap.path == path) {
+                        result.push_str(&format!("      {} (conflict)\n", path));
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// A subagent worktree to compare
+#[derive(Debug, Clone)]
+struct Candidate {
+    id: String,
+    branch: String,
+    worktree_path: PathBuf,
+    base_ref: Option<String>,
+    base_commit: Option<String>,
+}
+
+/// A subagent's work, captured as trees
+struct Snapshot {
+    changes: SubagentChanges,
+    /// Commit the subagent forked from
+    fork_point: Oid,
+    /// Current commit of the base
+    current_base: Oid,
+    /// Tree of the worktree, including uncommitted changes
+    tree: Oid,
+    /// Changed line ranges in the fork point's version of each changed file
+    hunks: IndexMap<String, Vec<LineRange>>,
+}
+
+/// A range of lines in the old version of a file, `start..start + lines`
+type LineRange = (u32, u32);
+
+/// Predict conflicts between all registered subagents whose worktrees still exist
+pub async fn predict_conflicts(
+    git_manager: &GitWorktreeManager,
+    registry: &Mutex<SubagentRegistry>,
+    options: &ConflictOptions,
+) -> Result<ConflictReport> {
+    let candidates: Vec<Candidate> = registry.lock().await
+        .records()
+        .filter(|record| record.worktree_path.exists())
+        .map(|record| Candidate {
+            id: record.id.clone(),
+            branch: record.branch.clone(),
+            worktree_path: record.worktree_path.clone(),
+            base_ref: record.base_ref.clone(),
+            base_commit: record.base_commit.clone(),
+        })
+        .collect();
+
+    let repo_path = git_manager.repo_path().to_path_buf();
+    let options = options.clone();
+
+    task::spawn_blocking(move || predict_conflicts_blocking(&repo_path, &candidates, &options))
+        .await
+        .context("Failed to spawn blocking task")?
+}
+
+fn predict_conflicts_blocking(repo_path: &Path, candidates: &[Candidate], options: &ConflictOptions) -> Result<ConflictReport> {
+    let repo = Repository::open(repo_path).context("Failed to open git repository")?;
+    let mut report = ConflictReport::default();
+
+    let mut snapshots = Vec::new();
+    for candidate in candidates {
+        match snapshot(&repo, candidate, options) {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(e) => {
+                warn!("Skipping subagent {} in conflict prediction: {:#}", candidate.id, e);
+                report.skipped.push(format!("{} ({}): {:#}", candidate.id, candidate.branch, e));
+            }
+        }
+    }
+
+    // A pair that cannot be compared is reported, rather than failing the whole run
+    for (i, left) in snapshots.iter().enumerate() {
+        for right in &snapshots[i + 1..] {
+            match compare_subagents(&repo, left, right) {
+                Ok(Some(prediction)) => report.between_subagents.push(prediction),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("Skipping {} <-> {} in conflict prediction: {:#}", left.changes.branch, right.changes.branch, e);
+                    report.skipped.push(format!("{} <-> {}: {:#}", left.changes.branch, right.changes.branch, e));
+                }
+            }
+        }
+    }
+
+    for snapshot in &snapshots {
+        match compare_with_base(&repo, snapshot) {
+            Ok(Some(prediction)) => report.with_base.push(prediction),
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Skipping {} <-> its base in conflict prediction: {:#}", snapshot.changes.branch, e);
+                report.skipped.push(format!("{} <-> {}: {:#}", snapshot.changes.branch, snapshot.changes.base, e));
+            }
+        }
+    }
+
+    info!(
+        "Predicted conflicts for {} subagents: {} overlapping pairs, {} overlapping with their base",
+        snapshots.len(),
+        report.between_subagents.len(),
+        report.with_base.len()
+    );
+    report.subagents = snapshots.into_iter().map(|snapshot| snapshot.changes).collect();
+    Ok(report)
+}
+
+/// Predict problems merging two subagents' work, or `None` if they touch separate files
+fn compare_subagents(repo: &Repository, left: &Snapshot, right: &Snapshot) -> Result<Option<ConflictPrediction>> {
+    let ancestor = repo.merge_base(left.fork_point, right.fork_point)
+        .context(format!("No common history between '{}' and '{}'", left.changes.branch, right.changes.branch))?;
+    let same_fork_point = left.fork_point == right.fork_point;
+
+    let overlapping_files = overlaps(&left.hunks, &right.hunks, same_fork_point);
+    let conflicting_files = merge_conflicts(repo, ancestor, left.tree, right.tree)?;
+    if overlapping_files.is_empty() && conflicting_files.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(ConflictPrediction {
+        left: left.changes.branch.clone(),
+        right: right.changes.branch.clone(),
+        overlapping_files,
+        conflicting_files,
+    }))
+}
+
+/// Predict problems merging a subagent's work into the current state of its base
+fn compare_with_base(repo: &Repository, snapshot: &Snapshot) -> Result<Option<ConflictPrediction>> {
+    if snapshot.current_base == snapshot.fork_point {
+        return Ok(None);
+    }
+    let fork_tree = repo.find_commit(snapshot.fork_point)?.tree()?;
+    let base_tree = repo.find_commit(snapshot.current_base)?.tree()?;
+    let base_hunks = changed_hunks(repo, &fork_tree, &base_tree)?;
+
+    let overlapping_files = overlaps(&snapshot.hunks, &base_hunks, true);
+    let conflicting_files = merge_conflicts(repo, snapshot.fork_point, snapshot.tree, base_tree.id())?;
+    if overlapping_files.is_empty() && conflicting_files.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(ConflictPrediction {
+        left: snapshot.changes.branch.clone(),
+        right: snapshot.changes.base.clone(),
+        overlapping_files,
+        conflicting_files,
+    }))
+}
+
+/// Capture a subagent's work relative to its fork point
+fn snapshot(repo: &Repository, candidate: &Candidate, options: &ConflictOptions) -> Result<Snapshot> {
+    let worktree_repo = Repository::open(&candidate.worktree_path)
+        .context(format!("Failed to open worktree {}", candidate.worktree_path.display()))?;
+    let head = worktree_repo.head()?.peel_to_commit()?;
+    // Trees written through the worktree land in the shared object database
+    let tree = GitWorktreeManager::snapshot_tree(&worktree_repo)?;
+
+    // A subagent forked from a tag or commit has no moving base, only its fork point
+    let base = candidate.base_ref.clone()
+        .or_else(|| candidate.base_commit.clone())
+        .or_else(|| options.base_branch.clone())
+        .unwrap_or_else(|| "HEAD".to_string());
+    let current_base = repo.revparse_single(&base)
+        .and_then(|object| object.peel_to_commit())
+        .context(format!("Failed to resolve base '{}'", base))?
+        .id();
+
+    let fork_point = match &candidate.base_commit {
+        Some(commit) => Oid::from_str(commit).context(format!("Invalid recorded base commit '{}'", commit))?,
+        None => repo.merge_base(head.id(), current_base)?,
+    };
+
+    let fork_tree = repo.find_commit(fork_point)?.tree()?;
+    let hunks = changed_hunks(repo, &fork_tree, &repo.find_tree(tree)?)?;
+    debug!("Subagent {} changed {} files since {}", candidate.id, hunks.len(), fork_point);
+
+    Ok(Snapshot {
+        changes: SubagentChanges {
+            id: candidate.id.clone(),
+            branch: candidate.branch.clone(),
+            base,
+            changed_files: hunks.keys().cloned().collect(),
+            uncommitted: tree != head.tree_id(),
+        },
+        fork_point,
+        current_base,
+        tree,
+        hunks,
+    })
+}
+
+/// Changed files between two trees, with the changed line ranges of the old version
+fn changed_hunks(repo: &Repository, old_tree: &Tree, new_tree: &Tree) -> Result<IndexMap<String, Vec<LineRange>>> {
+    let mut diff_options = DiffOptions::new();
+    diff_options.context_lines(0);
+    let diff = repo.diff_tree_to_tree(Some(old_tree), Some(new_tree), Some(&mut diff_options))?;
+
+    let mut hunks = IndexMap::new();
+    for index in 0..diff.deltas().len() {
+        let Some(delta) = diff.get_delta(index) else {
+            continue;
+        };
+        let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) else {
+            continue;
+        };
+
+        let mut ranges = Vec::new();
+        // Binary files have no patch and no hunks
+        if let Some(patch) = Patch::from_diff(&diff, index)? {
+            for hunk_index in 0..patch.num_hunks() {
+                let (hunk, _) = patch.hunk(hunk_index)?;
+                ranges.push((hunk.old_start(), hunk.old_lines()));
+            }
+        }
+        hunks.insert(path.to_string_lossy().into_owned(), ranges);
+    }
+
+    Ok(hunks)
+}
+
+/// Files changed on both sides, with whether their line ranges overlap
+fn overlaps(
+    left: &IndexMap<String, Vec<LineRange>>,
+    right: &IndexMap<String, Vec<LineRange>>,
+    comparable_lines: bool,
+) -> Vec<FileOverlap> {
+    left.iter()
+        .filter_map(|(path, left_ranges)| {
+            let right_ranges = right.get(path)?;
+            let hunks_overlap = comparable_lines.then(|| {
+                left_ranges.iter().any(|a| right_ranges.iter().any(|b| ranges_touch(*a, *b)))
+            });
+            Some(FileOverlap { path: path.clone(), hunks_overlap })
+        })
+        .collect()
+}
+
+/// Whether two line ranges overlap or are adjacent, which git also treats as a conflict
+///
+/// Pure insertions have no old lines and are treated as touching the line they follow.
+fn ranges_touch((a_start, a_lines): LineRange, (b_start, b_lines): LineRange) -> bool {
+    let a_end = a_start + a_lines.max(1);
+    let b_end = b_start + b_lines.max(1);
+    a_start <= b_end && b_start <= a_end
+}
+
+/// Files that conflict when merging two trees in memory, given the commit they both descend from
+fn merge_conflicts(repo: &Repository, ancestor: Oid, ours: Oid, theirs: Oid) -> Result<Vec<String>> {
+    let ancestor_tree = repo.find_commit(ancestor)?.tree()?;
+    let index = repo.merge_trees(&ancestor_tree, &repo.find_tree(ours)?, &repo.find_tree(theirs)?, None)
+        .context("Failed to merge trees")?;
+
+    let mut conflicting_files = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+        if let Some(entry) = entry {
+            conflicting_files.push(String::from_utf8_lossy(&entry.path).into_owned());
+        }
+    }
+    conflicting_files.dedup();
+    Ok(conflicting_files)
+}
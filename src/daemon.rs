@@ -0,0 +1,148 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{debug, info, warn};
+
+use crate::progress::ToolCallHandler;
+use crate::resources::ResourceNotifier;
+use crate::stream_transport;
+
+/// Name of the daemon's socket in the state directory
+pub const SOCKET_FILE_NAME: &str = "daemon.sock";
+
+/// Name of the daemon's log in the state directory
+pub const LOG_FILE_NAME: &str = "daemon.log";
+
+/// How long to wait for a newly started daemon to accept connections
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval between connection attempts while a daemon starts
+const START_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Path of the daemon socket for a state directory
+pub fn socket_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(SOCKET_FILE_NAME)
+}
+
+/// Bind the daemon socket, replacing a socket left behind by a daemon that exited
+///
+/// Fails if another daemon is listening on the socket.
+pub async fn bind(socket_path: &Path) -> Result<UnixListener> {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).await.is_ok() {
+            return Err(anyhow::anyhow!("A daemon is already listening on {}", socket_path.display()));
+        }
+        debug!("Removing stale daemon socket {}", socket_path.display());
+        std::fs::remove_file(socket_path)
+            .context(format!("Failed to remove stale daemon socket {}", socket_path.display()))?;
+    }
+    if let Some(parent) = socket_path.parent() {
+        std::fs::create_dir_all(parent)
+            .context(format!("Failed to create state directory {}", parent.display()))?;
+    }
+    UnixListener::bind(socket_path).context(format!("Failed to listen on {}", socket_path.display()))
+}
+
+/// Serve MCP to clients attaching over a Unix socket until the listener fails
+///
+/// Each connection carries newline-delimited JSON-RPC messages, like the stdio transport,
+/// and receives resource update notifications. The handler, and with it the subagents
+/// and background tasks, outlives the connections.
+pub async fn serve<H: ToolCallHandler>(
+    listener: UnixListener,
+    handler: Arc<H>,
+    notifier: Arc<ResourceNotifier>,
+) -> Result<()> {
+    info!("Daemon accepting clients");
+
+    loop {
+        let (stream, _) = listener.accept().await.context("Failed to accept client")?;
+        let handler = handler.clone();
+        let notifier = notifier.clone();
+        tokio::spawn(async move {
+            debug!("Client attached");
+            let (reader, writer) = stream.into_split();
+            if let Err(e) = stream_transport::serve_client(reader, writer, handler, notifier).await {
+                debug!("Client connection failed: {:#}", e);
+            }
+            debug!("Client detached");
+        });
+    }
+}
+
+/// Start the daemon for a repository in the background, logging to its state directory
+///
+/// The daemon runs in its own process group, so it is not stopped with the client that started it.
+pub fn start(repo_path: &Path, socket_path: &Path) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let log_path = socket_path.with_file_name(LOG_FILE_NAME);
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|e| anyhow::anyhow!("Failed to open daemon log {}: {}", log_path.display(), e))?;
+
+    std::process::Command::new(std::env::current_exe()?)
+        .arg("--daemon")
+        .current_dir(repo_path)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(log)
+        .process_group(0)
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to start daemon: {}", e))?;
+    Ok(())
+}
+
+/// Connect to the daemon on a socket, starting it first if none is listening
+pub async fn connect_or_start<F>(socket_path: &Path, start: F) -> Result<UnixStream>
+where
+    F: FnOnce() -> Result<()>,
+{
+    if let Ok(stream) = UnixStream::connect(socket_path).await {
+        debug!("Attached to running daemon on {}", socket_path.display());
+        return Ok(stream);
+    }
+
+    info!("Starting daemon on {}", socket_path.display());
+    start()?;
+
+    let deadline = tokio::time::Instant::now() + START_TIMEOUT;
+    loop {
+        match UnixStream::connect(socket_path).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) if tokio::time::Instant::now() >= deadline => {
+                return Err(anyhow::anyhow!(
+                    "Daemon did not start listening on {} within {}s: {}",
+                    socket_path.display(),
+                    START_TIMEOUT.as_secs(),
+                    e
+                ));
+            }
+            Err(_) => tokio::time::sleep(START_POLL_INTERVAL).await,
+        }
+    }
+}
+
+/// Relay stdio to an attached daemon until either side closes
+///
+/// The daemon keeps running when the client goes away, so its subagents do too.
+pub async fn proxy_stdio(stream: UnixStream) -> Result<()> {
+    let (mut from_daemon, mut to_daemon) = stream.into_split();
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+
+    tokio::select! {
+        result = tokio::io::copy(&mut stdin, &mut to_daemon) => {
+            result.context("Failed to forward client messages to the daemon")?;
+        }
+        result = tokio::io::copy(&mut from_daemon, &mut stdout) => {
+            result.context("Failed to forward daemon messages to the client")?;
+            warn!("Daemon closed the connection");
+        }
+    }
+    Ok(())
+}
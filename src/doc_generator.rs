@@ -1,6 +1,5 @@
 use anyhow::Result;
-use mcp::types::Tool;
-use serde_json::Value;
+use crate::mcp::types::{Tool, ToolInputSchema};
 use std::fs;
 use std::path::Path;
 
@@ -44,39 +43,38 @@ impl DocGenerator {
         }
 
         // Parameters from JSON schema
-        if let mcp::types::ToolInputSchema::JsonSchema(schema) = &tool.input_schema {
-            if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
-                if !properties.is_empty() {
-                    doc.push_str("**Parameters:**\n");
+        let ToolInputSchema(schema) = &tool.input_schema;
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            if !properties.is_empty() {
+                doc.push_str("**Parameters:**\n");
                     
-                    let required_fields = schema.get("required")
-                        .and_then(|r| r.as_array())
-                        .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect::<std::collections::HashSet<_>>())
-                        .unwrap_or_default();
-
-                    for (name, prop) in properties {
-                        if let Some(prop_obj) = prop.as_object() {
-                            let description = prop_obj.get("description")
-                                .and_then(|d| d.as_str())
-                                .unwrap_or("No description available");
+                let required_fields = schema.get("required")
+                    .and_then(|r| r.as_array())
+                    .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect::<std::collections::HashSet<_>>())
+                    .unwrap_or_default();
+
+                for (name, prop) in properties {
+                    if let Some(prop_obj) = prop.as_object() {
+                        let description = prop_obj.get("description")
+                            .and_then(|d| d.as_str())
+                            .unwrap_or("No description available");
                             
-                            let param_type = prop_obj.get("type")
-                                .and_then(|t| t.as_str())
-                                .unwrap_or("unknown");
+                        let param_type = prop_obj.get("type")
+                            .and_then(|t| t.as_str())
+                            .unwrap_or("unknown");
                             
-                            let required = required_fields.contains(name.as_str());
+                        let required = required_fields.contains(name.as_str());
                             
-                            doc.push_str(&format!(
-                                "- `{}`: {} ({}, {})\n",
-                                name,
-                                description,
-                                param_type,
-                                if required { "required" } else { "optional" }
-                            ));
-                        }
+                        doc.push_str(&format!(
+                            "- `{}`: {} ({}, {})\n",
+                            name,
+                            description,
+                            param_type,
+                            if required { "required" } else { "optional" }
+                        ));
                     }
-                    doc.push_str("\n");
                 }
+                doc.push_str("\n");
             }
         }
 
@@ -126,7 +124,7 @@ impl DocGenerator {
                 let existing_docs_end = end_idx;
                 let existing_docs = &readme_content[existing_docs_start..existing_docs_end].trim();
 
-                if existing_docs == generated_docs.trim() {
+                if *existing_docs == generated_docs.trim() {
                     println!("✅ Documentation matches implementation.");
                     return Ok(true);
                 } else {
@@ -152,19 +150,18 @@ impl DocGenerator {
                 println!("   Description: {}", description);
             }
             
-            if let mcp::types::ToolInputSchema::JsonSchema(schema) = &tool.input_schema {
-                if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
-                    println!("   Parameters: {} total", properties.len());
-                    for (name, prop) in properties {
-                        if let Some(prop_obj) = prop.as_object() {
-                            let description = prop_obj.get("description")
-                                .and_then(|d| d.as_str())
-                                .unwrap_or("No description");
-                            let param_type = prop_obj.get("type")
-                                .and_then(|t| t.as_str())
-                                .unwrap_or("unknown");
-                            println!("     - {}: {} ({})", name, description, param_type);
-                        }
+            let ToolInputSchema(schema) = &tool.input_schema;
+            if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+                println!("   Parameters: {} total", properties.len());
+                for (name, prop) in properties {
+                    if let Some(prop_obj) = prop.as_object() {
+                        let description = prop_obj.get("description")
+                            .and_then(|d| d.as_str())
+                            .unwrap_or("No description");
+                        let param_type = prop_obj.get("type")
+                            .and_then(|t| t.as_str())
+                            .unwrap_or("unknown");
+                        println!("     - {}: {} ({})", name, description, param_type);
                     }
                 }
             }
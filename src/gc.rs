@@ -0,0 +1,435 @@
+use anyhow::{Context, Result};
+use git2::{BranchType, Repository};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task;
+use tracing::{debug, info, warn};
+
+use crate::agent_monitor::AgentMonitor;
+use crate::git_operations::{same_path, GitWorktreeManager, WorktreeInfo};
+use crate::registry::{unix_now, SubagentRecord, SubagentRegistry};
+
+/// What to do with worktrees selected for garbage collection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GcPolicy {
+    /// Only report what would be collected
+    #[default]
+    ReportOnly,
+    /// Keep the branch tip under `refs/subagent-archive/`, then remove worktree and branch
+    Archive,
+    /// Remove the worktree, and the branch if it is merged
+    Remove,
+}
+
+impl fmt::Display for GcPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GcPolicy::ReportOnly => write!(f, "report_only"),
+            GcPolicy::Archive => write!(f, "archive"),
+            GcPolicy::Remove => write!(f, "remove"),
+        }
+    }
+}
+
+/// Options for a single garbage collection run
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+    /// What to do with collected worktrees
+    pub policy: GcPolicy,
+    /// Subagent worktrees idle for longer than this many seconds are collected
+    pub idle_ttl_secs: Option<u64>,
+    /// Base branch for the merged check when none was recorded at spawn
+    pub base_branch: Option<String>,
+}
+
+/// Why a worktree was selected for collection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GcReason {
+    /// The branch is fully merged into its base
+    Merged { base: String },
+    /// The subagent has been idle for longer than the TTL
+    Idle { idle_secs: u64 },
+    /// The worktree was created by us but has no registry entry
+    Orphaned,
+}
+
+impl fmt::Display for GcReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GcReason::Merged { base } => write!(f, "merged into {}", base),
+            GcReason::Idle { idle_secs } => write!(f, "idle for {}h", idle_secs / 3600),
+            GcReason::Orphaned => write!(f, "no registry entry"),
+        }
+    }
+}
+
+/// What happened to a collected worktree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum GcOutcome {
+    /// Nothing was done (report-only policy)
+    Reported,
+    /// The branch was archived and the worktree and branch removed
+    Archived { archive_ref: String },
+    /// The worktree was removed
+    Removed { branch_deleted: bool },
+    /// The worktree was left alone
+    Skipped { reason: String },
+    /// Cleanup was attempted but failed
+    Failed { error: String },
+}
+
+impl fmt::Display for GcOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GcOutcome::Reported => write!(f, "reported"),
+            GcOutcome::Archived { archive_ref } => write!(f, "archived as {}", archive_ref),
+            GcOutcome::Removed { branch_deleted: true } => write!(f, "removed (branch deleted)"),
+            GcOutcome::Removed { branch_deleted: false } => write!(f, "removed (branch kept)"),
+            GcOutcome::Skipped { reason } => write!(f, "skipped: {}", reason),
+            GcOutcome::Failed { error } => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// A collected worktree and what was done with it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcAction {
+    /// Path of the worktree
+    pub path: PathBuf,
+    /// Branch checked out in the worktree
+    pub branch: Option<String>,
+    /// Registry ID of the subagent, if registered
+    pub registry_id: Option<String>,
+    /// Why the worktree was selected
+    pub reasons: Vec<GcReason>,
+    /// What was done
+    pub outcome: GcOutcome,
+}
+
+/// Summary of a garbage collection run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GcReport {
+    /// Policy the run was performed with
+    pub policy: GcPolicy,
+    /// Worktree entries pruned because their directories no longer exist
+    pub pruned: Vec<String>,
+    /// Registry entries whose worktree no longer exists
+    pub stale_records: Vec<String>,
+    /// Collected worktrees
+    pub actions: Vec<GcAction>,
+}
+
+impl GcReport {
+    /// Human-readable summary of the run
+    pub fn summary(&self) -> String {
+        if self.pruned.is_empty() && self.stale_records.is_empty() && self.actions.is_empty() {
+            return "No stale worktrees found".to_string();
+        }
+
+        let mut result = format!("Garbage collection (policy: {}):\n", self.policy);
+
+        if !self.pruned.is_empty() {
+            result.push_str(&format!("Pruned {} worktree entries:\n", self.pruned.len()));
+            for line in &self.pruned {
+                result.push_str(&format!("  - {}\n", line));
+            }
+        }
+
+        if !self.stale_records.is_empty() {
+            result.push_str(&format!(
+                "Stale registry entries: {}\n",
+                self.stale_records.join(", ")
+            ));
+        }
+
+        for action in &self.actions {
+            let reasons: Vec<String> = action.reasons.iter().map(|r| r.to_string()).collect();
+            result.push_str(&format!(
+                "  - {} (branch: {}) [{}] -> {}\n",
+                action.path.display(),
+                action.branch.as_deref().unwrap_or("detached"),
+                reasons.join(", "),
+                action.outcome
+            ));
+        }
+
+        result
+    }
+}
+
+/// State of a worktree's branch relevant to collection
+struct BranchState {
+    /// Whether the branch is merged into its base
+    merged: bool,
+    /// Commit time of the branch tip (Unix seconds)
+    tip_time: u64,
+}
+
+/// Find stale subagent worktrees and clean them up according to the policy
+///
+/// A worktree is considered only if it was created by this server or is registered.
+/// It is collected when its branch is merged into its base, when its subagent has been
+/// idle for longer than the TTL, or when it has no registry entry. Worktrees with running
+/// agent processes or uncommitted changes are never removed.
+pub async fn collect_garbage(
+    git_manager: &GitWorktreeManager,
+    registry: &Mutex<SubagentRegistry>,
+    options: &GcOptions,
+) -> Result<GcReport> {
+    let mut report = GcReport {
+        policy: options.policy,
+        ..GcReport::default()
+    };
+
+    // Equivalent of `git worktree prune` for worktrees whose directories were deleted
+    if options.policy == GcPolicy::ReportOnly {
+        report.pruned = git_manager.list_worktrees().await?
+            .into_iter()
+            .filter(|w| !w.path.exists())
+            .map(|w| format!("Would prune {}", w.path.display()))
+            .collect();
+    } else {
+        report.pruned = git_manager.prune_worktrees().await?;
+    }
+
+    let worktrees = git_manager.list_worktrees().await?;
+    // The first entry is always the main worktree
+    let main_branch = worktrees.first().and_then(|w| w.branch.clone());
+
+    let records: Vec<_> = {
+        let mut registry = registry.lock().await;
+        let stale: Vec<String> = registry.records()
+            .filter(|r| !r.worktree_path.exists() || !worktrees.iter().any(|w| same_path(&w.path, &r.worktree_path)))
+            .map(|r| r.id.clone())
+            .collect();
+
+        if options.policy != GcPolicy::ReportOnly {
+            for id in &stale {
+                registry.remove(id)?;
+            }
+        }
+        report.stale_records = stale;
+
+        registry.records().cloned().collect()
+    };
+
+    let mut monitor = AgentMonitor::new(git_manager.repo_path().to_path_buf());
+    let now = unix_now();
+
+    for worktree in worktrees.iter().skip(1) {
+        if !worktree.path.exists() {
+            continue;
+        }
+
+        let record = records.iter().find(|r| same_path(&r.worktree_path, &worktree.path));
+        if record.is_none() && !GitWorktreeManager::is_managed_worktree(&worktree.path) {
+            // Not one of ours
+            continue;
+        }
+
+        let base = record
+            .and_then(|r| r.base_ref.clone())
+            .or_else(|| options.base_branch.clone())
+            .or_else(|| main_branch.clone());
+        let reasons = select_reasons(git_manager, worktree, record, base, options.idle_ttl_secs, now).await?;
+
+        if reasons.is_empty() {
+            continue;
+        }
+
+        debug!("Worktree {} selected for collection: {:?}", worktree.path.display(), reasons);
+
+        let agents = monitor.get_agents_in_directory(&worktree.path).await?;
+        let merged = reasons.iter().any(|r| matches!(r, GcReason::Merged { .. }));
+        let outcome = if !agents.is_empty() {
+            GcOutcome::Skipped { reason: format!("{} agent processes still running", agents.len()) }
+        } else if let (Some(reason), false) = (&worktree.locked, worktree.has_stale_lock()) {
+            GcOutcome::Skipped { reason: format!("worktree is locked ({})", reason) }
+        } else if worktree.locked.is_some() && options.policy != GcPolicy::ReportOnly {
+            // Left behind by an agent that exited while no server was waiting for it
+            match git_manager.unlock_worktree(&worktree.path).await {
+                Ok(()) => apply_policy(git_manager, options.policy, worktree, merged).await,
+                Err(e) => GcOutcome::Failed { error: format!("Failed to release stale lock: {}", e) },
+            }
+        } else {
+            apply_policy(git_manager, options.policy, worktree, merged).await
+        };
+
+        let cleaned_up = matches!(outcome, GcOutcome::Archived { .. } | GcOutcome::Removed { .. });
+        if let (true, Some(record)) = (cleaned_up, record) {
+            registry.lock().await.remove(&record.id)?;
+        }
+
+        report.actions.push(GcAction {
+            path: worktree.path.clone(),
+            branch: worktree.branch.clone(),
+            registry_id: record.map(|r| r.id.clone()),
+            reasons,
+            outcome,
+        });
+    }
+
+    info!(
+        "Garbage collection finished: {} pruned, {} collected",
+        report.pruned.len(),
+        report.actions.len()
+    );
+    Ok(report)
+}
+
+/// Determine why, if at all, a worktree should be collected
+async fn select_reasons(
+    git_manager: &GitWorktreeManager,
+    worktree: &WorktreeInfo,
+    record: Option<&SubagentRecord>,
+    base: Option<String>,
+    idle_ttl_secs: Option<u64>,
+    now: u64,
+) -> Result<Vec<GcReason>> {
+    let mut reasons = Vec::new();
+    if record.is_none() {
+        reasons.push(GcReason::Orphaned);
+    }
+
+    let (Some(branch), Some(base)) = (worktree.branch.clone(), base) else {
+        return Ok(reasons);
+    };
+
+    let repo_path = git_manager.repo_path().to_path_buf();
+    let fork_point = record.and_then(|r| r.base_commit.clone());
+    let created_at = record.map(|r| r.created_at);
+    let base_name = base.clone();
+    let state = task::spawn_blocking(move || {
+        inspect_branch(&repo_path, &branch, &base_name, fork_point.as_deref(), created_at)
+    })
+    .await
+    .context("Failed to spawn blocking task")?;
+
+    match state {
+        Ok(state) => {
+            if state.merged {
+                reasons.push(GcReason::Merged { base });
+            }
+            if let (Some(record), Some(ttl)) = (record, idle_ttl_secs) {
+                let idle_secs = now.saturating_sub(record.last_activity.max(state.tip_time));
+                if idle_secs > ttl {
+                    reasons.push(GcReason::Idle { idle_secs });
+                }
+            }
+        }
+        Err(e) => warn!("Failed to inspect worktree {}: {}", worktree.path.display(), e),
+    }
+
+    Ok(reasons)
+}
+
+/// Apply the collection policy to a single worktree
+async fn apply_policy(
+    git_manager: &GitWorktreeManager,
+    policy: GcPolicy,
+    worktree: &WorktreeInfo,
+    merged: bool,
+) -> GcOutcome {
+    if policy == GcPolicy::ReportOnly {
+        return GcOutcome::Reported;
+    }
+
+    match git_manager.has_uncommitted_changes(&worktree.path).await {
+        Ok(false) => {}
+        Ok(true) => return GcOutcome::Skipped { reason: "uncommitted changes".to_string() },
+        Err(e) => return GcOutcome::Failed { error: e.to_string() },
+    }
+
+    let result = async {
+        match (policy, worktree.branch.as_deref()) {
+            (GcPolicy::Archive, Some(branch)) => {
+                let archive_ref = git_manager.archive_branch(branch).await?;
+                git_manager.remove_worktree(&worktree.path).await?;
+                git_manager.delete_branch(branch).await?;
+                Ok::<_, anyhow::Error>(GcOutcome::Archived { archive_ref })
+            }
+            (_, branch) => {
+                git_manager.remove_worktree(&worktree.path).await?;
+                // Unmerged branches are kept so no committed work is lost
+                let branch_deleted = match branch {
+                    Some(branch) if merged => {
+                        git_manager.delete_branch(branch).await?;
+                        true
+                    }
+                    _ => false,
+                };
+                Ok(GcOutcome::Removed { branch_deleted })
+            }
+        }
+    };
+
+    result.await.unwrap_or_else(|e| GcOutcome::Failed { error: e.to_string() })
+}
+
+/// Determine whether a branch is merged into its base and when it last changed
+fn inspect_branch(
+    repo_path: &Path,
+    branch_name: &str,
+    base: &str,
+    fork_point: Option<&str>,
+    created_at: Option<u64>,
+) -> Result<BranchState> {
+    let repo = Repository::open(repo_path)
+        .context("Failed to open git repository")?;
+
+    let tip = repo.find_branch(branch_name, BranchType::Local)
+        .context(format!("Branch '{}' not found", branch_name))?
+        .get()
+        .peel_to_commit()
+        .context(format!("Failed to get commit from branch '{}'", branch_name))?;
+    let base_commit = repo.revparse_single(base)
+        .context(format!("Base '{}' not found", base))?
+        .peel_to_commit()
+        .context(format!("Failed to get commit from base '{}'", base))?;
+
+    let tip_time = u64::try_from(tip.time().seconds()).unwrap_or(0);
+
+    // A branch that never got its own commits is not "merged", just unused. The recorded
+    // fork point is exact; older records only have the spawn time to go by.
+    let has_own_commits = match (fork_point, created_at) {
+        (Some(fork_point), _) => tip.id().to_string() != fork_point,
+        (None, Some(created_at)) => tip_time > created_at,
+        (None, None) => tip.id() != base_commit.id(),
+    };
+    let reachable = tip.id() == base_commit.id()
+        || repo.graph_descendant_of(base_commit.id(), tip.id())?;
+
+    Ok(BranchState {
+        merged: has_own_commits && reachable,
+        tip_time,
+    })
+}
+
+/// Run garbage collection periodically in the background
+pub fn spawn_background_gc(
+    git_manager: GitWorktreeManager,
+    registry: Arc<Mutex<SubagentRegistry>>,
+    options: GcOptions,
+    interval: Duration,
+) -> task::JoinHandle<()> {
+    info!("Starting background garbage collection every {}s", interval.as_secs());
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            match collect_garbage(&git_manager, &registry, &options).await {
+                Ok(report) => info!("Background garbage collection:\n{}", report.summary()),
+                Err(e) => warn!("Background garbage collection failed: {}", e),
+            }
+        }
+    })
+}
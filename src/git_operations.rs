@@ -1,12 +1,33 @@
 use anyhow::{Context, Result};
-use git2::{BranchType, Repository};
+use git2::{
+    BranchType, ErrorCode, IndexAddOption, IndexEntryExtendedFlag, Repository, RepositoryState, Signature,
+};
+use std::collections::HashSet;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use tokio::task;
 use tracing::{debug, info, warn};
 
+use crate::agent_monitor::is_process_running;
+
+/// Marker file written into the git directory of every worktree we create
+const SUBAGENT_MARKER: &str = "subagent-worktree-mcp";
+
+/// Directory inside the git common directory where server state is kept
+const STATE_DIR_NAME: &str = "subagent-worktree-mcp";
+
+/// Identity used for checkpoint commits when none is configured and git has no user set
+const FALLBACK_IDENTITY: (&str, &str) = ("subagent-worktree-mcp", "subagent-worktree-mcp@localhost");
+
+/// Attribute that routes a path through git-lfs
+const LFS_FILTER_ATTRIBUTE: &str = "filter=lfs";
+
 /// Manages git worktree operations for subagent spawning
+#[derive(Debug, Clone)]
 pub struct GitWorktreeManager {
     repo_path: PathBuf,
+    worktree_root: Option<PathBuf>,
 }
 
 impl GitWorktreeManager {
@@ -17,7 +38,67 @@ impl GitWorktreeManager {
             return Err(anyhow::anyhow!("Path is not a git repository: {}", repo_path.display()));
         }
 
-        Ok(Self { repo_path })
+        Ok(Self { repo_path, worktree_root: None })
+    }
+
+    /// Create a manager for the repository containing a path
+    ///
+    /// The path may be anywhere inside the main checkout or a linked worktree; the manager
+    /// is for the main checkout either way.
+    pub fn discover(path: &Path) -> Result<Self> {
+        let repo = Repository::discover(path)
+            .context(format!("No git repository contains {}", path.display()))?;
+        let repo_path = if repo.is_worktree() {
+            // The git directory of a linked worktree is <common dir>/worktrees/<name>
+            repo.path().ancestors().nth(3).map(Path::to_path_buf)
+        } else {
+            repo.workdir().map(Path::to_path_buf)
+        }
+        .context(format!("Repository containing {} has no working directory", path.display()))?;
+
+        Self::new(repo_path)
+    }
+
+    /// Create new worktrees under `worktree_root` instead of next to the repository
+    ///
+    /// A relative root is resolved against the repository path.
+    pub fn with_worktree_root(mut self, worktree_root: PathBuf) -> Self {
+        self.worktree_root = Some(self.repo_path.join(worktree_root));
+        self
+    }
+
+    /// Directory new worktrees are created in
+    pub fn worktree_root(&self) -> Result<PathBuf> {
+        match &self.worktree_root {
+            Some(root) => Ok(root.clone()),
+            None => self.repo_path.parent()
+                .map(Path::to_path_buf)
+                .context("Repository has no parent directory"),
+        }
+    }
+
+    /// Path of the repository this manager operates on
+    pub fn repo_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    /// Directory for server state (registry, logs), shared by all worktrees of the repository
+    pub fn state_dir(&self) -> Result<PathBuf> {
+        let output = std::process::Command::new("git")
+            .arg("rev-parse")
+            .arg("--path-format=absolute")
+            .arg("--git-common-dir")
+            .current_dir(&self.repo_path)
+            .output()
+            .context("Failed to execute git rev-parse command")?;
+
+        if !output.status.success() {
+            let error_msg = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("Git rev-parse failed: {}", error_msg));
+        }
+
+        let common_dir = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(PathBuf::from(common_dir).join(STATE_DIR_NAME))
     }
 
     /// Check if the current directory is a git repository
@@ -34,7 +115,9 @@ impl GitWorktreeManager {
     /// 
     /// # Arguments
     /// * `branch_name` - Name of the branch to create
-    /// * `base_branch` - Optional base branch to create from (defaults to current branch)
+    /// * `base_ref` - Optional revision to create the branch from: a branch, remote-tracking
+    ///   branch, tag, commit SHA, expression like `HEAD~3`, or ref like `refs/pull/123/head`
+    ///   (defaults to the current HEAD)
     /// * `worktree_dir` - Optional directory name for the worktree (defaults to branch_name)
     /// 
     /// # Returns
@@ -42,17 +125,44 @@ impl GitWorktreeManager {
     pub async fn create_worktree(
         &self,
         branch_name: &str,
-        base_branch: Option<&str>,
+        base_ref: Option<&str>,
         worktree_dir: Option<&str>,
     ) -> Result<PathBuf> {
+        let created = self
+            .create_worktree_with_options(branch_name, base_ref, worktree_dir, &WorktreeOptions::default())
+            .await?;
+        Ok(created.path)
+    }
+
+    /// Create a new worktree for the subagent with additional checkout options
+    ///
+    /// See [`create_worktree`](Self::create_worktree) for the common arguments. If creation
+    /// fails, a branch created for it is deleted again. The returned value records what was
+    /// created, so callers can roll back if a later step fails.
+    pub async fn create_worktree_with_options(
+        &self,
+        branch_name: &str,
+        base_ref: Option<&str>,
+        worktree_dir: Option<&str>,
+        options: &WorktreeOptions,
+    ) -> Result<CreatedWorktree> {
         let repo_path = self.repo_path.clone();
+        let worktree_root = self.worktree_root()?;
         let branch_name = branch_name.to_string();
-        let base_branch = base_branch.map(|s| s.to_string());
+        let base_ref = base_ref.map(|s| s.to_string());
         let worktree_dir = worktree_dir.map(|s| s.to_string());
+        let options = options.clone();
 
         // Run git operations in a blocking task to avoid blocking the async runtime
         task::spawn_blocking(move || {
-            Self::create_worktree_blocking(&repo_path, &branch_name, base_branch.as_deref(), worktree_dir.as_deref())
+            Self::create_worktree_blocking(
+                &repo_path,
+                &worktree_root,
+                &branch_name,
+                base_ref.as_deref(),
+                worktree_dir.as_deref(),
+                &options,
+            )
         })
         .await
         .context("Failed to spawn blocking task")?
@@ -61,84 +171,185 @@ impl GitWorktreeManager {
     /// Blocking implementation of worktree creation
     fn create_worktree_blocking(
         repo_path: &Path,
+        worktree_root: &Path,
         branch_name: &str,
-        base_branch: Option<&str>,
+        base_ref: Option<&str>,
         worktree_dir: Option<&str>,
-    ) -> Result<PathBuf> {
+        options: &WorktreeOptions,
+    ) -> Result<CreatedWorktree> {
         // Open the git repository
         let repo = Repository::open(repo_path)
             .context("Failed to open git repository")?;
 
         debug!("Opened repository at: {}", repo_path.display());
 
-        // Determine the base branch
-        let base_branch_name = match base_branch {
-            Some(branch) => branch.to_string(),
-            None => {
-                // Get current branch
-                let head = repo.head()
-                    .context("Failed to get HEAD reference")?;
-                
-                if let Some(name) = head.shorthand() {
-                    name.to_string()
-                } else {
-                    return Err(anyhow::anyhow!("Could not determine current branch name"));
-                }
-            }
-        };
+        let base = Self::resolve_base(&repo, base_ref)?;
+        let base_name = base.name.clone().unwrap_or_else(|| base.commit.id().to_string());
 
-        info!("Creating branch '{}' from base branch '{}'", branch_name, base_branch_name);
-
-        // Check if branch already exists
-        if Self::branch_exists(&repo, branch_name)? {
-            warn!("Branch '{}' already exists, checking it out instead", branch_name);
-            
-            // If branch exists, just check it out
-            let branch_ref = repo.find_branch(branch_name, BranchType::Local)
-                .context("Failed to find existing branch")?;
-            
-            let commit = branch_ref.get().peel_to_commit()
-                .context("Failed to get commit from branch")?;
-            
-            repo.checkout_tree(&commit.into_object(), None)
-                .context("Failed to checkout existing branch")?;
-            
-            repo.set_head(&format!("refs/heads/{}", branch_name))
-                .context("Failed to set HEAD to existing branch")?;
+        info!("Creating branch '{}' from '{}' ({})", branch_name, base_name, base.commit.id());
+
+        // Check if branch already exists. The branch is only created here; it is checked
+        // out by `git worktree add` below, never in the main checkout.
+        let (created_branch, fork_point) = if Self::branch_exists(&repo, branch_name)? {
+            warn!("Branch '{}' already exists, using it for the worktree", branch_name);
+
+            // The existing branch forked from the base where their histories meet
+            let tip = repo.find_branch(branch_name, BranchType::Local)?.get().peel_to_commit()?;
+            let fork_point = repo.merge_base(tip.id(), base.commit.id()).unwrap_or(base.commit.id());
+            (false, fork_point)
         } else {
-            // Create new branch from base branch
-            let base_commit = Self::get_branch_commit(&repo, &base_branch_name)?;
-            
-            let branch_ref = repo.branch(branch_name, &base_commit, false)
+            // Create new branch from the base commit
+            let mut branch = repo.branch(branch_name, &base.commit, false)
                 .context("Failed to create new branch")?;
-            
-            // Checkout the new branch
-            repo.checkout_tree(&base_commit.into_object(), None)
-                .context("Failed to checkout new branch")?;
-            
-            repo.set_head(&format!("refs/heads/{}", branch_name))
-                .context("Failed to set HEAD to new branch")?;
-        }
+
+            if let Some(upstream) = &base.upstream {
+                branch.set_upstream(Some(upstream))
+                    .context(format!("Failed to set upstream of '{}' to '{}'", branch_name, upstream))?;
+                info!("Branch '{}' tracks '{}'", branch_name, upstream);
+            }
+            (true, base.commit.id())
+        };
+
+        let created = |path: PathBuf, created_worktree: bool| CreatedWorktree {
+            path,
+            created_branch,
+            created_worktree,
+            base_ref: base.name.clone(),
+            base_commit: fork_point.to_string(),
+            submodules: Vec::new(),
+            lfs: None,
+        };
 
         // Determine worktree directory name
         let worktree_dir_name = worktree_dir.unwrap_or(branch_name);
         
-        // Create worktree directory path (adjacent to the main repository)
-        let worktree_path = repo_path.parent()
-            .context("Repository has no parent directory")?
-            .join(&worktree_dir_name);
+        // Create worktree directory path (adjacent to the main repository unless a root is configured)
+        let worktree_path = worktree_root.join(worktree_dir_name);
 
         // Check if worktree directory already exists
         if worktree_path.exists() {
             warn!("Worktree directory already exists: {}", worktree_path.display());
-            return Ok(worktree_path);
+            return Ok(created(worktree_path, false));
         }
 
-        // Create the worktree using git command (more reliable than libgit2 for worktrees)
-        let output = std::process::Command::new("git")
-            .arg("worktree")
-            .arg("add")
-            .arg(&worktree_path)
+        if let Err(e) = Self::add_worktree(repo_path, &worktree_path, branch_name, options) {
+            // Don't leave behind a branch nobody asked to keep
+            if created_branch {
+                if let Ok(mut branch) = repo.find_branch(branch_name, BranchType::Local) {
+                    if let Err(delete_error) = branch.delete() {
+                        warn!("Failed to delete branch '{}' after failed worktree creation: {}", branch_name, delete_error);
+                    }
+                }
+            }
+            return Err(e);
+        }
+
+        info!("Successfully created worktree at: {}", worktree_path.display());
+        let mut result = created(worktree_path, true);
+
+        // Submodule and LFS failures are reported rather than failing the creation;
+        // the worktree itself is usable and the caller decides what to do
+        if options.submodules {
+            result.submodules = Self::update_submodules(&repo, &result.path);
+        }
+        result.lfs = Self::checkout_lfs(&result.path);
+
+        Ok(result)
+    }
+
+    /// Initialize and update the submodules of a new worktree recursively, one at a time
+    ///
+    /// Submodules already cloned in the main checkout are cloned with `--reference` to its
+    /// module object store, so their objects are shared rather than fetched again.
+    fn update_submodules(repo: &Repository, worktree_path: &Path) -> Vec<SubmoduleResult> {
+        if !worktree_path.join(".gitmodules").exists() {
+            return Vec::new();
+        }
+
+        // Fails when .gitmodules declares no paths, which means there is nothing to do
+        let declared = run_git(
+            worktree_path,
+            &["config", "--file", ".gitmodules", "--get-regexp", r"^submodule\..*\.path$"],
+        ).unwrap_or_default();
+
+        let mut results = Vec::new();
+        for line in declared.lines() {
+            let Some((key, path)) = line.split_once(' ') else {
+                continue;
+            };
+            let name = key.trim_start_matches("submodule.").trim_end_matches(".path");
+
+            // Submodules outside a sparse checkout have no directory and are left alone
+            if !worktree_path.join(path).exists() {
+                debug!("Skipping submodule '{}', {} is not checked out", name, path);
+                continue;
+            }
+
+            let module_dir = repo.path().join("modules").join(name);
+            let reference = module_dir.exists().then_some(module_dir);
+
+            let reference_arg = reference.as_ref().map(|dir| dir.to_string_lossy().into_owned());
+            let mut args = vec!["submodule", "update", "--init", "--recursive"];
+            if let Some(reference_arg) = &reference_arg {
+                args.extend(["--reference", reference_arg.as_str()]);
+            }
+            args.extend(["--", path]);
+
+            let error = match run_git(worktree_path, &args) {
+                Ok(_) => {
+                    info!("Initialized submodule '{}' in {}", path, worktree_path.display());
+                    None
+                }
+                Err(e) => {
+                    warn!("Failed to initialize submodule '{}' in {}: {}", path, worktree_path.display(), e);
+                    Some(format!("{:#}", e))
+                }
+            };
+
+            results.push(SubmoduleResult {
+                path: path.to_string(),
+                reference,
+                error,
+            });
+        }
+
+        results
+    }
+
+    /// Replace LFS pointer files with their content, if the worktree uses LFS
+    ///
+    /// LFS objects live in the git common directory, so content already fetched by
+    /// the main checkout is available without a download.
+    fn checkout_lfs(worktree_path: &Path) -> Option<LfsOutcome> {
+        let attributes = std::fs::read_to_string(worktree_path.join(".gitattributes")).ok()?;
+        if !attributes.contains(LFS_FILTER_ATTRIBUTE) {
+            return None;
+        }
+
+        Some(match run_git(worktree_path, &["lfs", "checkout"]) {
+            Ok(_) => {
+                info!("Checked out LFS files in {}", worktree_path.display());
+                LfsOutcome::CheckedOut
+            }
+            Err(e) => {
+                warn!("Failed to check out LFS files in {}: {}", worktree_path.display(), e);
+                LfsOutcome::Failed { error: format!("{:#}", e) }
+            }
+        })
+    }
+
+    /// Add and check out a worktree for an existing branch, removing it again if setup fails
+    fn add_worktree(repo_path: &Path, worktree_path: &Path, branch_name: &str, options: &WorktreeOptions) -> Result<()> {
+        // Create the worktree using git command (more reliable than libgit2 for worktrees).
+        // Sparse worktrees are created without a checkout, which is done once the
+        // sparse set is configured.
+        let mut cmd = std::process::Command::new("git");
+        cmd.arg("worktree").arg("add");
+        if options.sparse.is_some() {
+            cmd.arg("--no-checkout");
+        }
+        let output = cmd
+            .arg(worktree_path)
             .arg(branch_name)
             .current_dir(repo_path)
             .output()
@@ -149,8 +360,109 @@ impl GitWorktreeManager {
             return Err(anyhow::anyhow!("Git worktree add failed: {}", error_msg));
         }
 
-        info!("Successfully created worktree at: {}", worktree_path.display());
-        Ok(worktree_path)
+        let setup = Self::mark_managed_worktree(worktree_path).and_then(|()| match &options.sparse {
+            Some(sparse) => Self::checkout_sparse(worktree_path, sparse),
+            None => Ok(()),
+        });
+        if setup.is_err() {
+            if let Err(e) = Self::remove_worktree_blocking(repo_path, worktree_path, true) {
+                warn!("Failed to remove worktree after failed setup: {}", e);
+            }
+        }
+
+        setup
+    }
+
+    /// Configure sparse checkout for a worktree created with `--no-checkout`, then check it out
+    fn checkout_sparse(worktree_path: &Path, sparse: &SparseCheckout) -> Result<()> {
+        info!(
+            "Configuring sparse checkout ({} mode) for {}: {}",
+            if sparse.cone { "cone" } else { "pattern" },
+            worktree_path.display(),
+            sparse.paths.join(", ")
+        );
+
+        // `sparse-checkout set` stores its settings in the worktree-specific config,
+        // so other worktrees of the repository are unaffected
+        let mut args = vec!["sparse-checkout", "set", if sparse.cone { "--cone" } else { "--no-cone" }];
+        args.extend(sparse.paths.iter().map(String::as_str));
+        run_git(worktree_path, &args)?;
+
+        // Populate the index and working tree from HEAD, restricted to the sparse set
+        run_git(worktree_path, &["read-tree", "-mu", "HEAD"])?;
+
+        Ok(())
+    }
+
+    /// Add paths to the sparse set of a worktree
+    pub async fn add_sparse_paths(&self, worktree_path: &Path, paths: &[String]) -> Result<()> {
+        let worktree_path = worktree_path.to_path_buf();
+        let paths = paths.to_vec();
+
+        task::spawn_blocking(move || {
+            let mut args = vec!["sparse-checkout", "add"];
+            args.extend(paths.iter().map(String::as_str));
+            run_git(&worktree_path, &args)?;
+
+            info!("Added {} to sparse set of {}", paths.join(", "), worktree_path.display());
+            Ok(())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Disable sparse checkout for a worktree, checking out all files
+    pub async fn disable_sparse_checkout(&self, worktree_path: &Path) -> Result<()> {
+        let worktree_path = worktree_path.to_path_buf();
+
+        task::spawn_blocking(move || {
+            run_git(&worktree_path, &["sparse-checkout", "disable"])?;
+
+            info!("Disabled sparse checkout for {}", worktree_path.display());
+            Ok(())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Get the sparse set of a worktree, or `None` if the worktree is not sparse
+    fn sparse_paths(worktree_path: &Path) -> Option<Vec<String>> {
+        // Fails with "this worktree is not sparse" for full checkouts
+        let output = run_git(worktree_path, &["sparse-checkout", "list"]).ok()?;
+
+        Some(output.lines().map(|line| line.to_string()).collect())
+    }
+
+    /// Record that a worktree was created by us, so it can be told apart from user worktrees
+    fn mark_managed_worktree(worktree_path: &Path) -> Result<()> {
+        let worktree_repo = Repository::open(worktree_path)
+            .context("Failed to open new worktree")?;
+
+        std::fs::write(worktree_repo.path().join(SUBAGENT_MARKER), "")
+            .context("Failed to write subagent worktree marker")?;
+
+        Ok(())
+    }
+
+    /// Check if a worktree was created by this server
+    pub fn is_managed_worktree(worktree_path: &Path) -> bool {
+        Repository::open(worktree_path)
+            .map(|repo| repo.path().join(SUBAGENT_MARKER).exists())
+            .unwrap_or(false)
+    }
+
+    /// Check if a local branch exists
+    pub async fn has_branch(&self, branch_name: &str) -> Result<bool> {
+        let repo_path = self.repo_path.clone();
+        let branch_name = branch_name.to_string();
+
+        task::spawn_blocking(move || {
+            let repo = Repository::open(&repo_path)
+                .context("Failed to open git repository")?;
+            Self::branch_exists(&repo, &branch_name)
+        })
+        .await
+        .context("Failed to spawn blocking task")?
     }
 
     /// Check if a branch exists in the repository
@@ -162,18 +474,39 @@ impl GitWorktreeManager {
         }
     }
 
-    /// Get the commit for a given branch name
-    fn get_branch_commit<'a>(repo: &'a Repository, branch_name: &str) -> Result<git2::Commit<'a>> {
-        // Try to find the branch reference
-        let branch_ref = repo.find_branch(branch_name, BranchType::Local)
-            .or_else(|_| repo.find_branch(branch_name, BranchType::Remote))
-            .context(format!("Branch '{}' not found", branch_name))?;
+    /// Resolve the revision a new branch is created from
+    ///
+    /// Without a revision, the current HEAD is used.
+    fn resolve_base<'a>(repo: &'a Repository, base_ref: Option<&str>) -> Result<ResolvedBase<'a>> {
+        let (object, reference) = match base_ref {
+            Some(base_ref) => repo.revparse_ext(base_ref)
+                .context(format!(
+                    "Base '{}' not found (refs that are not present locally, like pull request refs, must be fetched first)",
+                    base_ref
+                ))?,
+            None => {
+                let head = repo.head()
+                    .context("Failed to get HEAD reference")?;
+                let object = head.peel(git2::ObjectType::Commit)
+                    .context("Failed to get commit from HEAD")?;
+                (object, Some(head))
+            }
+        };
+
+        let commit = object.peel_to_commit()
+            .context(format!("Base '{}' does not point to a commit", base_ref.unwrap_or("HEAD")))?;
 
-        // Get the commit from the branch
-        let commit = branch_ref.get().peel_to_commit()
-            .context(format!("Failed to get commit from branch '{}'", branch_name))?;
+        // Only a branch is recorded by name: other revisions either name a fixed commit, which
+        // is recorded as the fork point, or are relative to a HEAD that moves (`HEAD~3`)
+        let branch = reference
+            .and_then(|r| r.resolve().ok())
+            .filter(|r| r.is_branch() || r.is_remote());
+        let name = branch.as_ref().and_then(|r| r.shorthand().map(|s| s.to_string()));
+        let upstream = branch
+            .filter(|r| r.is_remote())
+            .and_then(|r| r.shorthand().map(|s| s.to_string()));
 
-        Ok(commit)
+        Ok(ResolvedBase { commit, name, upstream })
     }
 
     /// List all existing worktrees
@@ -187,6 +520,25 @@ impl GitWorktreeManager {
         .context("Failed to spawn blocking task")?
     }
 
+    /// Find the worktrees matching an identifier
+    ///
+    /// The identifier matches a worktree if it equals its branch name, its directory
+    /// name, or its path. The main worktree is never matched.
+    pub async fn find_worktrees(&self, identifier: &str) -> Result<Vec<WorktreeInfo>> {
+        let worktrees = self.list_worktrees().await?;
+        let identifier_path = Path::new(identifier);
+
+        Ok(worktrees
+            .into_iter()
+            .skip(1)
+            .filter(|w| {
+                w.branch.as_deref() == Some(identifier)
+                    || w.path.file_name().is_some_and(|name| name == identifier)
+                    || same_path(&w.path, identifier_path)
+            })
+            .collect())
+    }
+
     /// Blocking implementation of listing worktrees
     fn list_worktrees_blocking(repo_path: &Path) -> Result<Vec<WorktreeInfo>> {
         let output = std::process::Command::new("git")
@@ -219,6 +571,8 @@ impl GitWorktreeManager {
                     path: PathBuf::from(path),
                     branch: None,
                     commit: None,
+                    sparse_paths: None,
+                    locked: None,
                 });
             } else if line.starts_with("HEAD ") {
                 if let Some(ref mut worktree) = current_worktree {
@@ -228,6 +582,10 @@ impl GitWorktreeManager {
                 if let Some(ref mut worktree) = current_worktree {
                     worktree.branch = Some(line.strip_prefix("branch refs/heads/").unwrap_or("").to_string());
                 }
+            } else if line == "locked" || line.starts_with("locked ") {
+                if let Some(ref mut worktree) = current_worktree {
+                    worktree.locked = Some(line.strip_prefix("locked").unwrap_or("").trim().to_string());
+                }
             }
         }
 
@@ -236,6 +594,12 @@ impl GitWorktreeManager {
             worktrees.push(worktree);
         }
 
+        for worktree in &mut worktrees {
+            if worktree.path.exists() {
+                worktree.sparse_paths = Self::sparse_paths(&worktree.path);
+            }
+        }
+
         Ok(worktrees)
     }
 
@@ -245,17 +609,32 @@ impl GitWorktreeManager {
         let worktree_path = worktree_path.to_path_buf();
         
         task::spawn_blocking(move || {
-            Self::remove_worktree_blocking(&repo_path, &worktree_path)
+            Self::remove_worktree_blocking(&repo_path, &worktree_path, false)
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Remove a worktree even if it has untracked or modified files
+    pub async fn force_remove_worktree(&self, worktree_path: &Path) -> Result<()> {
+        let repo_path = self.repo_path.clone();
+        let worktree_path = worktree_path.to_path_buf();
+
+        task::spawn_blocking(move || {
+            Self::remove_worktree_blocking(&repo_path, &worktree_path, true)
         })
         .await
         .context("Failed to spawn blocking task")?
     }
 
     /// Blocking implementation of removing worktrees
-    fn remove_worktree_blocking(repo_path: &Path, worktree_path: &Path) -> Result<()> {
-        let output = std::process::Command::new("git")
-            .arg("worktree")
-            .arg("remove")
+    fn remove_worktree_blocking(repo_path: &Path, worktree_path: &Path, force: bool) -> Result<()> {
+        let mut cmd = std::process::Command::new("git");
+        cmd.arg("worktree").arg("remove");
+        if force {
+            cmd.arg("--force");
+        }
+        let output = cmd
             .arg(worktree_path)
             .current_dir(repo_path)
             .output()
@@ -269,6 +648,493 @@ impl GitWorktreeManager {
         info!("Successfully removed worktree: {}", worktree_path.display());
         Ok(())
     }
+
+    /// Lock a worktree so `git worktree prune` and `git worktree remove` leave it alone
+    pub async fn lock_worktree(&self, worktree_path: &Path, reason: &str) -> Result<()> {
+        let repo_path = self.repo_path.clone();
+        let worktree_path = worktree_path.to_path_buf();
+        let reason = reason.to_string();
+
+        task::spawn_blocking(move || {
+            let path = worktree_path.to_string_lossy();
+            run_git(&repo_path, &["worktree", "lock", "--reason", &reason, &path])?;
+
+            info!("Locked worktree {} ({})", worktree_path.display(), reason);
+            Ok(())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Unlock a worktree locked with [`lock_worktree`](Self::lock_worktree)
+    pub async fn unlock_worktree(&self, worktree_path: &Path) -> Result<()> {
+        let repo_path = self.repo_path.clone();
+        let worktree_path = worktree_path.to_path_buf();
+
+        task::spawn_blocking(move || {
+            let path = worktree_path.to_string_lossy();
+            run_git(&repo_path, &["worktree", "unlock", &path])?;
+
+            info!("Unlocked worktree {}", worktree_path.display());
+            Ok(())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Prune administrative data of worktrees whose directories no longer exist
+    ///
+    /// # Returns
+    /// One message per pruned worktree, as reported by `git worktree prune --verbose`
+    pub async fn prune_worktrees(&self) -> Result<Vec<String>> {
+        let repo_path = self.repo_path.clone();
+
+        task::spawn_blocking(move || {
+            let output = std::process::Command::new("git")
+                .arg("worktree")
+                .arg("prune")
+                .arg("--verbose")
+                .current_dir(&repo_path)
+                .output()
+                .context("Failed to execute git worktree prune command")?;
+
+            if !output.status.success() {
+                let error_msg = String::from_utf8_lossy(&output.stderr);
+                return Err(anyhow::anyhow!("Git worktree prune failed: {}", error_msg));
+            }
+
+            // `--verbose` reports pruned entries on stderr
+            Ok(String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty())
+                .collect())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Check if a worktree has uncommitted (staged, unstaged or untracked) changes
+    pub async fn has_uncommitted_changes(&self, worktree_path: &Path) -> Result<bool> {
+        let worktree_path = worktree_path.to_path_buf();
+
+        task::spawn_blocking(move || {
+            let output = std::process::Command::new("git")
+                .arg("status")
+                .arg("--porcelain")
+                .current_dir(&worktree_path)
+                .output()
+                .context("Failed to execute git status command")?;
+
+            if !output.status.success() {
+                let error_msg = String::from_utf8_lossy(&output.stderr);
+                return Err(anyhow::anyhow!("Git status failed: {}", error_msg));
+            }
+
+            Ok(!output.stdout.is_empty())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Stage all changes in a worktree and commit them
+    ///
+    /// Returns `None` if there was nothing to commit.
+    pub async fn checkpoint_worktree(&self, worktree_path: &Path, options: &CheckpointOptions) -> Result<Option<Checkpoint>> {
+        let worktree_path = worktree_path.to_path_buf();
+        let options = options.clone();
+
+        task::spawn_blocking(move || Self::checkpoint_worktree_blocking(&worktree_path, &options))
+            .await
+            .context("Failed to spawn blocking task")?
+    }
+
+    /// Blocking implementation of checkpointing
+    fn checkpoint_worktree_blocking(worktree_path: &Path, options: &CheckpointOptions) -> Result<Option<Checkpoint>> {
+        let repo = Repository::open(worktree_path)
+            .context(format!("Failed to open worktree {}", worktree_path.display()))?;
+
+        if repo.state() != RepositoryState::Clean {
+            return Err(anyhow::anyhow!(
+                "Worktree {} has a {:?} in progress; finish or abort it before checkpointing",
+                worktree_path.display(),
+                repo.state()
+            ));
+        }
+
+        let mut index = repo.index().context("Failed to read index")?;
+        stage_all_changes(&mut index)?;
+        index.write().context("Failed to write index")?;
+
+        let tree_id = index.write_tree().context("Failed to write tree")?;
+        let tree = repo.find_tree(tree_id)?;
+
+        let parent = match repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(e) if e.code() == ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e).context("Failed to resolve HEAD"),
+        };
+        let parent_tree = parent.as_ref().map(|commit| commit.tree()).transpose()?;
+
+        if parent_tree.as_ref().map(|tree| tree.id()) == Some(tree_id) {
+            debug!("Nothing to checkpoint in {}", worktree_path.display());
+            return Ok(None);
+        }
+
+        let files_changed = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?.deltas().len();
+
+        let default_signature = match repo.signature() {
+            Ok(signature) => signature,
+            Err(_) => Signature::now(FALLBACK_IDENTITY.0, FALLBACK_IDENTITY.1)?,
+        };
+        let author = match &options.author {
+            Some(identity) => Signature::now(&identity.name, &identity.email)?,
+            None => default_signature.clone(),
+        };
+        let committer = match &options.committer {
+            Some(identity) => Signature::now(&identity.name, &identity.email)?,
+            None => author.clone(),
+        };
+
+        let message = checkpoint_message(&options.message, &options.trailers);
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        let commit_id = repo.commit(Some("HEAD"), &author, &committer, &message, &tree, &parents)
+            .context("Failed to create checkpoint commit")?;
+
+        info!("Checkpointed {} files in {} as {}", files_changed, worktree_path.display(), commit_id);
+        Ok(Some(Checkpoint {
+            commit: commit_id.to_string(),
+            files_changed,
+        }))
+    }
+
+    /// Write a tree of a worktree's current state, including uncommitted and untracked changes
+    ///
+    /// The worktree's index on disk is left unchanged.
+    pub fn snapshot_tree(worktree_repo: &Repository) -> Result<git2::Oid> {
+        let mut index = worktree_repo.index().context("Failed to read index")?;
+        stage_all_changes(&mut index)?;
+        index.write_tree().context("Failed to write tree")
+    }
+
+    /// Create the detached worktree the merge queue integrates in, or reset it to `target`
+    ///
+    /// The worktree is locked so that pruning leaves it alone. Ignored files such as build
+    /// output are kept between runs. Returns the commit `target` resolved to.
+    pub async fn prepare_integration_worktree(&self, worktree_path: &Path, target: &str) -> Result<String> {
+        let repo_path = self.repo_path.clone();
+        let worktree_path = worktree_path.to_path_buf();
+        let target = target.to_string();
+
+        task::spawn_blocking(move || {
+            let path = worktree_path.to_string_lossy().into_owned();
+            if worktree_path.exists() {
+                // Also clears a merge left half-done by an interrupted run
+                run_git(&worktree_path, &["reset", "--hard"])?;
+                run_git(&worktree_path, &["checkout", "--detach", &target])?;
+                run_git(&worktree_path, &["clean", "-fd"])?;
+            } else {
+                info!("Creating integration worktree at {}", worktree_path.display());
+                run_git(&repo_path, &["worktree", "add", "--detach", &path, &target])?;
+                run_git(&repo_path, &["worktree", "lock", "--reason", "merge queue integration worktree", &path])?;
+            }
+
+            Ok(run_git(&worktree_path, &["rev-parse", "HEAD"])?.trim().to_string())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Fast-forward a branch to `commit`, failing if it no longer points at `expected`
+    ///
+    /// If the branch is checked out in a worktree, that checkout is fast-forwarded too.
+    pub async fn fast_forward_branch(&self, branch_name: &str, commit: &str, expected: &str) -> Result<()> {
+        let repo_path = self.repo_path.clone();
+        let branch_name = branch_name.to_string();
+        let commit = commit.to_string();
+        let expected = expected.to_string();
+
+        task::spawn_blocking(move || {
+            let worktrees = Self::list_worktrees_blocking(&repo_path)?;
+            let checkout = worktrees.iter().find(|w| w.branch.as_deref() == Some(branch_name.as_str()));
+
+            match checkout {
+                Some(worktree) => {
+                    let head = run_git(&worktree.path, &["rev-parse", "HEAD"])?.trim().to_string();
+                    if head != expected {
+                        return Err(anyhow::anyhow!(
+                            "Branch '{}' moved from {} to {} in the meantime",
+                            branch_name,
+                            expected,
+                            head
+                        ));
+                    }
+                    run_git(&worktree.path, &["merge", "--ff-only", &commit])?;
+                }
+                None => {
+                    // update-ref refuses if the branch no longer points at the expected commit
+                    run_git(&repo_path, &["update-ref", &format!("refs/heads/{}", branch_name), &commit, &expected])?;
+                }
+            }
+
+            info!("Fast-forwarded '{}' to {}", branch_name, commit);
+            Ok(())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Delete a local branch
+    pub async fn delete_branch(&self, branch_name: &str) -> Result<()> {
+        let repo_path = self.repo_path.clone();
+        let branch_name = branch_name.to_string();
+
+        task::spawn_blocking(move || {
+            let repo = Repository::open(&repo_path)
+                .context("Failed to open git repository")?;
+
+            let mut branch = repo.find_branch(&branch_name, BranchType::Local)
+                .context(format!("Branch '{}' not found", branch_name))?;
+
+            branch.delete()
+                .context(format!("Failed to delete branch '{}'", branch_name))?;
+
+            info!("Successfully deleted branch '{}'", branch_name);
+            Ok(())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Preserve the tip of a branch under `refs/subagent-archive/` so it survives branch deletion
+    ///
+    /// # Returns
+    /// The name of the archive reference
+    pub async fn archive_branch(&self, branch_name: &str) -> Result<String> {
+        let repo_path = self.repo_path.clone();
+        let branch_name = branch_name.to_string();
+
+        task::spawn_blocking(move || {
+            let repo = Repository::open(&repo_path)
+                .context("Failed to open git repository")?;
+
+            let branch = repo.find_branch(&branch_name, BranchType::Local)
+                .context(format!("Branch '{}' not found", branch_name))?;
+            let target = branch.get().peel_to_commit()
+                .context(format!("Failed to get commit from branch '{}'", branch_name))?
+                .id();
+
+            let archive_ref = format!("refs/subagent-archive/{}", branch_name);
+            repo.reference(&archive_ref, target, true, &format!("archive branch '{}'", branch_name))
+                .context(format!("Failed to create archive reference '{}'", archive_ref))?;
+
+            info!("Archived branch '{}' as '{}'", branch_name, archive_ref);
+            Ok(archive_ref)
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Fetch a ref from a configured remote into a worktree
+    ///
+    /// Only remotes the repository defines are accepted, and neither the remote nor the ref may
+    /// look like an option, so a caller cannot make `git fetch` run a command of its choosing.
+    /// A remote-tracking branch such as `origin/main` is fetched as the branch it tracks
+    /// (`main`), since the remote does not know the local name.
+    ///
+    /// # Returns
+    /// The fetched commit id
+    pub async fn fetch_ref(&self, worktree_path: &Path, remote: &str, refspec: &str) -> Result<String> {
+        let worktree_path = worktree_path.to_path_buf();
+        let remote = remote.to_string();
+        let refspec = refspec.to_string();
+
+        task::spawn_blocking(move || {
+            let repo = Repository::open(&worktree_path)
+                .context("Failed to open git repository")?;
+            if remote.starts_with('-') || refspec.starts_with('-') {
+                return Err(anyhow::anyhow!(
+                    "Refusing to fetch '{}' from '{}': names may not start with '-'",
+                    refspec,
+                    remote
+                ));
+            }
+            repo.find_remote(&remote)
+                .with_context(|| format!("Remote '{}' is not configured", remote))?;
+            let refspec = Self::remote_branch_name(&repo, &refspec).unwrap_or(refspec);
+            run_git(&worktree_path, &["fetch", "--end-of-options", &remote, &refspec])?;
+
+            // FETCH_HEAD is per-worktree, so concurrent syncs of other worktrees don't interfere
+            let commit = run_git(&worktree_path, &["rev-parse", "--verify", "FETCH_HEAD^{commit}"])?;
+            info!("Fetched '{}' from '{}' at {}", refspec, remote, commit.trim());
+            Ok(commit.trim().to_string())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Name of the branch on its remote, if `name` is a remote-tracking branch
+    fn remote_branch_name(repo: &Repository, name: &str) -> Option<String> {
+        let reference = repo.resolve_reference_from_short_name(name).ok()?;
+        if !reference.is_remote() {
+            return None;
+        }
+        let full_name = reference.name()?;
+        let remote = repo.branch_remote_name(full_name).ok()?;
+        full_name
+            .strip_prefix("refs/remotes/")?
+            .strip_prefix(remote.as_str()?)?
+            .strip_prefix('/')
+            .map(|branch| branch.to_string())
+    }
+
+    /// Summarize the state of a worktree: its HEAD, changed files, and a diffstat against `base`
+    ///
+    /// Without a base the diffstat covers uncommitted changes only.
+    pub async fn worktree_status(&self, worktree_path: &Path, base: Option<&str>) -> Result<WorktreeStatus> {
+        let worktree_path = worktree_path.to_path_buf();
+        let base = base.unwrap_or("HEAD").to_string();
+
+        task::spawn_blocking(move || {
+            let head = run_git(&worktree_path, &["rev-parse", "--verify", "--quiet", "HEAD"])
+                .ok()
+                .map(|head| head.trim().to_string());
+            let changes = run_git(&worktree_path, &["status", "--porcelain"])?
+                .lines()
+                .map(|line| line.to_string())
+                .collect();
+            let diff_stat = run_git(&worktree_path, &["diff", "--stat", &base])?.trim_end().to_string();
+
+            Ok(WorktreeStatus { head, changes, diff_stat })
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Diff of a worktree, including uncommitted changes to tracked files, against `base`
+    pub async fn worktree_diff(&self, worktree_path: &Path, base: &str) -> Result<String> {
+        let worktree_path = worktree_path.to_path_buf();
+        let base = base.to_string();
+
+        task::spawn_blocking(move || run_git(&worktree_path, &["diff", &base]))
+            .await
+            .context("Failed to spawn blocking task")?
+    }
+
+    /// Resolve a revision to a commit id, as seen from a worktree
+    pub async fn resolve_commit(&self, worktree_path: &Path, revision: &str) -> Result<String> {
+        let worktree_path = worktree_path.to_path_buf();
+        let revision = format!("{}^{{commit}}", revision);
+
+        task::spawn_blocking(move || {
+            let commit = run_git(&worktree_path, &["rev-parse", "--verify", &revision])?;
+            Ok(commit.trim().to_string())
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Rebase or merge an upstream commit into the branch checked out in a worktree
+    ///
+    /// On conflicts the operation is aborted if `abort_on_conflict` is set, restoring the
+    /// worktree to its state before the sync; otherwise it is left in progress for resolution.
+    pub async fn integrate_upstream(
+        &self,
+        worktree_path: &Path,
+        upstream: &str,
+        strategy: SyncStrategy,
+        abort_on_conflict: bool,
+    ) -> Result<SyncOutcome> {
+        let worktree_path = worktree_path.to_path_buf();
+        let upstream = upstream.to_string();
+
+        task::spawn_blocking(move || {
+            Self::integrate_upstream_blocking(&worktree_path, &upstream, strategy, abort_on_conflict)
+        })
+        .await
+        .context("Failed to spawn blocking task")?
+    }
+
+    /// Blocking implementation of integrating upstream changes
+    fn integrate_upstream_blocking(
+        worktree_path: &Path,
+        upstream: &str,
+        strategy: SyncStrategy,
+        abort_on_conflict: bool,
+    ) -> Result<SyncOutcome> {
+        let tracked_changes = run_git(worktree_path, &["status", "--porcelain", "--untracked-files=no"])?;
+        if !tracked_changes.trim().is_empty() {
+            return Err(anyhow::anyhow!(
+                "Worktree {} has uncommitted changes; commit or stash them before syncing",
+                worktree_path.display()
+            ));
+        }
+
+        let old_head = run_git(worktree_path, &["rev-parse", "HEAD"])?.trim().to_string();
+
+        // Nothing to do if the upstream commit is already part of the branch
+        let is_ancestor = std::process::Command::new("git")
+            .args(["merge-base", "--is-ancestor", upstream, "HEAD"])
+            .current_dir(worktree_path)
+            .status()
+            .context("Failed to execute git merge-base command")?;
+        if is_ancestor.success() {
+            return Ok(SyncOutcome::UpToDate { head: old_head });
+        }
+
+        info!("Syncing {} with {} ({})", worktree_path.display(), upstream, strategy);
+
+        let mut cmd = std::process::Command::new("git");
+        match strategy {
+            SyncStrategy::Rebase => cmd.args(["rebase", upstream]),
+            SyncStrategy::Merge => cmd.args(["merge", "--no-edit", upstream]),
+        };
+        let output = cmd
+            // Never wait for an editor, there is nobody to close it
+            .env("GIT_EDITOR", "true")
+            .current_dir(worktree_path)
+            .output()
+            .context(format!("Failed to execute git {}", strategy))?;
+
+        if output.status.success() {
+            let new_head = run_git(worktree_path, &["rev-parse", "HEAD"])?.trim().to_string();
+            return Ok(SyncOutcome::Updated { old_head, new_head });
+        }
+
+        let conflicted_files: Vec<String> = run_git(worktree_path, &["diff", "--name-only", "--diff-filter=U"])?
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+
+        if conflicted_files.is_empty() || abort_on_conflict {
+            if let Err(e) = run_git(worktree_path, &[&strategy.to_string(), "--abort"]) {
+                warn!("Failed to abort {} in {}: {}", strategy, worktree_path.display(), e);
+            }
+        }
+
+        if conflicted_files.is_empty() {
+            let error_msg = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("Git {} failed: {}", strategy, error_msg.trim()));
+        }
+
+        warn!("Sync of {} stopped on conflicts in {}", worktree_path.display(), conflicted_files.join(", "));
+        Ok(SyncOutcome::Conflicted {
+            strategy,
+            conflicted_files,
+            in_progress: !abort_on_conflict,
+        })
+    }
+}
+
+/// State of a worktree, from [`GitWorktreeManager::worktree_status`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorktreeStatus {
+    /// Commit checked out, if any
+    pub head: Option<String>,
+    /// Uncommitted changes, as `git status --porcelain` lines
+    pub changes: Vec<String>,
+    /// `git diff --stat` output against the base
+    pub diff_stat: String,
 }
 
 /// Information about a git worktree
@@ -277,4 +1143,227 @@ pub struct WorktreeInfo {
     pub path: PathBuf,
     pub branch: Option<String>,
     pub commit: Option<String>,
+    /// Sparse-checkout set, if the worktree is sparse
+    pub sparse_paths: Option<Vec<String>>,
+    /// Lock reason if the worktree is locked (empty if locked without a reason)
+    pub locked: Option<String>,
+}
+
+impl WorktreeInfo {
+    /// Pid of the agent the worktree is locked for, from a [`subagent_lock_reason`]
+    pub fn lock_pid(&self) -> Option<u32> {
+        let reason = self.locked.as_deref()?;
+        let (_, pid) = reason.strip_prefix("subagent ")?.rsplit_once(" pid ")?;
+        pid.parse().ok()
+    }
+
+    /// Whether the worktree is locked for an agent that is no longer running
+    ///
+    /// The lock is released when the agent exits, but only by the server that spawned it,
+    /// so a lock outlives its agent if the server is restarted in between.
+    pub fn has_stale_lock(&self) -> bool {
+        self.lock_pid().is_some_and(|pid| !is_process_running(pid))
+    }
+}
+
+/// Whether two paths name the same file, after resolving symlinks and relative components
+///
+/// Paths that cannot be resolved, such as worktrees whose directory was deleted, only
+/// match if they are equal as given.
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Reason a worktree is locked with while a subagent works in it
+pub fn subagent_lock_reason(subagent_id: &str, pid: Option<u32>) -> String {
+    match pid {
+        Some(pid) => format!("subagent {} pid {}", subagent_id, pid),
+        None => format!("subagent {}", subagent_id),
+    }
+}
+
+/// A worktree returned by [`GitWorktreeManager::create_worktree_with_options`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedWorktree {
+    /// Path to the worktree directory
+    pub path: PathBuf,
+    /// Whether the branch was created for this worktree, rather than already existing
+    pub created_branch: bool,
+    /// Whether the worktree was created, rather than its directory already existing
+    pub created_worktree: bool,
+    /// The base branch (local or remote-tracking), or the current branch if no base was given;
+    /// `None` if the base is another kind of revision, which only `base_commit` records
+    pub base_ref: Option<String>,
+    /// The commit the branch forked from its base
+    pub base_commit: String,
+    /// Submodules initialized in the worktree, if requested
+    pub submodules: Vec<SubmoduleResult>,
+    /// LFS checkout in the worktree, or `None` if the repository does not use LFS
+    pub lfs: Option<LfsOutcome>,
+}
+
+/// Result of initializing a submodule in a new worktree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmoduleResult {
+    /// Path of the submodule in the worktree
+    pub path: String,
+    /// Module directory of the main checkout whose objects were reused, if it had one
+    pub reference: Option<PathBuf>,
+    /// Error message if the submodule could not be initialized
+    pub error: Option<String>,
+}
+
+/// Result of checking out LFS files in a new worktree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LfsOutcome {
+    /// Pointer files were replaced with their content
+    CheckedOut,
+    /// `git lfs checkout` failed, for instance because git-lfs is not installed
+    Failed { error: String },
+}
+
+/// The revision a new branch is created from
+struct ResolvedBase<'a> {
+    /// Commit the base resolves to
+    commit: git2::Commit<'a>,
+    /// Name to record for the base, if it has one
+    name: Option<String>,
+    /// Remote-tracking branch the new branch should track, if the base is one
+    upstream: Option<String>,
+}
+
+/// Additional options for creating a worktree
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorktreeOptions {
+    /// Check out only part of the tree
+    pub sparse: Option<SparseCheckout>,
+    /// Initialize and update submodules recursively
+    #[serde(default)]
+    pub submodules: bool,
+}
+
+/// Sparse-checkout configuration for a worktree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SparseCheckout {
+    /// Directories (cone mode) or gitignore-style patterns (pattern mode) to check out
+    pub paths: Vec<String>,
+    /// Whether `paths` are cone-mode directories rather than patterns
+    pub cone: bool,
+}
+
+/// How upstream changes are integrated into a subagent branch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStrategy {
+    /// Rebase the branch onto the upstream commit
+    #[default]
+    Rebase,
+    /// Merge the upstream commit into the branch
+    Merge,
+}
+
+impl std::fmt::Display for SyncStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rebase => write!(f, "rebase"),
+            Self::Merge => write!(f, "merge"),
+        }
+    }
+}
+
+/// Result of syncing a worktree with its upstream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncOutcome {
+    /// The branch already contains the upstream commit
+    UpToDate { head: String },
+    /// The branch was rebased or merged
+    Updated { old_head: String, new_head: String },
+    /// The sync stopped on conflicts
+    Conflicted {
+        strategy: SyncStrategy,
+        conflicted_files: Vec<String>,
+        /// Whether the rebase or merge is still in progress, waiting for resolution
+        in_progress: bool,
+    },
+}
+
+/// Name and email of a commit author or committer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitIdentity {
+    pub name: String,
+    pub email: String,
+}
+
+/// Options for [`GitWorktreeManager::checkpoint_worktree`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CheckpointOptions {
+    /// Commit message, without trailers
+    pub message: String,
+    /// Author of the commit (defaults to git's configured user)
+    pub author: Option<GitIdentity>,
+    /// Committer of the commit (defaults to the author)
+    pub committer: Option<GitIdentity>,
+    /// Trailers appended to the message, such as `Subagent-Id: <id>`
+    pub trailers: Vec<(String, String)>,
+}
+
+/// A commit created by [`GitWorktreeManager::checkpoint_worktree`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Id of the new commit
+    pub commit: String,
+    /// Number of files changed by the commit
+    pub files_changed: usize,
+}
+
+/// Stage new, modified and deleted files in an index, like `git add -A`
+fn stage_all_changes(index: &mut git2::Index) -> Result<()> {
+    // Files outside a sparse checkout are missing on purpose and must not be staged as deletions
+    let outside_sparse_set: HashSet<PathBuf> = index.iter()
+        .filter(|entry| IndexEntryExtendedFlag::from_bits_truncate(entry.flags_extended).is_skip_worktree())
+        .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned()))
+        .collect();
+    let mut skip_sparse = |path: &Path, _: &[u8]| i32::from(outside_sparse_set.contains(path));
+
+    index.add_all(["*"], IndexAddOption::DEFAULT, Some(&mut skip_sparse))
+        .context("Failed to stage changes")?;
+    index.update_all(["*"], Some(&mut skip_sparse))
+        .context("Failed to stage deletions")?;
+
+    Ok(())
+}
+
+/// Build a commit message with a trailer block
+fn checkpoint_message(message: &str, trailers: &[(String, String)]) -> String {
+    let mut message = message.trim_end().to_string();
+    if !trailers.is_empty() {
+        message.push_str("\n\n");
+        let lines: Vec<String> = trailers.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+        message.push_str(&lines.join("\n"));
+    }
+    message.push('\n');
+    message
+}
+
+/// Run a git command in the given directory and return its stdout
+fn run_git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .context(format!("Failed to execute git {}", args.join(" ")))?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("Git {} failed: {}", args.first().unwrap_or(&""), error_msg.trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
@@ -0,0 +1,265 @@
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::command_runner::{self, CommandOutput};
+
+/// Default timeout for each bootstrap command, in seconds
+pub const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 600;
+
+/// What to do with a new worktree when one of its bootstrap steps fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HookFailurePolicy {
+    /// Abort the spawn, leaving the worktree in place for inspection
+    #[default]
+    Keep,
+    /// Abort the spawn and remove the worktree
+    Rollback,
+}
+
+impl fmt::Display for HookFailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keep => write!(f, "keep"),
+            Self::Rollback => write!(f, "rollback"),
+        }
+    }
+}
+
+/// Setup run in a new worktree after it is created and before the agent starts
+///
+/// Steps run in order: files are copied, then symlinked, then commands are run.
+/// The first failing step stops the bootstrap.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct BootstrapConfig {
+    /// Files or directories (relative to the repository root) copied from the main checkout,
+    /// typically ignored files such as `.env`
+    pub copy: Vec<String>,
+    /// Files or directories (relative to the repository root) symlinked from the main checkout
+    pub symlink: Vec<String>,
+    /// Shell commands run in the new worktree, such as `npm ci` or `cargo fetch`
+    pub commands: Vec<String>,
+    /// Timeout for each command in seconds (default: 600)
+    pub timeout_secs: Option<u64>,
+    /// What to do with the worktree if a step fails
+    pub on_failure: HookFailurePolicy,
+}
+
+impl BootstrapConfig {
+    /// Whether there is nothing to do
+    pub fn is_empty(&self) -> bool {
+        self.copy.is_empty() && self.symlink.is_empty() && self.commands.is_empty()
+    }
+}
+
+/// Result of a single bootstrap step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookStepResult {
+    /// Description of the step
+    pub step: String,
+    /// Whether the step succeeded
+    pub success: bool,
+    /// Error message, for failed file steps
+    pub error: Option<String>,
+    /// Captured command output, for command steps
+    pub output: Option<CommandOutput>,
+}
+
+/// Result of bootstrapping a worktree
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BootstrapReport {
+    /// Steps that were run, in order; a failed step is always the last one
+    pub steps: Vec<HookStepResult>,
+}
+
+impl BootstrapReport {
+    /// The step that failed, if any
+    pub fn failure(&self) -> Option<&HookStepResult> {
+        self.steps.iter().find(|step| !step.success)
+    }
+
+    /// Human-readable summary of the steps, including output of failed commands
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        for step in &self.steps {
+            let status = if step.success { "ok" } else { "FAILED" };
+            summary.push_str(&format!("  - {}: {}", step.step, status));
+            if let Some(error) = &step.error {
+                summary.push_str(&format!(" ({})", error));
+            }
+            if let Some(output) = &step.output {
+                summary.push_str(&format!(" ({}, {}ms)", output.status_description(), output.duration_ms));
+                if !step.success {
+                    for stream in [&output.stdout, &output.stderr] {
+                        if !stream.trim().is_empty() {
+                            summary.push_str(&format!("\n{}", indent(stream.trim_end())));
+                        }
+                    }
+                }
+            }
+            summary.push('\n');
+        }
+        summary
+    }
+}
+
+fn indent(text: &str) -> String {
+    text.lines().map(|line| format!("      {}", line)).collect::<Vec<_>>().join("\n")
+}
+
+/// Bootstrap a new worktree from the main checkout at `source_root`
+///
+/// Step failures are reported in the returned report rather than as errors,
+/// so the caller can apply its failure policy.
+pub async fn run_bootstrap(source_root: &Path, worktree_path: &Path, config: &BootstrapConfig) -> Result<BootstrapReport> {
+    let mut report = BootstrapReport::default();
+    if config.is_empty() {
+        return Ok(report);
+    }
+
+    info!("Bootstrapping worktree {}", worktree_path.display());
+
+    for path in &config.copy {
+        let result = step_paths(source_root, worktree_path, path)
+            .and_then(|(source, target)| copy_path(&source, &target));
+        if !record_file_step(&mut report, format!("copy {}", path), result) {
+            return Ok(report);
+        }
+    }
+
+    for path in &config.symlink {
+        let result = step_paths(source_root, worktree_path, path)
+            .and_then(|(source, target)| symlink_path(&source, &target));
+        if !record_file_step(&mut report, format!("symlink {}", path), result) {
+            return Ok(report);
+        }
+    }
+
+    let timeout = Duration::from_secs(config.timeout_secs.unwrap_or(DEFAULT_HOOK_TIMEOUT_SECS));
+    for command in &config.commands {
+        let output = command_runner::run_shell_command(command, worktree_path, timeout).await?;
+        let success = output.success();
+        if !success {
+            warn!("Bootstrap command '{}' {}", command, output.status_description());
+        }
+        report.steps.push(HookStepResult {
+            step: format!("run `{}`", command),
+            success,
+            error: None,
+            output: Some(output),
+        });
+        if !success {
+            return Ok(report);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Record the result of a copy or symlink step, returning whether it succeeded
+fn record_file_step(report: &mut BootstrapReport, step: String, result: Result<()>) -> bool {
+    let error = result.err().map(|e| format!("{:#}", e));
+    if let Some(error) = &error {
+        warn!("Bootstrap step '{}' failed: {}", step, error);
+    }
+    let success = error.is_none();
+    report.steps.push(HookStepResult { step, success, error, output: None });
+    success
+}
+
+/// Resolve a copy or symlink path against the main checkout and the worktree
+fn step_paths(source_root: &Path, worktree_path: &Path, path: &str) -> Result<(PathBuf, PathBuf)> {
+    Ok((contained_path(source_root, path)?, contained_path(worktree_path, path)?))
+}
+
+/// Join a repository-relative path onto `root`, refusing paths that lead outside it
+///
+/// Absolute paths and `..` components are rejected outright. The joined path is then
+/// checked after resolving symlinks, as far as it exists, so a symlinked directory
+/// cannot be used to reach outside the root either.
+fn contained_path(root: &Path, path: &str) -> Result<PathBuf> {
+    let relative = Path::new(path);
+    let mut names_file = false;
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) => names_file = true,
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(anyhow::anyhow!(
+                    "'{}' must be a path relative to the repository root without '..'",
+                    path
+                ));
+            }
+        }
+    }
+    if !names_file {
+        return Err(anyhow::anyhow!("'{}' does not name a file or directory", path));
+    }
+
+    let joined = root.join(relative);
+    let root = root.canonicalize()
+        .context(format!("Failed to resolve {}", root.display()))?;
+    let existing = joined.ancestors()
+        .find(|ancestor| ancestor.symlink_metadata().is_ok())
+        .unwrap_or(&joined);
+    let resolved = existing.canonicalize()
+        .context(format!("Failed to resolve {}", existing.display()))?;
+    if !resolved.starts_with(&root) {
+        return Err(anyhow::anyhow!("'{}' resolves outside {}", path, root.display()));
+    }
+
+    Ok(joined)
+}
+
+/// Copy a file or directory tree, creating parent directories as needed
+fn copy_path(source: &Path, target: &Path) -> Result<()> {
+    if !source.exists() {
+        return Err(anyhow::anyhow!("{} does not exist", source.display()));
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .context(format!("Failed to create directory {}", parent.display()))?;
+    }
+
+    if source.is_dir() {
+        std::fs::create_dir_all(target)
+            .context(format!("Failed to create directory {}", target.display()))?;
+        for entry in std::fs::read_dir(source).context(format!("Failed to read {}", source.display()))? {
+            let entry = entry?;
+            copy_path(&entry.path(), &target.join(entry.file_name()))?;
+        }
+    } else {
+        std::fs::copy(source, target)
+            .context(format!("Failed to copy {} to {}", source.display(), target.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Symlink a file or directory, creating parent directories as needed
+fn symlink_path(source: &Path, target: &Path) -> Result<()> {
+    if !source.exists() {
+        return Err(anyhow::anyhow!("{} does not exist", source.display()));
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .context(format!("Failed to create directory {}", parent.display()))?;
+    }
+
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(source, target);
+    #[cfg(windows)]
+    let result = if source.is_dir() {
+        std::os::windows::fs::symlink_dir(source, target)
+    } else {
+        std::os::windows::fs::symlink_file(source, target)
+    };
+
+    result.context(format!("Failed to symlink {} to {}", target.display(), source.display()))
+}
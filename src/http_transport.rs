@@ -0,0 +1,681 @@
+use anyhow::{Context, Result};
+use crate::mcp::server::RequestHandler;
+use crate::mcp::types::{
+    CallToolRequest, GetPromptRequest, ListPromptsRequest, ListResourcesRequest, ListToolsRequest,
+    ReadResourceRequest, ResourceContents, Role, SubscribeRequest, ToolInputSchema, UnsubscribeRequest,
+};
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, info, warn};
+
+use crate::progress::{InFlightRequests, ProgressReporter, ToolCallContext, ToolCallHandler};
+use crate::resources::{ClientSubscriptions, ResourceNotifier};
+use crate::roots::ClientRoots;
+
+/// Path MCP clients post messages to and open the notification stream on
+pub const MCP_PATH: &str = "/mcp";
+
+/// Environment variable the bearer token is read from when not given on the command line
+pub const TOKEN_ENV_VAR: &str = "SUBAGENT_WORKTREE_MCP_TOKEN";
+
+/// MCP protocol version reported on initialization
+const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// Largest request head (request line and headers) accepted
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// Interval of keep-alive comments on notification streams, so closed connections are noticed
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Header carrying the session a client was given at initialization
+const SESSION_HEADER: &str = "Mcp-Session-Id";
+
+/// Serve MCP over streamable HTTP on a bound listener until the listener fails
+///
+/// Clients post JSON-RPC messages to [`MCP_PATH`] and get the response as JSON; a GET
+/// request on the same path opens a server-sent event stream of notifications. All
+/// clients share one server, so subagents outlive the client that spawned them.
+/// If a token is set, every request needs an `Authorization: Bearer <token>` header.
+///
+/// Each `initialize` starts a session, whose ID is returned in the `Mcp-Session-Id` header.
+/// Requests carrying it share the session's in-flight calls, so a client can cancel calls
+/// it posted earlier but never another client's; requests without it are on their own.
+/// Clients are never asked for their roots, so their calls are not restricted to roots.
+pub async fn serve<H: ToolCallHandler>(
+    listener: TcpListener,
+    handler: Arc<H>,
+    notifier: Arc<ResourceNotifier>,
+    token: Option<String>,
+) -> Result<()> {
+    let shared = Arc::new(Shared {
+        handler,
+        notifier,
+        token,
+        sessions: Mutex::new(HashMap::new()),
+    });
+    info!("Serving MCP over HTTP on http://{}{}", listener.local_addr()?, MCP_PATH);
+
+    loop {
+        let (stream, peer) = listener.accept().await.context("Failed to accept connection")?;
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, peer, shared).await {
+                debug!("Connection from {} failed: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+/// Whether an address only accepts connections from this machine
+pub fn is_loopback(addr: &SocketAddr) -> bool {
+    addr.ip().is_loopback()
+}
+
+/// What a transport knows about the client a message came from
+#[derive(Debug, Clone, Default)]
+pub struct ClientSession {
+    /// Tool calls of the client in progress, so they can be cancelled
+    pub in_flight: Arc<InFlightRequests>,
+    /// Where progress notifications for the client go, if it can receive them
+    pub progress: Option<mpsc::UnboundedSender<Value>>,
+    /// Roots the client declared
+    pub roots: Arc<ClientRoots>,
+    /// Resources the client subscribed to
+    pub subscriptions: Arc<ClientSubscriptions>,
+}
+
+/// State shared by all connections
+struct Shared<H> {
+    handler: Arc<H>,
+    notifier: Arc<ResourceNotifier>,
+    token: Option<String>,
+    /// Sessions by the ID issued at initialization. Roots are not requested, as the server
+    /// cannot send requests to HTTP clients.
+    sessions: Mutex<HashMap<String, ClientSession>>,
+}
+
+/// A parsed HTTP request
+struct HttpRequest {
+    method: String,
+    path: String,
+    /// Header values by lowercase name
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl HttpRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+/// Serve one request; connections are closed after each response
+async fn handle_connection<H: ToolCallHandler>(stream: TcpStream, peer: SocketAddr, shared: Arc<Shared<H>>) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let request = match read_request(&mut stream).await {
+        Ok(request) => request,
+        Err(e) => {
+            write_response(stream.get_mut(), 400, "text/plain", format!("{:#}\n", e).as_bytes()).await?;
+            return Ok(());
+        }
+    };
+    debug!("{} {} from {}", request.method, request.path, peer);
+
+    if let Some(token) = &shared.token {
+        if !authorized(request.header("authorization"), token) {
+            warn!("Rejected unauthorized request from {}", peer);
+            return write_response(stream.get_mut(), 401, "text/plain", b"Missing or invalid bearer token\n").await;
+        }
+    }
+
+    let path = request.path.split('?').next().unwrap_or_default();
+    if path != MCP_PATH {
+        return write_response(stream.get_mut(), 404, "text/plain", b"Not found\n").await;
+    }
+
+    match request.method.as_str() {
+        "POST" => {
+            let message: Value = match serde_json::from_slice(&request.body) {
+                Ok(message) => message,
+                Err(e) => {
+                    let error = error_response(Value::Null, -32700, &format!("Parse error: {}", e));
+                    return write_response(stream.get_mut(), 400, "application/json", error.to_string().as_bytes()).await;
+                }
+            };
+
+            let sessionless = request.header("mcp-session-id").is_none();
+            let (session, new_session) = if message.get("method").and_then(Value::as_str) == Some("initialize") {
+                let id = new_session_id();
+                let session = ClientSession::default();
+                shared.sessions.lock().await.insert(id.clone(), session.clone());
+                info!("Started session {} for {}", id, peer);
+                (session, Some(id))
+            } else {
+                match request.header("mcp-session-id") {
+                    Some(id) => match shared.sessions.lock().await.get(id) {
+                        Some(session) => (session.clone(), None),
+                        None => return write_response(stream.get_mut(), 404, "text/plain", b"Unknown session, initialize again\n").await,
+                    },
+                    None => (ClientSession::default(), None),
+                }
+            };
+
+            // Progress is streamed to clients that can receive it, ahead of the response
+            let accepts_stream = request.header("accept").is_some_and(|accept| accept.contains("text/event-stream"));
+            if accepts_stream && requests_progress(&message) {
+                return stream_response(stream.into_inner(), &shared, session, message).await;
+            }
+
+            let headers: Vec<(&str, &str)> = new_session.iter().map(|id| (SESSION_HEADER, id.as_str())).collect();
+            let response = dispatch(&*shared.handler, message, &session).await;
+            // Without a session there is no stream to deliver updates on, so subscriptions end with the request
+            if sessionless && new_session.is_none() {
+                release_subscriptions(&*shared.handler, &session).await;
+            }
+            match response {
+                Some(response) => write_response_with_headers(
+                    stream.get_mut(),
+                    200,
+                    "application/json",
+                    &headers,
+                    response.to_string().as_bytes(),
+                ).await,
+                // Notifications and responses get no reply
+                None => write_response(stream.get_mut(), 202, "text/plain", b"").await,
+            }
+        }
+        "DELETE" => {
+            // Clients end their session when they are done with it
+            let Some(id) = request.header("mcp-session-id") else {
+                return write_response(stream.get_mut(), 400, "text/plain", b"Missing Mcp-Session-Id header\n").await;
+            };
+            let removed = shared.sessions.lock().await.remove(id);
+            match removed {
+                Some(session) => {
+                    release_subscriptions(&*shared.handler, &session).await;
+                    info!("Ended session {}", id);
+                    write_response(stream.get_mut(), 200, "text/plain", b"").await
+                }
+                None => write_response(stream.get_mut(), 404, "text/plain", b"Unknown session\n").await,
+            }
+        }
+        "GET" => {
+            if !request.header("accept").is_some_and(|accept| accept.contains("text/event-stream")) {
+                return write_response(stream.get_mut(), 406, "text/plain", b"Accept text/event-stream to open the notification stream\n").await;
+            }
+            // Updates are sent for the resources the session subscribed to
+            let Some(id) = request.header("mcp-session-id") else {
+                return write_response(stream.get_mut(), 400, "text/plain", b"Missing Mcp-Session-Id header\n").await;
+            };
+            let session = shared.sessions.lock().await.get(id).cloned();
+            let Some(session) = session else {
+                return write_response(stream.get_mut(), 404, "text/plain", b"Unknown session, initialize again\n").await;
+            };
+            stream_notifications(stream.into_inner(), shared.notifier.clone(), session.subscriptions).await
+        }
+        _ => write_response(stream.get_mut(), 405, "text/plain", b"Method not allowed\n").await,
+    }
+}
+
+/// Read a request head and its body
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Result<HttpRequest> {
+    let mut head_bytes = 0;
+    let mut request_line = String::new();
+    head_bytes += stream.read_line(&mut request_line).await.context("Failed to read request line")?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(anyhow::anyhow!("Malformed request line"));
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        let read = stream.read_line(&mut line).await.context("Failed to read headers")?;
+        head_bytes += read;
+        if head_bytes > MAX_HEAD_BYTES {
+            return Err(anyhow::anyhow!("Request headers too large"));
+        }
+        let line = line.trim_end();
+        if read == 0 || line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let content_length = match headers.get("content-length") {
+        Some(length) => length.parse::<usize>().context("Invalid Content-Length")?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_BYTES {
+        return Err(anyhow::anyhow!("Request body larger than {} bytes", MAX_BODY_BYTES));
+    }
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await.context("Failed to read request body")?;
+
+    Ok(HttpRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        headers,
+        body,
+    })
+}
+
+/// Whether an Authorization header carries the expected bearer token
+fn authorized(header: Option<&str>, token: &str) -> bool {
+    let Some(given) = header.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    // Compare in constant time, so the token cannot be guessed byte by byte
+    given.len() == token.len()
+        && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// A new, unguessable session ID
+fn new_session_id() -> String {
+    // Each RandomState is seeded randomly
+    let part = || RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}", part(), part())
+}
+
+/// Write a complete response and close the connection
+async fn write_response(stream: &mut TcpStream, status: u16, content_type: &str, body: &[u8]) -> Result<()> {
+    write_response_with_headers(stream, status, content_type, &[], body).await
+}
+
+/// Write a complete response with additional headers and close the connection
+async fn write_response_with_headers(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<()> {
+    let extra: String = headers.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        status,
+        reason_phrase(status),
+        content_type,
+        body.len(),
+        extra
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        _ => "Error",
+    }
+}
+
+/// Whether a message or batch asks for progress notifications
+fn requests_progress(message: &Value) -> bool {
+    match message {
+        Value::Array(messages) => messages.iter().any(requests_progress),
+        message => message.pointer("/params/_meta/progressToken").is_some(),
+    }
+}
+
+/// Answer a post with a server-sent event stream of its progress notifications and then its response
+async fn stream_response<H: ToolCallHandler>(
+    mut stream: TcpStream,
+    shared: &Shared<H>,
+    session: ClientSession,
+    message: Value,
+) -> Result<()> {
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+    ).await?;
+    stream.flush().await?;
+
+    let (sink, mut progress) = mpsc::unbounded_channel();
+    let session = ClientSession {
+        progress: Some(sink),
+        ..session
+    };
+    let id = message.get("id").cloned();
+    let response = dispatch(&*shared.handler, message, &session);
+    tokio::pin!(response);
+    let response = loop {
+        tokio::select! {
+            response = &mut response => break response,
+            Some(notification) = progress.recv() => {
+                let written = async {
+                    stream.write_all(format!("event: message\ndata: {}\n\n", notification).as_bytes()).await?;
+                    stream.flush().await
+                };
+                if let Err(e) = written.await {
+                    // The client is gone. Dropping the call could abandon a step that cannot be
+                    // interrupted, such as creating a worktree, without rolling it back; cancel
+                    // it instead and let it finish.
+                    if let Some(id) = &id {
+                        session.in_flight.cancel(id).await;
+                    }
+                    response.await;
+                    return Err(e.into());
+                }
+            }
+        }
+    };
+
+    // Notifications sent just before the call finished come first
+    while let Ok(notification) = progress.try_recv() {
+        stream.write_all(format!("event: message\ndata: {}\n\n", notification).as_bytes()).await?;
+    }
+    if let Some(response) = response {
+        stream.write_all(format!("event: message\ndata: {}\n\n", response).as_bytes()).await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Send a session's notifications as server-sent events until the client disconnects
+async fn stream_notifications(
+    mut stream: TcpStream,
+    notifier: Arc<ResourceNotifier>,
+    subscriptions: Arc<ClientSubscriptions>,
+) -> Result<()> {
+    // Listen before answering, so no update sent after the client sees the stream open is missed
+    let mut updates = notifier.updates();
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+    ).await?;
+    stream.flush().await?;
+
+    let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+    loop {
+        let event = tokio::select! {
+            update = updates.recv() => match update {
+                Ok(uri) => {
+                    if !subscriptions.contains(&uri).await {
+                        continue;
+                    }
+                    format!("event: message\ndata: {}\n\n", resource_updated(&uri))
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Notification stream fell behind, skipped {} updates", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            _ = keep_alive.tick() => ": keep-alive\n\n".to_string(),
+        };
+        // Writing fails once the client is gone
+        stream.write_all(event.as_bytes()).await?;
+        stream.flush().await?;
+    }
+}
+
+/// The notification sent to clients when a subscribed resource changes
+pub fn resource_updated(uri: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/resources/updated",
+        "params": { "uri": uri },
+    })
+}
+
+/// Handle a JSON-RPC message or batch, returning the response to send, if any
+///
+/// This is shared by every transport: HTTP, clients attached to the daemon, and in-process stdio.
+///
+/// Tool calls are tracked in the session so a later `notifications/cancelled` can stop them,
+/// report progress if the call carries a progress token, and are restricted to the client's roots.
+pub async fn dispatch<H: ToolCallHandler>(handler: &H, message: Value, session: &ClientSession) -> Option<Value> {
+    match message {
+        Value::Array(messages) => {
+            let mut responses = Vec::new();
+            for message in messages {
+                if let Some(response) = dispatch_message(handler, message, session).await {
+                    responses.push(response);
+                }
+            }
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        message => dispatch_message(handler, message, session).await,
+    }
+}
+
+/// Handle one JSON-RPC message
+async fn dispatch_message<H: ToolCallHandler>(handler: &H, message: Value, session: &ClientSession) -> Option<Value> {
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        // Responses to server requests are not expected
+        return None;
+    };
+    if method == "notifications/cancelled" {
+        if let Some(id) = message.pointer("/params/requestId") {
+            if session.in_flight.cancel(id).await {
+                info!("Client cancelled request {}", id);
+            }
+        }
+        return None;
+    }
+    // Messages without an ID are notifications, which get no response
+    let id = message.get("id")?.clone();
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    let result = match method {
+        "tools/call" => call_tool(handler, &id, params, session).await,
+        "resources/subscribe" => subscribe(handler, params, session).await,
+        "resources/unsubscribe" => unsubscribe(handler, params, session).await,
+        _ => handle_method(handler, method, params).await,
+    };
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(MethodError::NotFound) => error_response(id, -32601, &format!("Method not found: {}", method)),
+        Err(MethodError::InvalidParams(message)) => error_response(id, -32602, &message),
+        Err(MethodError::Failed(e)) => error_response(id, -32603, &format!("{:#}", e)),
+    })
+}
+
+/// Why a JSON-RPC method failed
+enum MethodError {
+    NotFound,
+    InvalidParams(String),
+    Failed(anyhow::Error),
+}
+
+impl From<anyhow::Error> for MethodError {
+    fn from(e: anyhow::Error) -> Self {
+        MethodError::Failed(e)
+    }
+}
+
+fn error_response(id: Value, code: i32, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// A required string parameter
+fn string_param(params: &Value, name: &str) -> Result<String, MethodError> {
+    params.get(name)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| MethodError::InvalidParams(format!("Missing string parameter '{}'", name)))
+}
+
+/// Subscribe the client to a resource; the handler validates it and keeps it watched
+async fn subscribe<H: ToolCallHandler>(handler: &H, params: Value, session: &ClientSession) -> Result<Value, MethodError> {
+    let uri = string_param(&params, "uri")?;
+    if !session.subscriptions.contains(&uri).await {
+        handler.subscribe(SubscribeRequest { uri: uri.clone() }).await?;
+        session.subscriptions.insert(&uri).await;
+    }
+    Ok(json!({}))
+}
+
+/// Unsubscribe the client from a resource, leaving other clients' subscriptions alone
+async fn unsubscribe<H: ToolCallHandler>(handler: &H, params: Value, session: &ClientSession) -> Result<Value, MethodError> {
+    let uri = string_param(&params, "uri")?;
+    if session.subscriptions.remove(&uri).await {
+        handler.unsubscribe(UnsubscribeRequest { uri }).await?;
+    }
+    Ok(json!({}))
+}
+
+/// Unsubscribe a client that is gone from every resource it subscribed to
+pub async fn release_subscriptions<H: RequestHandler>(handler: &H, session: &ClientSession) {
+    for uri in session.subscriptions.clear().await {
+        if let Err(e) = handler.unsubscribe(UnsubscribeRequest { uri }).await {
+            debug!("Failed to release subscription: {:#}", e);
+        }
+    }
+}
+
+/// Call a tool, with progress reporting, cancellation and the client's roots
+async fn call_tool<H: ToolCallHandler>(
+    handler: &H,
+    id: &Value,
+    params: Value,
+    session: &ClientSession,
+) -> Result<Value, MethodError> {
+    let request = CallToolRequest {
+        name: string_param(&params, "name")?,
+        arguments: params.get("arguments").cloned().unwrap_or(Value::Null),
+    };
+    let progress = params.pointer("/_meta/progressToken")
+        .cloned()
+        .zip(session.progress.clone())
+        .map(|(token, sink)| ProgressReporter::new(token, sink));
+
+    let result = match session.roots.current().await {
+        Ok(roots) => {
+            let cancellation = session.in_flight.start(id).await;
+            let context = ToolCallContext::new(progress, cancellation).with_roots(roots);
+            let result = handler.call_tool_with_context(request, context).await;
+            session.in_flight.finish(id).await;
+            result
+        }
+        Err(e) => Err(e),
+    };
+
+    // Tool failures are results the model should see, not protocol errors
+    Ok(match result {
+        Ok(result) => json!({
+            "content": result.content.iter()
+                .map(|content| json!({ "type": content.r#type, "text": content.text }))
+                .collect::<Vec<_>>(),
+            "isError": result.is_error,
+        }),
+        Err(e) => json!({
+            "content": [{ "type": "text", "text": format!("Error: {:#}", e) }],
+            "isError": true,
+        }),
+    })
+}
+
+async fn handle_method<H: RequestHandler>(handler: &H, method: &str, params: Value) -> Result<Value, MethodError> {
+    match method {
+        "initialize" => Ok(json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {
+                "tools": {},
+                "resources": { "subscribe": true },
+                "prompts": {},
+            },
+            "serverInfo": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })),
+        "ping" => Ok(json!({})),
+        "tools/list" => {
+            let result = handler.list_tools(ListToolsRequest).await?;
+            let tools: Vec<Value> = result.tools.iter()
+                .map(|tool| {
+                    let mut entry = json!({ "name": tool.name, "description": tool.description });
+                    let ToolInputSchema(schema) = &tool.input_schema;
+                    entry["inputSchema"] = schema.clone();
+                    entry
+                })
+                .collect();
+            Ok(json!({ "tools": tools }))
+        }
+        "resources/list" => {
+            let result = handler.list_resources(ListResourcesRequest).await?;
+            let resources: Vec<Value> = result.resources.iter()
+                .map(|resource| json!({
+                    "uri": resource.uri,
+                    "name": resource.name,
+                    "description": resource.description,
+                    "mimeType": resource.mime_type,
+                }))
+                .collect();
+            Ok(json!({ "resources": resources }))
+        }
+        "resources/read" => {
+            let uri = string_param(&params, "uri")?;
+            let result = handler.read_resource(ReadResourceRequest { uri }).await?;
+            let contents: Vec<Value> = result.contents.iter()
+                .map(|contents| match contents {
+                    ResourceContents::Text(text) => json!({
+                        "uri": text.uri,
+                        "mimeType": text.mime_type,
+                        "text": text.text,
+                    }),
+                })
+                .collect();
+            Ok(json!({ "contents": contents }))
+        }
+        "prompts/list" => {
+            let result = handler.list_prompts(ListPromptsRequest).await?;
+            let prompts: Vec<Value> = result.prompts.iter()
+                .map(|prompt| json!({
+                    "name": prompt.name,
+                    "description": prompt.description,
+                    "arguments": prompt.arguments.as_ref().map(|arguments| arguments.iter()
+                        .map(|argument| json!({
+                            "name": argument.name,
+                            "description": argument.description,
+                            "required": argument.required,
+                        }))
+                        .collect::<Vec<_>>()),
+                }))
+                .collect();
+            Ok(json!({ "prompts": prompts }))
+        }
+        "prompts/get" => {
+            let name = string_param(&params, "name")?;
+            let arguments = match params.get("arguments") {
+                None | Some(Value::Null) => None,
+                Some(arguments) => Some(serde_json::from_value::<HashMap<String, String>>(arguments.clone())
+                    .map_err(|e| MethodError::InvalidParams(format!("Invalid prompt arguments: {}", e)))?),
+            };
+            let result = handler.get_prompt(GetPromptRequest { name, arguments }).await?;
+            let messages: Vec<Value> = result.messages.iter()
+                .map(|message| json!({
+                    "role": match message.role {
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                    },
+                    "content": { "type": message.content.r#type, "text": message.content.text },
+                }))
+                .collect();
+            Ok(json!({ "description": result.description, "messages": messages }))
+        }
+        _ => Err(MethodError::NotFound),
+    }
+}
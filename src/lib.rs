@@ -5,19 +5,60 @@
 //! for AI agents using git worktrees and managing their lifecycle.
 
 pub mod agent_monitor;
+pub mod branch_naming;
+pub mod command_runner;
+pub mod config;
+pub mod conflicts;
+#[cfg(unix)]
+pub mod daemon;
+pub mod gc;
 pub mod git_operations;
+pub mod hooks;
+pub mod http_transport;
+pub mod mcp;
+pub mod merge_queue;
+pub mod progress;
+pub mod prompts;
+pub mod registry;
+pub mod repositories;
+pub mod resources;
+pub mod roots;
+pub mod schema;
+pub mod stream_transport;
 pub mod subagent_spawner;
+pub mod verification;
 pub mod doc_generator;
 
 // Re-export main types for easier use
 pub use agent_monitor::{AgentMonitor, AgentMonitorConfig, AgentProcessInfo, AgentSummary};
-pub use git_operations::{GitWorktreeManager, WorktreeInfo};
-pub use subagent_spawner::{AgentSpawner, AgentOptions, AgentInfo, SubagentSpawner, CursorCliAgent};
+pub use branch_naming::BranchNamingPolicy;
+pub use config::{
+    AgentDefaults, CheckpointIdentity, CheckpointSettings, CommandSettings, MergeQueueSettings, RepositoryEntry, ServerConfig,
+};
+pub use conflicts::{ConflictOptions, ConflictReport};
+pub use gc::{GcOptions, GcPolicy, GcReport};
+pub use git_operations::{
+    Checkpoint, CheckpointOptions, CreatedWorktree, GitIdentity, GitWorktreeManager, LfsOutcome, SparseCheckout,
+    SubmoduleResult, SyncOutcome, SyncStrategy, WorktreeInfo, WorktreeOptions, WorktreeStatus,
+};
+pub use hooks::{BootstrapConfig, BootstrapReport, HookFailurePolicy};
+pub use merge_queue::{MergeQueue, MergeQueueEntry, MergeStatus};
+pub use progress::{Cancellation, ToolCallContext, ToolCallHandler};
+pub use prompts::{PromptArgument, PromptTemplate};
+pub use registry::{RunRecord, SubagentRecord, SubagentRegistry};
+pub use repositories::{RepoContext, RepoRegistry};
+pub use resources::{ResourceNotifier, ResourceUri};
+pub use subagent_spawner::{AgentSpawner, AgentCapabilities, AgentOptions, AgentOutputStream, AgentInfo, AgentRunResult, SpawnedAgent, SubagentSpawner, CursorCliAgent};
+pub use verification::{RunTarget, WorktreeRun};
 pub use doc_generator::{DocGenerator, run_doc_generator};
 
 /// Main server configuration and implementation
 pub mod server {
-    pub use crate::main::{SubagentWorktreeServer, SubagentConfig, CleanupConfig};
+    pub use crate::main::{
+        SubagentWorktreeServer, SubagentConfig, CleanupConfig, GcConfig, SparseCheckoutConfig, SyncConfig,
+        ListWorktreesConfig, AddRepositoryConfig, CheckpointConfig, PredictConflictsConfig,
+        EnqueueMergeConfig, ListMergeQueueConfig, RunInWorktreeConfig, ListAgentsConfig,
+    };
 }
 
 // Include the main module (which contains the server implementation)
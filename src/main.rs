@@ -1,338 +1,1408 @@
-use anyhow::{Context, Result};
-use mcp::server::{McpServer, RequestHandler};
-use mcp::types::{
-    CallToolRequest, CallToolResult, ListToolsRequest, ListToolsResult, 
-    Tool, ToolInputSchema, TextContent, ImageContent, EmbeddedResource
+use anyhow::Result;
+use crate::mcp::server::RequestHandler;
+use crate::mcp::types::{
+    CallToolRequest, CallToolResult, ListToolsRequest, ListToolsResult,
+    Tool, ToolInputSchema, TextContent,
+    ListResourcesRequest, ListResourcesResult, ReadResourceRequest, ReadResourceResult, Resource,
+    ResourceContents, SubscribeRequest, TextResourceContents, UnsubscribeRequest,
+    GetPromptRequest, GetPromptResult, ListPromptsRequest, ListPromptsResult, Prompt, PromptArgument,
+    PromptMessage, Role,
 };
+use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::path::PathBuf;
-use tracing::{info, warn};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
 
-use crate::git_operations::GitWorktreeManager;
-use crate::subagent_spawner::{SubagentSpawner, CursorCliAgent, AgentOptions};
+use crate::agent_monitor::{self, AgentMonitorConfig};
+use crate::config::{AgentDefaults, CheckpointSettings, RepositoryEntry, ServerConfig};
+use crate::conflicts::{self, ConflictOptions};
+use crate::gc::{self, GcOptions, GcPolicy};
+use crate::git_operations::{
+    subagent_lock_reason, CheckpointOptions, CreatedWorktree, GitWorktreeManager, LfsOutcome, SparseCheckout,
+    SyncOutcome, SyncStrategy, WorktreeOptions,
+};
+use crate::hooks::{self, BootstrapConfig, HookFailurePolicy};
+#[cfg(unix)]
+use crate::daemon;
+use crate::http_transport;
+use crate::merge_queue::MergeStatus;
+use crate::progress::{ToolCallContext, ToolCallHandler};
+use crate::roots;
+use crate::schema;
+use crate::prompts;
+use crate::stream_transport;
+use crate::registry::{self, SubagentRecord};
+use crate::repositories::{self, RepoContext, RepoRegistry};
+use crate::resources::{self, ResourceNotifier};
+use crate::subagent_spawner::{self, SubagentSpawner, CursorCliAgent, AgentOptions, AgentRunResult};
+use crate::verification::{self, RunTarget};
+
+/// How long to wait for the output of an exited agent to be logged before parsing it
+const OUTPUT_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
 /// Configuration for spawning a subagent
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct SubagentConfig {
-    /// Name of the branch to create for the subagent
-    pub branch_name: String,
-    /// Optional base branch to create from (defaults to current branch)
-    pub base_branch: Option<String>,
-    /// Prompt to give to the new subagent
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+    /// Name of the branch to create for the subagent (generated from the configured naming template if omitted)
+    pub branch_name: Option<String>,
+    /// Revision to create the branch from: branch, remote-tracking branch (tracked as upstream), tag,
+    /// commit SHA, expression like HEAD~3, or ref like refs/pull/123/head (defaults to HEAD; base_branch
+    /// is accepted as an alias)
+    #[serde(alias = "base_branch")]
+    pub base_ref: Option<String>,
+    /// Initial prompt to give to the new subagent
     pub prompt: String,
-    /// Optional directory name for the worktree (defaults to branch_name)
+    /// Custom worktree directory name (defaults to branch_name)
     pub worktree_dir: Option<String>,
     
-    /// Agent type to spawn (defaults to "cursor-cli")
+    /// Type of agent to spawn (defaults to the repository's configured agent, or "cursor-cli")
     pub agent_type: Option<String>,
     
     /// Agent-specific options
     pub agent_options: Option<AgentOptions>,
+
+    /// Only check out these paths in the worktree (defaults to a full checkout)
+    pub sparse_paths: Option<Vec<String>>,
+    /// Treat sparse_paths as cone-mode directories rather than gitignore-style patterns (defaults to true)
+    pub sparse_cone: Option<bool>,
+
+    /// Initialize and update submodules recursively, sharing objects with the main checkout
+    /// (defaults to the configured setting)
+    pub submodules: Option<bool>,
+
+    /// Setup run in the new worktree before the agent starts, replacing the configured bootstrap;
+    /// its commands must be configured tasks or allowed commands
+    pub bootstrap: Option<BootstrapConfig>,
+
+    /// Keep the worktree and branch if spawning fails, instead of rolling them back
+    #[serde(default)]
+    pub keep_on_failure: bool,
 }
 
 /// Configuration for cleaning up a worktree and its agents
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct CleanupConfig {
-    /// Name of the worktree/branch to clean up
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+    /// Worktree to clean up: branch name, directory name, path, or subagent ID
     pub worktree_name: String,
-    /// Whether to force cleanup even if agents are still running
+    /// Force cleanup even if agents are still running or the worktree is locked
+    #[serde(default)]
     pub force: bool,
-    /// Whether to remove the git branch after cleanup
+    /// Remove the git branch after cleanup
+    #[serde(default)]
     pub remove_branch: bool,
-    /// Whether to kill running agents before cleanup
+    /// Kill running agents before cleanup
+    #[serde(default)]
     pub kill_agents: bool,
 }
 
+/// Configuration for widening the sparse-checkout set of a worktree
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SparseCheckoutConfig {
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+    /// Worktree to widen: branch name, directory name, path, or subagent ID
+    pub worktree_name: String,
+    /// Paths to add to the sparse set (may be omitted if disable is set)
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Disable sparse checkout entirely, checking out all files
+    #[serde(default)]
+    pub disable: bool,
+}
+
+/// Configuration for syncing a subagent worktree with its base branch
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SyncConfig {
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+    /// Worktree to sync: branch name, directory name, path, or subagent ID
+    pub worktree_name: String,
+    /// Base revision to sync with (defaults to the base recorded at spawn)
+    #[serde(alias = "base_branch")]
+    pub base_ref: Option<String>,
+    /// Name of a configured remote to fetch the base branch from (defaults to the configured remote,
+    /// or the local branch)
+    pub remote: Option<String>,
+    /// How to integrate the base branch (defaults to the configured strategy)
+    pub strategy: Option<SyncStrategy>,
+    /// Leave a conflicted rebase or merge in progress instead of aborting it
+    #[serde(default)]
+    pub keep_conflicts: bool,
+    /// Sync even if agents are running in the worktree
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Configuration for committing the work in a worktree
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CheckpointConfig {
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+    /// Worktree to checkpoint: branch name, directory name, path, or subagent ID
+    pub worktree_name: String,
+    /// Commit message (generated from the subagent's prompt if omitted)
+    pub message: Option<String>,
+    /// Additional trailers appended to the message, e.g. {"Refs": "#123"}
+    #[serde(default)]
+    pub trailers: IndexMap<String, String>,
+}
+
+/// Configuration for running a command in a worktree
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RunInWorktreeConfig {
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+    /// Worktree to run in: branch name, directory name, path, or subagent ID
+    pub worktree_name: String,
+    /// Name of a task from the configuration (either task or command is required)
+    pub task: Option<String>,
+    /// Command to run; must be a configured task or listed in allowed_commands (either task or
+    /// command is required)
+    pub command: Option<String>,
+    /// Timeout in seconds (defaults to the configured timeout)
+    pub timeout_secs: Option<u64>,
+}
+
+/// Configuration for predicting conflicts between active subagents
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PredictConflictsConfig {
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+    /// Base branch for subagents spawned without a recorded base (defaults to HEAD)
+    pub base_branch: Option<String>,
+}
+
+/// Configuration for adding a branch to the merge queue
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EnqueueMergeConfig {
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+    /// Branch to merge
+    pub branch: String,
+    /// Branch to merge into (defaults to the configured target or the branch checked out in the
+    /// main checkout)
+    pub target_branch: Option<String>,
+}
+
+/// Configuration for showing the merge queue
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ListMergeQueueConfig {
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+}
+
+/// Configuration for garbage collecting stale and orphaned worktrees
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GcConfig {
+    /// Repository to operate on: name or path (defaults to the repository the server was started in)
+    pub repo: Option<String>,
+    /// What to do with collected worktrees (defaults to the configured policy)
+    pub policy: Option<GcPolicy>,
+    /// Collect subagent worktrees idle for longer than this many seconds (defaults to the configured TTL)
+    pub idle_ttl_secs: Option<u64>,
+    /// Base branch for the merged check when none was recorded at spawn
+    pub base_branch: Option<String>,
+}
+
+/// Configuration for listing worktrees
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ListWorktreesConfig {
+    /// Repository to list: name or path (defaults to all repositories)
+    pub repo: Option<String>,
+}
+
+/// Configuration for listing the agent types the server can spawn
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ListAgentsConfig {
+    /// Only list agents that are available on this system
+    #[serde(default)]
+    pub only_available: bool,
+}
+
+/// Configuration for adding a repository to the server
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AddRepositoryConfig {
+    /// Path of the repository
+    pub path: PathBuf,
+    /// Name used to refer to the repository in tool calls (defaults to its directory name)
+    pub name: Option<String>,
+    /// Directory new worktrees are created in, relative to the repository
+    /// (defaults to the repository's configuration, or its parent directory)
+    pub worktree_root: Option<PathBuf>,
+    /// Agent type used when a spawn request does not name one
+    pub agent_type: Option<String>,
+    /// Agent options used when a spawn request does not give any
+    pub agent_options: Option<AgentOptions>,
+}
+
 /// Main MCP server implementation
 pub struct SubagentWorktreeServer {
-    git_manager: GitWorktreeManager,
     spawner: SubagentSpawner,
+    repos: Arc<RwLock<RepoRegistry>>,
+    /// Resource subscriptions of the client
+    resources: Arc<ResourceNotifier>,
 }
 
 impl SubagentWorktreeServer {
     /// Create a new server instance
-    pub fn new(repo_path: PathBuf) -> Result<Self> {
-        let git_manager = GitWorktreeManager::new(repo_path)?;
+    ///
+    /// The launch directory is the default repository if it is a git repository; the
+    /// repositories listed in its configuration are managed as well.
+    pub fn new(launch_path: PathBuf) -> Result<Self> {
         let mut spawner = SubagentSpawner::new()?;
         
         // Register default agents
         spawner.register_agent(Box::new(CursorCliAgent));
+
+        let mut repos = RepoRegistry::new();
+        if GitWorktreeManager::new(launch_path.clone()).is_ok() {
+            repos.add(RepoContext::open(&RepositoryEntry::new(launch_path.clone()))?)?;
+        }
+
+        let launch_config = ServerConfig::load(&launch_path)?;
+        for entry in &launch_config.repositories {
+            // Relative paths are relative to the launch directory
+            let mut entry = entry.clone();
+            entry.path = launch_path.join(&entry.path);
+            repos.add(RepoContext::open(&entry)?)?;
+        }
+
+        if repos.names().is_empty() {
+            warn!(
+                "{} is not a git repository and no repositories are configured; add one with add_repository",
+                launch_path.display()
+            );
+        }
         
         Ok(Self {
-            git_manager,
             spawner,
+            repos: Arc::new(RwLock::new(repos)),
+            resources: Arc::new(ResourceNotifier::new()),
         })
     }
 
+    /// Start background tasks enabled in the configuration of each repository, and the resource watcher
+    pub async fn start_background_tasks(&self) {
+        for repo in self.repos.read().await.all() {
+            repo.start_background_tasks();
+        }
+        resources::spawn_resource_watcher(self.repos.clone(), self.resources.clone(), resources::WATCH_INTERVAL);
+    }
+
+    /// Resource subscriptions, for forwarding updates to the client
+    pub fn resource_notifier(&self) -> Arc<ResourceNotifier> {
+        self.resources.clone()
+    }
+
+    /// Resolve the repository a tool call operates on
+    async fn repo(&self, identifier: Option<&str>) -> Result<Arc<RepoContext>> {
+        self.repos.read().await.resolve(identifier)
+    }
+
+    /// Restrict a tool call to the client's roots, if it declared any
+    ///
+    /// Calls that name no repository operate on the repository of the first root that is in
+    /// one, rather than the directory the server was started in. Calls naming a repository
+    /// outside the roots are refused. Roots are only requested on stdio, so calls over HTTP
+    /// are not restricted.
+    async fn scope_to_roots(&self, mut request: CallToolRequest, context: &ToolCallContext) -> Result<CallToolRequest> {
+        let Some(roots) = context.roots() else {
+            return Ok(request);
+        };
+        if roots.is_empty() {
+            return Err(anyhow::anyhow!("The client declared no roots, so no repository may be used"));
+        }
+        let root_repos = self.repos_for_roots(roots).await;
+
+        if request.name == "add_repository" {
+            let path = request.arguments.get("path").and_then(Value::as_str).unwrap_or_default();
+            if !roots::is_within(std::path::Path::new(path), roots) {
+                return Err(anyhow::anyhow!("{} is outside the client's roots", path));
+            }
+            return Ok(request);
+        }
+
+        let takes_repo = Self::get_tools().iter()
+            .find(|tool| tool.name == request.name)
+            .map(|tool| &tool.input_schema)
+            .is_some_and(|ToolInputSchema(schema)| schema.pointer("/properties/repo").is_some());
+        if !takes_repo {
+            return Ok(request);
+        }
+
+        match request.arguments.get("repo").and_then(Value::as_str) {
+            Some(identifier) => {
+                let repo = self.repo(Some(identifier)).await?;
+                let from_root = root_repos.iter().any(|r| r.name == repo.name);
+                if !from_root && !roots::is_within(repo.git_manager.repo_path(), roots) {
+                    return Err(anyhow::anyhow!(
+                        "Repository '{}' at {} is outside the client's roots",
+                        repo.name,
+                        repo.git_manager.repo_path().display()
+                    ));
+                }
+            }
+            None => {
+                let repo = root_repos.first().ok_or_else(|| anyhow::anyhow!(
+                    "None of the client's roots is in a git repository: {}",
+                    roots.iter().map(|r| r.display().to_string()).collect::<Vec<_>>().join(", ")
+                ))?;
+                if request.arguments.is_null() {
+                    request.arguments = json!({});
+                }
+                if let Some(arguments) = request.arguments.as_object_mut() {
+                    arguments.insert("repo".to_string(), json!(repo.name));
+                }
+            }
+        }
+        Ok(request)
+    }
+
+    /// Manage the repository of a root, unless another call already did
+    async fn add_root_repo(&self, repo_path: &std::path::Path) -> Result<Arc<RepoContext>> {
+        let mut repos = self.repos.write().await;
+        if let Some(repo) = repos.find_by_path(repo_path) {
+            return Ok(repo);
+        }
+        let repo = repos.add(RepoContext::open(&RepositoryEntry::new(repo_path.to_path_buf()))?)?;
+        repo.start_background_tasks();
+        Ok(repo)
+    }
+
+    /// The repositories containing the client's roots, in root order, managing them if needed
+    async fn repos_for_roots(&self, roots: &[PathBuf]) -> Vec<Arc<RepoContext>> {
+        let mut repos = Vec::new();
+        for root in roots {
+            let git_manager = match GitWorktreeManager::discover(root) {
+                Ok(git_manager) => git_manager,
+                Err(e) => {
+                    debug!("Root {} is not in a repository: {:#}", root.display(), e);
+                    continue;
+                }
+            };
+
+            let existing = self.repos.read().await.find_by_path(git_manager.repo_path());
+            let repo = match existing {
+                Some(repo) => repo,
+                None => match self.add_root_repo(git_manager.repo_path()).await {
+                    Ok(repo) => repo,
+                    Err(e) => {
+                        warn!("Failed to manage repository of root {}: {:#}", root.display(), e);
+                        continue;
+                    }
+                },
+            };
+            if !repos.iter().any(|r: &Arc<RepoContext>| r.name == repo.name) {
+                repos.push(repo);
+            }
+        }
+        repos
+    }
+
     /// Handle the spawn_subagent tool call
-    async fn handle_spawn_subagent(&self, config: SubagentConfig) -> Result<String> {
+    ///
+    /// Spawning is transactional: if a step fails after the worktree was created, the
+    /// worktree, the branch (if it was created for this spawn) and the registry entry
+    /// are rolled back, unless the caller asked to keep them. Cancelling the call before
+    /// the agent runs rolls back the same way; cancelling while waiting for the agent
+    /// kills it and keeps its worktree.
+    async fn handle_spawn_subagent(&self, config: SubagentConfig, context: &ToolCallContext) -> Result<String> {
         info!("Spawning subagent with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
         
         // Validate that we're in a git repository
-        if !self.git_manager.is_git_repo() {
+        if !repo.git_manager.is_git_repo() {
             return Err(anyhow::anyhow!("Not in a git repository"));
         }
 
-        // Create the worktree
-        let worktree_path = self.git_manager.create_worktree(
-            &config.branch_name,
-            config.base_branch.as_deref(),
-            config.worktree_dir.as_deref(),
+        // Commands given with the request come from the client, so they are held to the same
+        // allow-list as run_in_worktree; only the repository's configured bootstrap is trusted
+        if let Some(bootstrap) = &config.bootstrap {
+            for command in &bootstrap.commands {
+                verification::resolve_command(&repo.config.commands, &RunTarget::Command(command.clone()))
+                    .map_err(|e| e.context("Bootstrap command refused"))?;
+            }
+        }
+
+        // Determine agent type and options, falling back to the repository's defaults
+        let defaults = &repo.config.agent_defaults;
+        let agent_type = config.agent_type.clone()
+            .or_else(|| defaults.agent_type.clone())
+            .unwrap_or_else(|| "cursor-cli".to_string());
+        let agent_options = config.agent_options.clone()
+            .or_else(|| defaults.agent_options.clone())
+            .unwrap_or_default();
+
+        // Fail before touching the repository if the agent cannot be started
+        self.spawner.ensure_agent_available(&agent_type).await?;
+        self.spawner.validate_options(&agent_type, &agent_options)?;
+        let capabilities = self.spawner.capabilities(&agent_type)?;
+
+        let subagent_id = registry::generate_id();
+        let branch_name = self.subagent_branch_name(&repo, &config, &agent_type, &subagent_id).await?;
+
+        // Generated names are hierarchical; keep their worktrees next to the repository
+        let worktree_dir = match (&config.worktree_dir, &config.branch_name) {
+            (Some(dir), _) => Some(dir.clone()),
+            (None, Some(_)) => None,
+            (None, None) => Some(branch_name.replace('/', "-")),
+        };
+
+        let worktree_options = WorktreeOptions {
+            sparse: config.sparse_paths.clone().map(|paths| SparseCheckout {
+                paths,
+                cone: config.sparse_cone.unwrap_or(true),
+            }),
+            submodules: config.submodules.unwrap_or(repo.config.submodules),
+        };
+
+        // Create the worktree. This runs in a blocking task that cannot be interrupted, so a
+        // cancellation takes effect once it is done and the worktree can be rolled back. The
+        // call must not be dropped meanwhile, or the worktree would be left behind.
+        context.report("Creating branch and checking out worktree");
+        let created = repo.git_manager.create_worktree_with_options(
+            &branch_name,
+            config.base_ref.as_deref(),
+            worktree_dir.as_deref(),
+            &worktree_options,
         ).await?;
+        let worktree_path = created.path.clone();
 
         info!("Created worktree at: {}", worktree_path.display());
+        if let Err(e) = context.check_cancelled("Creating branch and checking out worktree") {
+            return Err(self.abort_spawn(&repo, &branch_name, &created, None, config.keep_on_failure, e).await);
+        }
+
+        // Prepare the worktree before the agent starts
+        let bootstrap = config.bootstrap.as_ref().unwrap_or(&repo.config.bootstrap);
+        let bootstrap_summary = match context.run("Running bootstrap hooks", self.bootstrap_worktree(&repo, &worktree_path, bootstrap)).await {
+            Ok(summary) => summary,
+            Err(e) => {
+                let keep = config.keep_on_failure
+                    || (!context.is_cancelled() && bootstrap.on_failure == HookFailurePolicy::Keep);
+                return Err(self.abort_spawn(&repo, &branch_name, &created, None, keep, e).await);
+            }
+        };
 
-        // Determine agent type and options
-        let agent_type = config.agent_type.unwrap_or_else(|| "cursor-cli".to_string());
-        let agent_options = config.agent_options.unwrap_or_default();
+        // Register the subagent before it starts, so it is known while it runs
+        context.report("Registering subagent");
+        let now = registry::unix_now();
+        let record = SubagentRecord {
+            id: subagent_id.clone(),
+            branch: branch_name.clone(),
+            worktree_path: worktree_path.clone(),
+            base_ref: created.base_ref.clone(),
+            base_commit: Some(created.base_commit.clone()),
+            agent_type: agent_type.clone(),
+            prompt: config.prompt.clone(),
+            created_at: now,
+            last_activity: now,
+            last_run: None,
+            agent_pid: None,
+        };
+        let exit_checkpoint = repo.config.checkpoint.on_agent_exit
+            .then(|| checkpoint_options(&repo.config.checkpoint, Some(&record), Some(&branch_name), None, IndexMap::new()));
+        if let Err(e) = repo.registry.lock().await.insert(record) {
+            return Err(self.abort_spawn(&repo, &branch_name, &created, None, config.keep_on_failure, e).await);
+        }
         
         // Spawn the specified agent in the new worktree directory
-        self.spawner.spawn_agent(&agent_type, &worktree_path, &config.prompt, &agent_options).await?;
+        let spawn = self.spawner.spawn_agent(&agent_type, &worktree_path, &config.prompt, &agent_options);
+        let mut spawned = match context.run("Starting agent", spawn).await {
+            Ok(spawned) => spawned,
+            Err(e) => {
+                return Err(self.abort_spawn(
+                    &repo,
+                    &branch_name,
+                    &created,
+                    Some(&subagent_id),
+                    config.keep_on_failure,
+                    e,
+                ).await);
+            }
+        };
+        let kill_switch = spawned.take_kill_switch();
 
-        Ok(format!(
-            "Successfully spawned subagent in worktree '{}' at {}",
-            config.branch_name,
-            worktree_path.display()
-        ))
+        // Log the agent's output, which is exposed as the agent://<id>/log resource
+        let mut log_path = None;
+        let mut captures = Vec::new();
+        match repo.git_manager.state_dir() {
+            Ok(state_dir) => {
+                let path = resources::agent_log_path(&state_dir, &subagent_id);
+                for stream in spawned.take_output() {
+                    captures.push(resources::capture_agent_output(stream, path.clone(), subagent_id.clone(), self.resources.clone()));
+                }
+                log_path = Some(path);
+            }
+            Err(e) => warn!("Not logging output of subagent {}: {}", subagent_id, e),
+        }
+
+        // Only this process may be killed when the worktree is cleaned up
+        if let Some(pid) = spawned.pid {
+            if let Err(e) = repo.registry.lock().await.record_agent_pid(&subagent_id, pid) {
+                warn!("Failed to record agent process of subagent {}: {:#}", subagent_id, e);
+            }
+        }
+
+        // Protect the worktree from pruning and cleanup while the agent works in it
+        let lock_reason = subagent_lock_reason(&subagent_id, spawned.pid);
+        let locked = match repo.git_manager.lock_worktree(&worktree_path, &lock_reason).await {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Failed to lock worktree {}: {}", worktree_path.display(), e);
+                false
+            }
+        };
+
+        let pid = spawned.pid;
+        let git_manager = repo.git_manager.clone();
+        let lock_path = worktree_path.clone();
+        let wait_and_unlock = async move {
+            let exit = spawned.wait().await;
+            // Commit whatever the agent left behind, so cleanup cannot lose it
+            if let Some(options) = &exit_checkpoint {
+                match git_manager.checkpoint_worktree(&lock_path, options).await {
+                    Ok(Some(checkpoint)) => info!(
+                        "Checkpointed {} files in {} as {} after agent exit",
+                        checkpoint.files_changed,
+                        lock_path.display(),
+                        checkpoint.commit
+                    ),
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to checkpoint worktree {} after agent exit: {:#}", lock_path.display(), e),
+                }
+            }
+            if locked {
+                if let Err(e) = git_manager.unlock_worktree(&lock_path).await {
+                    warn!("Failed to unlock worktree {}: {}", lock_path.display(), e);
+                }
+            }
+            exit
+        };
+
+        let agent_status = if agent_options.detach {
+            tokio::spawn(wait_and_unlock);
+            "running".to_string()
+        } else {
+            // Agents with a window for the user finish when the user closes it
+            context.report(if capabilities.headless { "Waiting for agent" } else { "Waiting for agent window to close" });
+            tokio::pin!(wait_and_unlock);
+            let exit = tokio::select! {
+                exit = &mut wait_and_unlock => exit?,
+                _ = context.cancelled() => {
+                    // The agent may have done work by now, so it is stopped but not rolled back
+                    if let Some(kill) = kill_switch {
+                        let _ = kill.send(());
+                    }
+                    wait_and_unlock.await?;
+                    return Err(anyhow::anyhow!(
+                        "Cancelled by the client while waiting for agent; subagent '{}' was killed and its worktree at {} was kept",
+                        subagent_id,
+                        worktree_path.display()
+                    ));
+                }
+            };
+            match exit {
+                Some(code) => format!("exited with code {}", code),
+                None => "terminated by signal".to_string(),
+            }
+        };
+
+        // Agents reporting as JSON lines give their result and session in their output
+        let run_result = match (&log_path, agent_options.json_output && !agent_options.detach) {
+            (Some(log_path), true) => Self::agent_run_result(log_path, captures).await,
+            _ => None,
+        };
+
+        let mut result = format!(
+            "Successfully spawned subagent '{}' in worktree '{}' at {} (PID: {}, {})",
+            subagent_id,
+            branch_name,
+            worktree_path.display(),
+            pid.map_or_else(|| "unknown".to_string(), |pid| pid.to_string()),
+            agent_status
+        );
+        let checkout_summary = checkout_summary(&created);
+        if !checkout_summary.is_empty() {
+            result.push_str(&format!("\nCheckout:\n{}", checkout_summary));
+        }
+        if !bootstrap_summary.is_empty() {
+            result.push_str(&format!("\nBootstrap:\n{}", bootstrap_summary));
+        }
+        if let Some(run_result) = run_result {
+            if let Some(session_id) = &run_result.session_id {
+                result.push_str(&format!("\nSession: {} (pass as resume_session to continue it)", session_id));
+            }
+            if let Some(text) = &run_result.result {
+                let label = if run_result.is_error { "Agent failed" } else { "Agent result" };
+                result.push_str(&format!("\n{}:\n{}", label, text));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Parse the result of a finished agent run from its log, once the captured output is written
+    ///
+    /// Output is only waited for briefly, since processes the agent started may hold its pipes open.
+    async fn agent_run_result(log_path: &std::path::Path, captures: Vec<tokio::task::JoinHandle<()>>) -> Option<AgentRunResult> {
+        let captured = async {
+            for capture in captures {
+                let _ = capture.await;
+            }
+        };
+        if tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, captured).await.is_err() {
+            warn!("Agent output still open after exit, parsing the output logged so far");
+        }
+        match tokio::fs::read_to_string(log_path).await {
+            Ok(output) => subagent_spawner::parse_json_output(&output),
+            Err(e) => {
+                warn!("Failed to read agent log {}: {}", log_path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Determine the branch for a new subagent, generating it if not given, and enforce the naming policy
+    async fn subagent_branch_name(
+        &self,
+        repo: &RepoContext,
+        config: &SubagentConfig,
+        agent_type: &str,
+        subagent_id: &str,
+    ) -> Result<String> {
+        let policy = &repo.config.branch_naming;
+        let branch_name = match &config.branch_name {
+            Some(branch_name) => {
+                policy.validate(branch_name)?;
+                branch_name.clone()
+            }
+            None => policy.generate(agent_type, &config.prompt, subagent_id)?,
+        };
+
+        if policy.forbid_existing && repo.git_manager.has_branch(&branch_name).await? {
+            return Err(anyhow::anyhow!(
+                "Branch '{}' already exists and the naming policy forbids reusing branches",
+                branch_name
+            ));
+        }
+
+        Ok(branch_name)
+    }
+
+    /// Run bootstrap steps in a new worktree
+    ///
+    /// Returns a summary of the steps that were run, or an error describing the failed step.
+    async fn bootstrap_worktree(
+        &self,
+        repo: &RepoContext,
+        worktree_path: &std::path::Path,
+        bootstrap: &BootstrapConfig,
+    ) -> Result<String> {
+        let report = hooks::run_bootstrap(repo.git_manager.repo_path(), worktree_path, bootstrap).await
+            .map_err(|e| anyhow::anyhow!("Bootstrap failed: {:#}", e))?;
+
+        match report.failure() {
+            Some(step) => Err(anyhow::anyhow!(
+                "Bootstrap step '{}' failed\n{}",
+                step.step,
+                report.summary()
+            )),
+            None => Ok(report.summary()),
+        }
+    }
+
+    /// Undo a partially completed spawn, returning an error that states what was rolled back
+    async fn abort_spawn(
+        &self,
+        repo: &RepoContext,
+        branch_name: &str,
+        created: &CreatedWorktree,
+        record_id: Option<&str>,
+        keep: bool,
+        error: anyhow::Error,
+    ) -> anyhow::Error {
+        warn!("Spawn of subagent on branch '{}' failed: {:#}", branch_name, error);
+
+        if keep {
+            return anyhow::anyhow!(
+                "Spawn failed: {:#}\nNothing was rolled back; the worktree at {} and branch '{}' were kept for inspection",
+                error,
+                created.path.display(),
+                branch_name
+            );
+        }
+
+        let mut rolled_back = Vec::new();
+        let mut rollback_errors = Vec::new();
+
+        if let Some(id) = record_id {
+            match repo.registry.lock().await.remove(id) {
+                Ok(_) => rolled_back.push(format!("removed registry entry '{}'", id)),
+                Err(e) => rollback_errors.push(format!("failed to remove registry entry '{}': {:#}", id, e)),
+            }
+        }
+        if created.created_worktree {
+            match repo.git_manager.force_remove_worktree(&created.path).await {
+                Ok(()) => rolled_back.push(format!("removed worktree at {}", created.path.display())),
+                Err(e) => rollback_errors.push(format!("failed to remove worktree at {}: {:#}", created.path.display(), e)),
+            }
+        }
+        if created.created_branch {
+            match repo.git_manager.delete_branch(branch_name).await {
+                Ok(()) => rolled_back.push(format!("deleted branch '{}'", branch_name)),
+                Err(e) => rollback_errors.push(format!("failed to delete branch '{}': {:#}", branch_name, e)),
+            }
+        }
+
+        let mut message = format!("Spawn failed: {:#}\n", error);
+        if rolled_back.is_empty() {
+            message.push_str("Nothing was rolled back; the worktree and branch existed before this spawn");
+        } else {
+            message.push_str(&format!("Rolled back: {}", rolled_back.join(", ")));
+        }
+        if !rollback_errors.is_empty() {
+            message.push_str(&format!("\nRollback incomplete: {}", rollback_errors.join(", ")));
+        }
+
+        anyhow::anyhow!(message)
     }
 
     /// Handle the cleanup_worktree tool call
     async fn handle_cleanup_worktree(&self, config: CleanupConfig) -> Result<String> {
         info!("Cleaning up worktree with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
         
         // Validate that we're in a git repository
-        if !self.git_manager.is_git_repo() {
+        if !repo.git_manager.is_git_repo() {
             return Err(anyhow::anyhow!("Not in a git repository"));
         }
 
-        // Find the worktree path
-        let repo_path = std::env::current_dir()?;
-        let worktree_path = repo_path.parent()
-            .context("Repository has no parent directory")?
-            .join(&config.worktree_name);
+        let worktree = repo.resolve_worktree(&config.worktree_name).await?;
 
-        if !worktree_path.exists() {
-            return Err(anyhow::anyhow!("Worktree '{}' does not exist", config.worktree_name));
+        // A locked worktree is still in use by an agent, unless that agent is gone
+        if let (Some(reason), false) = (&worktree.locked, config.force || worktree.has_stale_lock()) {
+            return Err(anyhow::anyhow!(
+                "Worktree at {} is locked ({}); use force to clean it up anyway",
+                worktree.path.display(),
+                if reason.is_empty() { "no reason given" } else { reason }
+            ));
         }
 
         // Kill running agents if requested
         if config.kill_agents {
-            self.kill_agents_in_worktree(&worktree_path, config.force).await?;
+            self.kill_agents_in_worktree(&repo, &worktree.path, config.force).await?;
+        }
+
+        if worktree.locked.is_some() {
+            repo.git_manager.unlock_worktree(&worktree.path).await?;
         }
 
         // Remove the worktree
-        self.git_manager.remove_worktree(&worktree_path).await?;
+        repo.git_manager.remove_worktree(&worktree.path).await?;
 
-        // Remove the branch if requested
-        if config.remove_branch {
-            self.remove_branch(&config.worktree_name).await?;
+        {
+            let mut registry = repo.registry.lock().await;
+            let record_id = registry.find_by_worktree(&worktree.path).map(|r| r.id.clone());
+            if let Some(id) = record_id {
+                registry.remove(&id)?;
+            }
         }
 
+        // Remove the branch if requested
+        let branch_note = match (config.remove_branch, worktree.branch.as_deref()) {
+            (true, Some(branch)) => {
+                repo.git_manager.delete_branch(branch).await?;
+                format!(" and removed branch '{}'", branch)
+            }
+            (true, None) => " (detached worktree, no branch to remove)".to_string(),
+            (false, _) => String::new(),
+        };
+
         Ok(format!(
-            "Successfully cleaned up worktree '{}'{}",
-            config.worktree_name,
-            if config.remove_branch { " and removed branch" } else { "" }
+            "Successfully cleaned up worktree at {}{}",
+            worktree.path.display(),
+            branch_note
         ))
     }
 
-    /// Kill agents running in a specific worktree
-    async fn kill_agents_in_worktree(&self, worktree_path: &std::path::Path, force: bool) -> Result<()> {
-        use crate::agent_monitor::{AgentMonitor, AgentMonitorConfig};
-        
-        let mut monitor = AgentMonitor::new(std::env::current_dir()?);
-        let config = AgentMonitorConfig {
-            only_our_agents: true,
-            only_waiting_agents: false,
-            agent_types: None,
-            worktree_paths: Some(vec![worktree_path.to_string_lossy().to_string()]),
+    /// Kill the agent spawned for the subagent in a worktree, if it is still running
+    ///
+    /// Only the process recorded in the registry is killed, never other programs that
+    /// happen to run in the worktree, such as the user's editor.
+    async fn kill_agents_in_worktree(&self, repo: &RepoContext, worktree_path: &std::path::Path, force: bool) -> Result<()> {
+        use crate::agent_monitor::AgentMonitor;
+
+        let record = repo.registry.lock().await.find_by_worktree(worktree_path).cloned();
+        let Some((id, pid)) = record.and_then(|record| record.agent_pid.map(|pid| (record.id, pid))) else {
+            info!("No agent process is recorded for worktree {}", worktree_path.display());
+            return Ok(());
         };
+        if !agent_monitor::is_process_running(pid) {
+            debug!("Agent of subagent {} (PID: {}) is no longer running", id, pid);
+            return Ok(());
+        }
 
-        let agents = monitor.get_running_agents(&config).await?;
-        
-        for agent in agents {
-            info!("Killing agent {} (PID: {}) in worktree {}", 
-                  agent.name, agent.pid, worktree_path.display());
-            
-            let success = monitor.kill_agent(agent.pid, force).await?;
-            if !success {
-                warn!("Failed to kill agent {} (PID: {})", agent.name, agent.pid);
-            }
+        info!("Killing agent of subagent {} (PID: {}) in worktree {}", id, pid, worktree_path.display());
+        let mut monitor = AgentMonitor::new(repo.git_manager.repo_path().to_path_buf());
+        if !monitor.kill_agent(pid, force).await? {
+            warn!("Failed to kill agent of subagent {} (PID: {})", id, pid);
         }
 
         Ok(())
     }
 
-    /// Remove a git branch
-    async fn remove_branch(&self, branch_name: &str) -> Result<()> {
-        use tokio::task;
-        use git2::{Repository, BranchType};
-        
-        let repo_path = std::env::current_dir()?;
-        let branch_name = branch_name.to_string(); // Convert to owned string
-        
-        task::spawn_blocking(move || {
-            let repo = Repository::open(&repo_path)
-                .context("Failed to open git repository")?;
-            
-            // Find the branch
-            let mut branch = repo.find_branch(&branch_name, BranchType::Local)
-                .context(format!("Branch '{}' not found", branch_name))?;
-            
-            // Delete the branch
-            branch.delete()
-                .context(format!("Failed to delete branch '{}'", branch_name))?;
-            
-            info!("Successfully deleted branch '{}'", branch_name);
-            Ok(())
-        }).await.context("Failed to spawn blocking task")?
-    }
-
-    /// List all worktrees and their status
-    async fn list_worktrees(&self) -> Result<String> {
-        info!("Listing all worktrees");
-        
+    /// List worktrees and their status, in one repository or all of them
+    async fn list_worktrees(&self, config: ListWorktreesConfig) -> Result<String> {
+        info!("Listing worktrees with config: {:?}", config);
+
+        let repos = match &config.repo {
+            Some(identifier) => vec![self.repo(Some(identifier)).await?],
+            None => self.repos.read().await.all(),
+        };
+
+        match repos.as_slice() {
+            [] => Ok("No repositories are managed by the server".to_string()),
+            [repo] => {
+                let worktrees = Self::describe_worktrees(repo).await?;
+                if worktrees.is_empty() {
+                    return Ok("No worktrees found".to_string());
+                }
+                Ok(format!("Worktrees:\n{}", worktrees))
+            }
+            _ => {
+                let mut result = String::new();
+                for repo in &repos {
+                    let worktrees = Self::describe_worktrees(repo).await?;
+                    result.push_str(&format!(
+                        "Repository '{}' at {}:\n{}",
+                        repo.name,
+                        repo.git_manager.repo_path().display(),
+                        if worktrees.is_empty() { "  No worktrees found\n\n" } else { &worktrees }
+                    ));
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    /// List the registered agent types with their availability, capabilities and options
+    async fn handle_list_agents(&self, config: ListAgentsConfig) -> Result<String> {
+        info!("Listing agents with config: {:?}", config);
+
+        let mut agents = self.spawner.list_available_agents().await?;
+        if config.only_available {
+            agents.retain(|agent| agent.available);
+        }
+
+        Ok(serde_json::to_string_pretty(&json!({ "agents": agents }))?)
+    }
+
+    /// Describe the worktrees of a repository, one entry per worktree
+    async fn describe_worktrees(repo: &RepoContext) -> Result<String> {
         // Validate that we're in a git repository
-        if !self.git_manager.is_git_repo() {
+        if !repo.git_manager.is_git_repo() {
             return Err(anyhow::anyhow!("Not in a git repository"));
         }
 
-        let worktrees = self.git_manager.list_worktrees().await?;
-        
-        if worktrees.is_empty() {
-            return Ok("No worktrees found".to_string());
-        }
+        let worktrees = repo.git_manager.list_worktrees().await?;
 
-        let mut result = String::from("Worktrees:\n");
+        let mut result = String::new();
         for worktree in worktrees {
             result.push_str(&format!(
-                "  - Path: {}\n    Branch: {}\n    Commit: {}\n\n",
+                "  - Path: {}\n    Branch: {}\n    Commit: {}\n",
                 worktree.path.display(),
                 worktree.branch.as_deref().unwrap_or("unknown"),
                 worktree.commit.as_deref().unwrap_or("unknown")
             ));
+            if let Some(sparse_paths) = &worktree.sparse_paths {
+                result.push_str(&format!("    Sparse: {}\n", sparse_paths.join(", ")));
+            }
+            if let Some(reason) = &worktree.locked {
+                result.push_str(&format!(
+                    "    Locked: {}{}\n",
+                    if reason.is_empty() { "yes" } else { reason },
+                    if worktree.has_stale_lock() { " (agent no longer running)" } else { "" }
+                ));
+            }
+            result.push('\n');
         }
 
         Ok(result)
     }
 
-    /// Get the list of available tools with their JSON schemas
+    /// Handle the add_repository tool call
+    async fn handle_add_repository(&self, config: AddRepositoryConfig) -> Result<String> {
+        info!("Adding repository with config: {:?}", config);
+
+        // A linked worktree is added as its main checkout, whose state it shares
+        let path = repositories::main_checkout_path(&config.path)?;
+        let mut repos = self.repos.write().await;
+        if let Some(existing) = repos.find_by_path(&path) {
+            return Ok(format!(
+                "Repository at {} is already managed as '{}'",
+                existing.git_manager.repo_path().display(),
+                existing.name
+            ));
+        }
+
+        // Options of the default agent are checked now rather than at the first spawn
+        if let Some(agent_options) = &config.agent_options {
+            let agent_type = config.agent_type.as_deref().unwrap_or("cursor-cli");
+            self.spawner.validate_options(agent_type, agent_options)?;
+        }
+
+        let agent_defaults = match (config.agent_type, config.agent_options) {
+            (None, None) => None,
+            (agent_type, agent_options) => Some(AgentDefaults { agent_type, agent_options }),
+        };
+        let entry = RepositoryEntry {
+            path,
+            name: config.name,
+            worktree_root: config.worktree_root,
+            agent_defaults,
+        };
+
+        let repo = repos.add(RepoContext::open(&entry)?)?;
+        repo.start_background_tasks();
+
+        Ok(format!(
+            "Added repository '{}' at {} (worktrees are created in {})",
+            repo.name,
+            repo.git_manager.repo_path().display(),
+            repo.git_manager.worktree_root()?.display()
+        ))
+    }
+
+    /// Handle the widen_sparse_checkout tool call
+    async fn handle_widen_sparse_checkout(&self, config: SparseCheckoutConfig) -> Result<String> {
+        info!("Widening sparse checkout with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
+
+        let worktree = repo.resolve_worktree(&config.worktree_name).await?;
+
+        if worktree.sparse_paths.is_none() {
+            return Err(anyhow::anyhow!(
+                "Worktree at {} is not a sparse checkout",
+                worktree.path.display()
+            ));
+        }
+
+        if config.disable {
+            repo.git_manager.disable_sparse_checkout(&worktree.path).await?;
+            return Ok(format!(
+                "Disabled sparse checkout for worktree at {}",
+                worktree.path.display()
+            ));
+        }
+
+        if config.paths.is_empty() {
+            return Err(anyhow::anyhow!("No paths given to add to the sparse set"));
+        }
+
+        repo.git_manager.add_sparse_paths(&worktree.path, &config.paths).await?;
+
+        Ok(format!(
+            "Added {} to the sparse set of worktree at {}",
+            config.paths.join(", "),
+            worktree.path.display()
+        ))
+    }
+
+    /// Handle the sync_worktree tool call
+    async fn handle_sync_worktree(&self, config: SyncConfig) -> Result<String> {
+        use crate::agent_monitor::AgentMonitor;
+
+        info!("Syncing worktree with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
+
+        let worktree = repo.resolve_worktree(&config.worktree_name).await?;
+        if worktree.branch.is_none() {
+            return Err(anyhow::anyhow!(
+                "Worktree at {} has a detached HEAD, there is no branch to sync",
+                worktree.path.display()
+            ));
+        }
+
+        // Rewriting files under a running agent would confuse it
+        if !config.force {
+            let mut monitor = AgentMonitor::new(repo.git_manager.repo_path().to_path_buf());
+            let agents = monitor.get_agents_in_directory(&worktree.path).await?;
+            if !agents.is_empty() {
+                let names: Vec<String> = agents.iter()
+                    .map(|agent| format!("{} (PID: {})", agent.name, agent.pid))
+                    .collect();
+                return Err(anyhow::anyhow!(
+                    "Agents are running in worktree {}: {}. Use force to sync anyway",
+                    worktree.path.display(),
+                    names.join(", ")
+                ));
+            }
+        }
+
+        let (recorded_branch, recorded_commit) = repo.registry.lock().await
+            .find_by_worktree(&worktree.path)
+            .map(|record| (record.base_ref.clone(), record.base_commit.clone()))
+            .unwrap_or_default();
+        let remote = config.remote.or_else(|| repo.config.sync.remote.clone());
+
+        // A base given as a tag or commit is only recorded as the commit it resolved to,
+        // which can be synced with locally but not fetched
+        let recorded_base = match &remote {
+            Some(_) => recorded_branch,
+            None => recorded_branch.or(recorded_commit),
+        };
+        let base_ref = config.base_ref.or(recorded_base).ok_or_else(|| anyhow::anyhow!(
+            "No base branch was recorded for worktree {}, specify base_ref",
+            worktree.path.display()
+        ))?;
+
+        let strategy = config.strategy.unwrap_or(repo.config.sync.strategy);
+
+        let (upstream, source) = match &remote {
+            Some(remote) => {
+                let commit = repo.git_manager.fetch_ref(&worktree.path, remote, &base_ref).await?;
+                (commit, format!("'{}' from {}", base_ref, remote))
+            }
+            None => {
+                let commit = repo.git_manager.resolve_commit(&worktree.path, &base_ref).await?;
+                (commit, format!("local '{}'", base_ref))
+            }
+        };
+
+        let outcome = repo.git_manager
+            .integrate_upstream(&worktree.path, &upstream, strategy, !config.keep_conflicts)
+            .await?;
+
+        Ok(match outcome {
+            SyncOutcome::UpToDate { head } => format!(
+                "Worktree at {} is up to date with {} (HEAD {})",
+                worktree.path.display(),
+                source,
+                short_commit(&head)
+            ),
+            SyncOutcome::Updated { old_head, new_head } => format!(
+                "Synced worktree at {} with {} using {} ({} -> {})",
+                worktree.path.display(),
+                source,
+                strategy,
+                short_commit(&old_head),
+                short_commit(&new_head)
+            ),
+            SyncOutcome::Conflicted { strategy, conflicted_files, in_progress } => {
+                let state = if in_progress {
+                    format!(
+                        "The {} is still in progress; resolve the conflicts and run `git {} --continue`, or `git {} --abort`",
+                        strategy, strategy, strategy
+                    )
+                } else {
+                    format!("The {} was aborted; the worktree is unchanged", strategy)
+                };
+                format!(
+                    "Sync of worktree at {} with {} stopped on conflicts in:\n{}\n{}",
+                    worktree.path.display(),
+                    source,
+                    conflicted_files.iter().map(|f| format!("  - {}", f)).collect::<Vec<_>>().join("\n"),
+                    state
+                )
+            }
+        })
+    }
+
+    /// Handle the checkpoint_worktree tool call
+    async fn handle_checkpoint_worktree(&self, config: CheckpointConfig) -> Result<String> {
+        info!("Checkpointing worktree with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
+        let worktree = repo.resolve_worktree(&config.worktree_name).await?;
+
+        let record = repo.registry.lock().await.find_by_worktree(&worktree.path).cloned();
+        let options = checkpoint_options(
+            &repo.config.checkpoint,
+            record.as_ref(),
+            worktree.branch.as_deref(),
+            config.message,
+            config.trailers,
+        );
+
+        let Some(checkpoint) = repo.git_manager.checkpoint_worktree(&worktree.path, &options).await? else {
+            return Ok(format!("Nothing to checkpoint in worktree at {}", worktree.path.display()));
+        };
+
+        if let Some(record) = &record {
+            repo.registry.lock().await.touch(&record.id)?;
+        }
+
+        Ok(format!(
+            "Committed checkpoint {} ({} files changed) on {} in worktree at {}",
+            short_commit(&checkpoint.commit),
+            checkpoint.files_changed,
+            worktree.branch.as_deref().map_or_else(|| "detached HEAD".to_string(), |b| format!("branch '{}'", b)),
+            worktree.path.display()
+        ))
+    }
+
+    /// Handle the run_in_worktree tool call
+    async fn handle_run_in_worktree(&self, config: RunInWorktreeConfig, context: &ToolCallContext) -> Result<String> {
+        info!("Running in worktree with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
+        let target = match (config.task, config.command) {
+            (Some(task), None) => RunTarget::Task(task),
+            (None, Some(command)) => RunTarget::Command(command),
+            _ => return Err(anyhow::anyhow!("Specify exactly one of task or command")),
+        };
+        let worktree = repo.resolve_worktree(&config.worktree_name).await?;
+
+        // Cancelling the call kills the command
+        let run = context.run("Running command", verification::run_in_worktree(
+            &repo.git_manager,
+            &worktree.path,
+            &repo.config.commands,
+            &target,
+            config.timeout_secs,
+        )).await?;
+
+        let record = repo.registry.lock().await.find_by_worktree(&worktree.path).cloned();
+        if let Some(record) = &record {
+            repo.registry.lock().await.record_run(&record.id, run.record.clone())?;
+        }
+
+        let status = if run.record.success { "✅ passed" } else { "❌ FAILED" };
+        let mut result = format!(
+            "{} `{}` in worktree at {} ({}, {}ms)\n",
+            status,
+            run.record.command,
+            worktree.path.display(),
+            run.output.status_description(),
+            run.output.duration_ms
+        );
+        if let Some(commit) = &run.record.commit {
+            let dirty = if run.record.dirty { " with uncommitted changes" } else { "" };
+            result.push_str(&format!("Commit: {}{}\n", short_commit(commit), dirty));
+        }
+        match &record {
+            Some(record) => result.push_str(&format!("Recorded as the latest run of subagent {}\n", record.id)),
+            None => result.push_str("The worktree does not belong to a registered subagent; the run was not recorded\n"),
+        }
+        for (name, stream) in [("stdout", &run.output.stdout), ("stderr", &run.output.stderr)] {
+            if !stream.trim().is_empty() {
+                result.push_str(&format!("\n--- {} ---\n{}\n", name, stream.trim_end()));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Handle the predict_conflicts tool call
+    async fn handle_predict_conflicts(&self, config: PredictConflictsConfig) -> Result<String> {
+        info!("Predicting conflicts with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
+        let options = ConflictOptions {
+            base_branch: config.base_branch,
+        };
+
+        let report = conflicts::predict_conflicts(&repo.git_manager, &repo.registry, &options).await?;
+        Ok(report.summary())
+    }
+
+    /// Handle the enqueue_merge tool call
+    async fn handle_enqueue_merge(&self, config: EnqueueMergeConfig) -> Result<String> {
+        info!("Enqueueing merge with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
+
+        let target = match config.target_branch.or_else(|| repo.config.merge_queue.target_branch.clone()) {
+            Some(target) => target,
+            None => repo.git_manager.list_worktrees().await?
+                .into_iter()
+                .next()
+                .and_then(|main| main.branch)
+                .ok_or_else(|| anyhow::anyhow!(
+                    "The main checkout has no branch checked out; pass target_branch or configure merge_queue.target_branch"
+                ))?,
+        };
+
+        if config.branch == target {
+            return Err(anyhow::anyhow!("Cannot merge branch '{}' into itself", target));
+        }
+        for branch in [&config.branch, &target] {
+            if !repo.git_manager.has_branch(branch).await? {
+                return Err(anyhow::anyhow!("Branch '{}' does not exist", branch));
+            }
+        }
+
+        let entry = repo.merge_queue.enqueue(&config.branch, &target).await?;
+        let pending = repo.merge_queue.pending().await;
+
+        let mut result = format!(
+            "Enqueued '{}' for merging into '{}' (entry {}, position {} in the queue)",
+            entry.branch, entry.target, entry.id, pending
+        );
+        match &repo.config.merge_queue.verify_command {
+            Some(command) => result.push_str(&format!("\nEach merge is verified with `{}`", command)),
+            None => result.push_str("\nNo verification command is configured; merges are only checked for conflicts"),
+        }
+        Ok(result)
+    }
+
+    /// Handle the list_merge_queue tool call
+    async fn handle_list_merge_queue(&self, config: ListMergeQueueConfig) -> Result<String> {
+        info!("Listing merge queue with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
+        let entries = repo.merge_queue.entries().await;
+        if entries.is_empty() {
+            return Ok("The merge queue is empty".to_string());
+        }
+
+        let mut result = format!("Merge queue ({} entries):\n", entries.len());
+        for entry in &entries {
+            result.push_str(&format!("\n📋 {} → {} [{}]\n", entry.branch, entry.target, entry.id));
+            let icon = match entry.status {
+                MergeStatus::Queued | MergeStatus::Running => "⏳",
+                MergeStatus::Merged { .. } => "✅",
+                MergeStatus::Ejected { .. } => "❌",
+            };
+            result.push_str(&format!("   {} {}\n", icon, entry.status));
+        }
+        Ok(result)
+    }
+
+    /// Handle the gc_worktrees tool call
+    async fn handle_gc_worktrees(&self, config: GcConfig) -> Result<String> {
+        info!("Garbage collecting worktrees with config: {:?}", config);
+
+        let repo = self.repo(config.repo.as_deref()).await?;
+
+        // Validate that we're in a git repository
+        if !repo.git_manager.is_git_repo() {
+            return Err(anyhow::anyhow!("Not in a git repository"));
+        }
+
+        let options = GcOptions {
+            policy: config.policy.unwrap_or(repo.config.gc.policy),
+            idle_ttl_secs: config.idle_ttl_secs.or(repo.config.gc.idle_ttl_secs),
+            base_branch: config.base_branch,
+        };
+
+        let report = gc::collect_garbage(&repo.git_manager, &repo.registry, &options).await?;
+        Ok(report.summary())
+    }
+
+    /// Tools of a server, with the agent choices filled in from its registered agents
+    ///
+    /// `agent_type` is restricted to the registered agents that are available on this
+    /// system, and `agent_options` accepts the options each of them declares.
+    pub async fn tools(&self) -> Vec<Tool> {
+        let agents = self.spawner.available_agents().await;
+        let names: Vec<&str> = agents.iter().map(|agent| agent.name()).collect();
+
+        let mut tools = Self::get_tools();
+        for tool in &mut tools {
+            let ToolInputSchema(schema) = &mut tool.input_schema;
+            let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) else {
+                continue;
+            };
+            if let Some(agent_type) = properties.get_mut("agent_type") {
+                let description = agent_type["description"].as_str().unwrap_or_default().to_string();
+                *agent_type = schema::string_enum(&description, &names);
+            }
+            if let Some(agent_options) = properties.get_mut("agent_options") {
+                let description = agent_options["description"].as_str().unwrap_or_default().to_string();
+                let options = agents.iter().map(|agent| (agent.name(), agent.options_schema())).collect();
+                *agent_options = schema::agent_options(&description, options);
+            }
+        }
+        tools
+    }
+
+    /// Get the list of tools with their JSON schemas
+    ///
+    /// The schemas do not depend on the server; see [`tools`](Self::tools) for the ones
+    /// advertised to clients.
     pub fn get_tools() -> Vec<Tool> {
         vec![
             Tool {
                 name: "spawn_subagent".to_string(),
                 description: Some("Spawn a new subagent with a git worktree for isolated development".to_string()),
-                input_schema: ToolInputSchema::JsonSchema(json!({
-                    "type": "object",
-                    "properties": {
-                        "branch_name": {
-                            "type": "string",
-                            "description": "Name of the branch to create for the subagent"
-                        },
-                        "prompt": {
-                            "type": "string", 
-                            "description": "Initial prompt to give to the new subagent"
-                        },
-                        "base_branch": {
-                            "type": "string",
-                            "description": "Base branch to create from (optional, defaults to current branch)"
-                        },
-                        "worktree_dir": {
-                            "type": "string",
-                            "description": "Custom worktree directory name (optional, defaults to branch_name)"
-                        },
-                        "agent_type": {
-                            "type": "string",
-                            "description": "Type of agent to spawn (optional, defaults to 'cursor-cli')",
-                            "enum": ["cursor-cli", "vscode", "vim", "neovim"]
-                        },
-                        "agent_options": {
-                            "type": "object",
-                            "description": "Agent-specific options",
-                            "properties": {
-                                "new_window": {
-                                    "type": "boolean",
-                                    "description": "Open in new window"
-                                },
-                                "wait": {
-                                    "type": "boolean", 
-                                    "description": "Wait for process completion"
-                                },
-                                "detach": {
-                                    "type": "boolean",
-                                    "description": "Detach process"
-                                },
-                                "custom_options": {
-                                    "type": "object",
-                                    "description": "Custom options as key-value pairs"
-                                }
-                            }
-                        }
-                    },
-                    "required": ["branch_name", "prompt"]
-                }))
+                input_schema: ToolInputSchema(schema::input_schema::<SubagentConfig>())
             },
             Tool {
                 name: "monitor_agents".to_string(),
                 description: Some("Monitor running agent processes and their status".to_string()),
-                input_schema: ToolInputSchema::JsonSchema(json!({
-                    "type": "object",
-                    "properties": {
-                        "only_our_agents": {
-                            "type": "boolean",
-                            "description": "Only show agents we spawned (optional)"
-                        },
-                        "only_waiting_agents": {
-                            "type": "boolean",
-                            "description": "Only show agents waiting for input (optional)"
-                        },
-                        "agent_types": {
-                            "type": "array",
-                            "items": {"type": "string"},
-                            "description": "Filter by agent types (optional)"
-                        },
-                        "worktree_paths": {
-                            "type": "array", 
-                            "items": {"type": "string"},
-                            "description": "Filter by worktree paths (optional)"
-                        }
-                    }
-                }))
+                input_schema: ToolInputSchema(schema::input_schema::<AgentMonitorConfig>())
             },
             Tool {
                 name: "cleanup_worktree".to_string(),
                 description: Some("⚠️ DESTRUCTIVE: Clean up a worktree and optionally kill running agents and remove the branch".to_string()),
-                input_schema: ToolInputSchema::JsonSchema(json!({
-                    "type": "object",
-                    "properties": {
-                        "worktree_name": {
-                            "type": "string",
-                            "description": "Name of the worktree/branch to clean up"
-                        },
-                        "force": {
-                            "type": "boolean",
-                            "description": "Force cleanup even if agents are still running (optional)"
-                        },
-                        "remove_branch": {
-                            "type": "boolean",
-                            "description": "Remove the git branch after cleanup (optional)"
-                        },
-                        "kill_agents": {
-                            "type": "boolean",
-                            "description": "Kill running agents before cleanup (optional)"
-                        }
-                    },
-                    "required": ["worktree_name"]
-                }))
+                input_schema: ToolInputSchema(schema::input_schema::<CleanupConfig>())
             },
             Tool {
                 name: "list_worktrees".to_string(),
-                description: Some("List all worktrees and their current status".to_string()),
-                input_schema: ToolInputSchema::JsonSchema(json!({
-                    "type": "object",
-                    "properties": {},
-                    "description": "No parameters required"
-                }))
+                description: Some("List all worktrees and their current status, across all managed repositories".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<ListWorktreesConfig>())
+            },
+            Tool {
+                name: "list_agents".to_string(),
+                description: Some("List the agent types that can be spawned, with their availability, version, capabilities and options schema".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<ListAgentsConfig>())
+            },
+            Tool {
+                name: "widen_sparse_checkout".to_string(),
+                description: Some("Add paths to the sparse-checkout set of a worktree, or disable sparse checkout".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<SparseCheckoutConfig>())
+            },
+            Tool {
+                name: "sync_worktree".to_string(),
+                description: Some("Fetch the base branch and rebase or merge it into a subagent worktree, stopping cleanly on conflicts".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<SyncConfig>())
+            },
+            Tool {
+                name: "checkpoint_worktree".to_string(),
+                description: Some("Stage all changes in a worktree and commit them, so the work survives cleanup".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<CheckpointConfig>())
+            },
+            Tool {
+                name: "run_in_worktree".to_string(),
+                description: Some("Run a configured task or allow-listed command, such as `cargo test`, in a worktree and record the result against its subagent".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<RunInWorktreeConfig>())
+            },
+            Tool {
+                name: "predict_conflicts".to_string(),
+                description: Some("Compare the changes of all active subagents, including uncommitted work, pairwise and against their base, and report overlapping files and merge conflicts".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<PredictConflictsConfig>())
+            },
+            Tool {
+                name: "enqueue_merge".to_string(),
+                description: Some("Add a branch to the merge queue, which merges queued branches into the target branch one at a time in an integration worktree, runs the configured verification command, and ejects branches that conflict or fail verification".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<EnqueueMergeConfig>())
+            },
+            Tool {
+                name: "list_merge_queue".to_string(),
+                description: Some("Show the branches in the merge queue and whether they are queued, merged, or ejected".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<ListMergeQueueConfig>())
+            },
+            Tool {
+                name: "gc_worktrees".to_string(),
+                description: Some("⚠️ DESTRUCTIVE: Prune stale worktrees and collect subagent worktrees that are merged, idle past the TTL, or unregistered".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<GcConfig>())
+            },
+            Tool {
+                name: "add_repository".to_string(),
+                description: Some("Add a git repository to the server, so tools can operate on it with the repo parameter".to_string()),
+                input_schema: ToolInputSchema(schema::input_schema::<AddRepositoryConfig>())
             }
         ]
     }
@@ -341,15 +1411,110 @@ impl SubagentWorktreeServer {
 impl RequestHandler for SubagentWorktreeServer {
     async fn list_tools(&self, _request: ListToolsRequest) -> Result<ListToolsResult> {
         Ok(ListToolsResult {
-            tools: Self::get_tools(),
+            tools: self.tools().await,
+        })
+    }
+
+    async fn list_resources(&self, _request: ListResourcesRequest) -> Result<ListResourcesResult> {
+        let entries = resources::list_resources(&*self.repos.read().await).await?;
+        Ok(ListResourcesResult {
+            resources: entries.into_iter()
+                .map(|entry| Resource {
+                    uri: entry.uri,
+                    name: entry.name,
+                    description: Some(entry.description),
+                    mime_type: Some(entry.mime_type),
+                })
+                .collect(),
+        })
+    }
+
+    async fn read_resource(&self, request: ReadResourceRequest) -> Result<ReadResourceResult> {
+        let content = resources::read_resource(&*self.repos.read().await, &request.uri).await?;
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::Text(TextResourceContents {
+                uri: content.uri,
+                mime_type: Some(content.mime_type),
+                text: content.text,
+            })],
+        })
+    }
+
+    async fn subscribe(&self, request: SubscribeRequest) -> Result<()> {
+        self.resources.subscribe(&request.uri).await
+    }
+
+    async fn unsubscribe(&self, request: UnsubscribeRequest) -> Result<()> {
+        self.resources.unsubscribe(&request.uri).await;
+        Ok(())
+    }
+
+    async fn list_prompts(&self, _request: ListPromptsRequest) -> Result<ListPromptsResult> {
+        let mut templates = prompts::builtin_templates()?;
+        for repo in self.repos.read().await.all() {
+            match prompts::repo_templates(&repo) {
+                Ok(repo_templates) => {
+                    for template in repo_templates {
+                        if !templates.iter().any(|t| t.name == template.name) {
+                            templates.push(template);
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to load prompt templates of repository '{}': {:#}", repo.name, e),
+            }
+        }
+
+        Ok(ListPromptsResult {
+            prompts: templates.into_iter()
+                .map(|template| Prompt {
+                    name: template.name,
+                    description: Some(template.description),
+                    arguments: Some(template.arguments.into_iter()
+                        .map(|argument| PromptArgument {
+                            name: argument.name,
+                            description: Some(argument.description),
+                            required: Some(argument.required),
+                        })
+                        .collect()),
+                })
+                .collect(),
+        })
+    }
+
+    async fn get_prompt(&self, request: GetPromptRequest) -> Result<GetPromptResult> {
+        let arguments = request.arguments.unwrap_or_default();
+        let repo = self.repo(arguments.get("repo").map(String::as_str)).await?;
+        let template = prompts::repo_templates(&repo)?
+            .into_iter()
+            .find(|t| t.name == request.name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown prompt '{}' in repository '{}'", request.name, repo.name))?;
+
+        let text = prompts::render_prompt(&repo, &template, &arguments).await?;
+        Ok(GetPromptResult {
+            description: Some(template.description),
+            messages: vec![PromptMessage {
+                role: Role::User,
+                content: TextContent {
+                    text,
+                    r#type: "text".to_string(),
+                },
+            }],
         })
     }
 
     async fn call_tool(&self, request: CallToolRequest) -> Result<CallToolResult> {
+        // The stdio server reports no progress and does not forward cancellations
+        self.call_tool_with_context(request, ToolCallContext::default()).await
+    }
+}
+
+impl ToolCallHandler for SubagentWorktreeServer {
+    async fn call_tool_with_context(&self, request: CallToolRequest, context: ToolCallContext) -> Result<CallToolResult> {
+        let request = self.scope_to_roots(request, &context).await?;
         match request.name.as_str() {
             "spawn_subagent" => {
                 let config: SubagentConfig = serde_json::from_value(request.arguments)?;
-                let result = self.handle_spawn_subagent(config).await?;
+                let result = self.handle_spawn_subagent(config, &context).await?;
                 Ok(CallToolResult {
                     content: vec![TextContent {
                         text: result,
@@ -380,7 +1545,133 @@ impl RequestHandler for SubagentWorktreeServer {
                 })
             }
             "list_worktrees" => {
-                let result = self.list_worktrees().await?;
+                // The tool takes no required arguments, so clients may send none at all
+                let config: ListWorktreesConfig = if request.arguments.is_null() {
+                    ListWorktreesConfig::default()
+                } else {
+                    serde_json::from_value(request.arguments)?
+                };
+                let result = self.list_worktrees(config).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "list_agents" => {
+                // The tool takes no required arguments, so clients may send none at all
+                let config: ListAgentsConfig = if request.arguments.is_null() {
+                    ListAgentsConfig::default()
+                } else {
+                    serde_json::from_value(request.arguments)?
+                };
+                let result = self.handle_list_agents(config).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "widen_sparse_checkout" => {
+                let config: SparseCheckoutConfig = serde_json::from_value(request.arguments)?;
+                let result = self.handle_widen_sparse_checkout(config).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "sync_worktree" => {
+                let config: SyncConfig = serde_json::from_value(request.arguments)?;
+                let result = self.handle_sync_worktree(config).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "checkpoint_worktree" => {
+                let config: CheckpointConfig = serde_json::from_value(request.arguments)?;
+                let result = self.handle_checkpoint_worktree(config).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "run_in_worktree" => {
+                let config: RunInWorktreeConfig = serde_json::from_value(request.arguments)?;
+                let result = self.handle_run_in_worktree(config, &context).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "predict_conflicts" => {
+                let config: PredictConflictsConfig = serde_json::from_value(request.arguments)?;
+                let result = self.handle_predict_conflicts(config).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "enqueue_merge" => {
+                let config: EnqueueMergeConfig = serde_json::from_value(request.arguments)?;
+                let result = self.handle_enqueue_merge(config).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "list_merge_queue" => {
+                // The tool takes no required arguments, so clients may send none at all
+                let config: ListMergeQueueConfig = if request.arguments.is_null() {
+                    ListMergeQueueConfig::default()
+                } else {
+                    serde_json::from_value(request.arguments)?
+                };
+                let result = self.handle_list_merge_queue(config).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "gc_worktrees" => {
+                let config: GcConfig = serde_json::from_value(request.arguments)?;
+                let result = self.handle_gc_worktrees(config).await?;
+                Ok(CallToolResult {
+                    content: vec![TextContent {
+                        text: result,
+                        r#type: "text".to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "add_repository" => {
+                let config: AddRepositoryConfig = serde_json::from_value(request.arguments)?;
+                let result = self.handle_add_repository(config).await?;
                 Ok(CallToolResult {
                     content: vec![TextContent {
                         text: result,
@@ -394,31 +1685,183 @@ impl RequestHandler for SubagentWorktreeServer {
     }
 }
 
+/// Build the options for a checkpoint commit, applying the identity configured for the agent type
+fn checkpoint_options(
+    settings: &CheckpointSettings,
+    record: Option<&SubagentRecord>,
+    branch: Option<&str>,
+    message: Option<String>,
+    trailers: IndexMap<String, String>,
+) -> CheckpointOptions {
+    let message = message.unwrap_or_else(|| match record {
+        Some(record) => format!("Checkpoint of subagent {}: {}", record.id, prompt_summary(&record.prompt)),
+        None => format!("Checkpoint of {}", branch.unwrap_or("detached HEAD")),
+    });
+
+    let identity = record
+        .and_then(|record| settings.identities.get(&record.agent_type))
+        .cloned()
+        .unwrap_or_default();
+
+    let mut all_trailers = Vec::new();
+    if let (true, Some(record)) = (settings.subagent_trailer, record) {
+        all_trailers.push(("Subagent-Id".to_string(), record.id.clone()));
+    }
+    all_trailers.extend(trailers);
+
+    CheckpointOptions {
+        message,
+        author: identity.author,
+        committer: identity.committer,
+        trailers: all_trailers,
+    }
+}
+
+/// First line of a prompt, shortened for a commit subject
+fn prompt_summary(prompt: &str) -> String {
+    const MAX_CHARS: usize = 50;
+    let line = prompt.lines().next().unwrap_or("").trim();
+    if line.chars().count() <= MAX_CHARS {
+        return line.to_string();
+    }
+    format!("{}...", line.chars().take(MAX_CHARS).collect::<String>().trim_end())
+}
+
+/// Summarize submodule and LFS setup of a new worktree, one line per step
+fn checkout_summary(created: &CreatedWorktree) -> String {
+    let mut summary = String::new();
+    for submodule in &created.submodules {
+        let status = match (&submodule.error, &submodule.reference) {
+            (Some(error), _) => format!("FAILED ({})", error),
+            (None, Some(reference)) => format!("ok (objects shared with {})", reference.display()),
+            (None, None) => "ok".to_string(),
+        };
+        summary.push_str(&format!("  - submodule {}: {}\n", submodule.path, status));
+    }
+    match &created.lfs {
+        Some(LfsOutcome::CheckedOut) => summary.push_str("  - lfs checkout: ok\n"),
+        Some(LfsOutcome::Failed { error }) => summary.push_str(&format!("  - lfs checkout: FAILED ({})\n", error)),
+        None => {}
+    }
+    summary
+}
+
+/// Abbreviate a commit id for display
+fn short_commit(commit: &str) -> &str {
+    commit.get(..8).unwrap_or(commit)
+}
+
+/// Transport the MCP server is served over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Transport {
+    /// Serve a single client over standard input and output
+    Stdio,
+    /// Serve any number of clients over streamable HTTP
+    Http,
+}
+
+/// MCP server for spawning subagents in git worktrees
+#[derive(Debug, clap::Parser)]
+#[command(version)]
+struct Cli {
+    /// Transport to serve MCP over
+    #[arg(long, value_enum, default_value = "stdio")]
+    transport: Transport,
+
+    /// Address to listen on with the HTTP transport
+    #[arg(long, default_value = "127.0.0.1:8765")]
+    listen: std::net::SocketAddr,
+
+    /// Bearer token clients must send with the HTTP transport; defaults to $SUBAGENT_WORKTREE_MCP_TOKEN
+    #[arg(long)]
+    token: Option<String>,
+
+    /// Run as the daemon that owns subagents, serving clients on a Unix socket in the state directory
+    #[arg(long, conflicts_with_all = ["transport", "in_process"])]
+    daemon: bool,
+
+    /// Serve stdio from this process instead of attaching to the daemon; subagents stop supervision on exit
+    #[arg(long)]
+    in_process: bool,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
+    use clap::Parser;
+
+    let cli = Cli::parse();
+
+    // Initialize tracing; logs go to stderr so they never mix with stdio messages
     tracing_subscriber::fmt()
         .with_env_filter("subagent_worktree_mcp=info")
+        .with_writer(std::io::stderr)
         .init();
 
     // Get the current working directory as the repository path
     let repo_path = std::env::current_dir()?;
+    #[cfg(unix)]
+    let socket_path = GitWorktreeManager::new(repo_path.clone())
+        .and_then(|manager| manager.state_dir())
+        .map(|state_dir| daemon::socket_path(&state_dir));
+
+    // By default stdio clients attach to the daemon, so subagents outlive the client
+    if cli.transport == Transport::Stdio && !cli.daemon && !cli.in_process {
+        #[cfg(unix)]
+        match &socket_path {
+            Ok(socket_path) => {
+                let stream = daemon::connect_or_start(socket_path, || daemon::start(&repo_path, socket_path)).await?;
+                return daemon::proxy_stdio(stream).await;
+            }
+            Err(e) => warn!("Serving in-process, as the daemon needs a git repository: {:#}", e),
+        }
+        #[cfg(not(unix))]
+        info!("Serving in-process, as the daemon needs Unix sockets");
+    }
+
     info!("Starting MCP server for repository: {}", repo_path.display());
 
     // Create the server
     let server = SubagentWorktreeServer::new(repo_path)?;
+    server.start_background_tasks().await;
+
+    let resource_notifier = server.resource_notifier();
 
-    // Start the MCP server
-    let mut mcp_server = McpServer::new(server);
-    
     info!("MCP server started with tools:");
     let tools = SubagentWorktreeServer::get_tools();
     for tool in &tools {
         info!("  - {}: {}", tool.name, tool.description.as_deref().unwrap_or("No description"));
     }
 
-    // Run the MCP server
-    mcp_server.run().await?;
+    #[cfg(unix)]
+    if cli.daemon {
+        let socket_path = socket_path.map_err(|e| e.context("The daemon needs a git repository for its state"))?;
+        let listener = daemon::bind(&socket_path).await?;
+        info!("Daemon listening on {}", socket_path.display());
+        let result = daemon::serve(listener, Arc::new(server), resource_notifier).await;
+        let _ = std::fs::remove_file(&socket_path);
+        return result;
+    }
+    #[cfg(not(unix))]
+    if cli.daemon {
+        return Err(anyhow::anyhow!("The daemon needs Unix sockets; use --in-process or --transport http"));
+    }
+
+    if cli.transport == Transport::Http {
+        let token = cli.token
+            .or_else(|| std::env::var(http_transport::TOKEN_ENV_VAR).ok())
+            .filter(|token| !token.is_empty());
+        if token.is_none() && !http_transport::is_loopback(&cli.listen) {
+            return Err(anyhow::anyhow!(
+                "Refusing to listen on {} without a token; pass --token or set {}",
+                cli.listen,
+                http_transport::TOKEN_ENV_VAR
+            ));
+        }
+        let listener = tokio::net::TcpListener::bind(cli.listen).await
+            .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", cli.listen, e))?;
+        return http_transport::serve(listener, Arc::new(server), resource_notifier, token).await;
+    }
 
-    Ok(())
+    // Serve the client on stdio; roots, progress and cancellation work as with the daemon
+    stream_transport::serve_client(tokio::io::stdin(), tokio::io::stdout(), Arc::new(server), resource_notifier).await
 }
@@ -0,0 +1,196 @@
+/// Requests and results of the Model Context Protocol methods the server implements
+///
+/// Only the fields the server uses are modelled; the transports translate them to and
+/// from their JSON-RPC form.
+pub mod types {
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    /// Parameters of `tools/list`
+    #[derive(Debug, Clone, Default)]
+    pub struct ListToolsRequest;
+
+    /// Result of `tools/list`
+    #[derive(Debug, Clone)]
+    pub struct ListToolsResult {
+        pub tools: Vec<Tool>,
+    }
+
+    /// A tool the client may call
+    #[derive(Debug, Clone)]
+    pub struct Tool {
+        pub name: String,
+        pub description: Option<String>,
+        pub input_schema: ToolInputSchema,
+    }
+
+    /// JSON schema of the arguments of a tool
+    #[derive(Debug, Clone)]
+    pub struct ToolInputSchema(pub Value);
+
+    /// Parameters of `tools/call`
+    #[derive(Debug, Clone)]
+    pub struct CallToolRequest {
+        pub name: String,
+        pub arguments: Value,
+    }
+
+    /// Result of `tools/call`; a failed tool reports `is_error` rather than a protocol error
+    #[derive(Debug, Clone)]
+    pub struct CallToolResult {
+        pub content: Vec<TextContent>,
+        pub is_error: bool,
+    }
+
+    /// Text content of a tool result or prompt message
+    #[derive(Debug, Clone)]
+    pub struct TextContent {
+        pub text: String,
+        /// Content type, always `text`
+        pub r#type: String,
+    }
+
+    /// Parameters of `resources/list`
+    #[derive(Debug, Clone, Default)]
+    pub struct ListResourcesRequest;
+
+    /// Result of `resources/list`
+    #[derive(Debug, Clone)]
+    pub struct ListResourcesResult {
+        pub resources: Vec<Resource>,
+    }
+
+    /// A resource the client may read or subscribe to
+    #[derive(Debug, Clone)]
+    pub struct Resource {
+        pub uri: String,
+        pub name: String,
+        pub description: Option<String>,
+        pub mime_type: Option<String>,
+    }
+
+    /// Parameters of `resources/read`
+    #[derive(Debug, Clone)]
+    pub struct ReadResourceRequest {
+        pub uri: String,
+    }
+
+    /// Result of `resources/read`
+    #[derive(Debug, Clone)]
+    pub struct ReadResourceResult {
+        pub contents: Vec<ResourceContents>,
+    }
+
+    /// Contents of a resource
+    #[derive(Debug, Clone)]
+    pub enum ResourceContents {
+        Text(TextResourceContents),
+    }
+
+    /// Text contents of a resource
+    #[derive(Debug, Clone)]
+    pub struct TextResourceContents {
+        pub uri: String,
+        pub mime_type: Option<String>,
+        pub text: String,
+    }
+
+    /// Parameters of `resources/subscribe`
+    #[derive(Debug, Clone)]
+    pub struct SubscribeRequest {
+        pub uri: String,
+    }
+
+    /// Parameters of `resources/unsubscribe`
+    #[derive(Debug, Clone)]
+    pub struct UnsubscribeRequest {
+        pub uri: String,
+    }
+
+    /// Parameters of `prompts/list`
+    #[derive(Debug, Clone, Default)]
+    pub struct ListPromptsRequest;
+
+    /// Result of `prompts/list`
+    #[derive(Debug, Clone)]
+    pub struct ListPromptsResult {
+        pub prompts: Vec<Prompt>,
+    }
+
+    /// A prompt template the client may get
+    #[derive(Debug, Clone)]
+    pub struct Prompt {
+        pub name: String,
+        pub description: Option<String>,
+        pub arguments: Option<Vec<PromptArgument>>,
+    }
+
+    /// An argument of a prompt template
+    #[derive(Debug, Clone)]
+    pub struct PromptArgument {
+        pub name: String,
+        pub description: Option<String>,
+        pub required: Option<bool>,
+    }
+
+    /// Parameters of `prompts/get`
+    #[derive(Debug, Clone)]
+    pub struct GetPromptRequest {
+        pub name: String,
+        pub arguments: Option<HashMap<String, String>>,
+    }
+
+    /// Result of `prompts/get`
+    #[derive(Debug, Clone)]
+    pub struct GetPromptResult {
+        pub description: Option<String>,
+        pub messages: Vec<PromptMessage>,
+    }
+
+    /// A message of a rendered prompt
+    #[derive(Debug, Clone)]
+    pub struct PromptMessage {
+        pub role: Role,
+        pub content: TextContent,
+    }
+
+    /// Who a prompt message is from
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Role {
+        User,
+        Assistant,
+    }
+}
+
+/// The methods an MCP server answers
+pub mod server {
+    use super::types::{
+        CallToolRequest, CallToolResult, GetPromptRequest, GetPromptResult, ListPromptsRequest, ListPromptsResult,
+        ListResourcesRequest, ListResourcesResult, ListToolsRequest, ListToolsResult, ReadResourceRequest,
+        ReadResourceResult, SubscribeRequest, UnsubscribeRequest,
+    };
+    use anyhow::Result;
+    use std::future::Future;
+
+    /// Handler of MCP requests, shared by every connection of a transport
+    pub trait RequestHandler: Send + Sync + 'static {
+        fn list_tools(&self, request: ListToolsRequest) -> impl Future<Output = Result<ListToolsResult>> + Send;
+
+        fn call_tool(&self, request: CallToolRequest) -> impl Future<Output = Result<CallToolResult>> + Send;
+
+        fn list_resources(
+            &self,
+            request: ListResourcesRequest,
+        ) -> impl Future<Output = Result<ListResourcesResult>> + Send;
+
+        fn read_resource(&self, request: ReadResourceRequest) -> impl Future<Output = Result<ReadResourceResult>> + Send;
+
+        fn subscribe(&self, request: SubscribeRequest) -> impl Future<Output = Result<()>> + Send;
+
+        fn unsubscribe(&self, request: UnsubscribeRequest) -> impl Future<Output = Result<()>> + Send;
+
+        fn list_prompts(&self, request: ListPromptsRequest) -> impl Future<Output = Result<ListPromptsResult>> + Send;
+
+        fn get_prompt(&self, request: GetPromptRequest) -> impl Future<Output = Result<GetPromptResult>> + Send;
+    }
+}
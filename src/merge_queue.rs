@@ -0,0 +1,337 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+use tracing::{debug, info, warn};
+
+use crate::command_runner::{self, DEFAULT_COMMAND_TIMEOUT_SECS};
+use crate::config::MergeQueueSettings;
+use crate::git_operations::{GitWorktreeManager, SyncOutcome, SyncStrategy};
+use crate::registry::{generate_id, unix_now};
+
+/// File name of the queue inside the state directory
+const QUEUE_FILE: &str = "merge-queue.json";
+
+/// File name of the lock held while the queue is rewritten
+const QUEUE_LOCK_FILE: &str = "merge-queue.json.lock";
+
+/// Directory inside the state directory where logs of ejected branches are kept
+const LOG_DIR: &str = "merge-queue-logs";
+
+/// State of a queued branch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum MergeStatus {
+    /// Waiting to be integrated
+    Queued,
+    /// Being merged and verified
+    Running,
+    /// Merged into the target branch
+    Merged { commit: String },
+    /// Removed from the queue without being merged
+    Ejected {
+        reason: String,
+        /// Output of the failed verification, if it ran
+        log_path: Option<PathBuf>,
+    },
+}
+
+impl fmt::Display for MergeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeStatus::Queued => write!(f, "queued"),
+            MergeStatus::Running => write!(f, "running"),
+            MergeStatus::Merged { commit } => write!(f, "merged as {}", commit.get(..8).unwrap_or(commit)),
+            MergeStatus::Ejected { reason, log_path: Some(log_path) } => {
+                write!(f, "ejected: {} (log: {})", reason, log_path.display())
+            }
+            MergeStatus::Ejected { reason, log_path: None } => write!(f, "ejected: {}", reason),
+        }
+    }
+}
+
+/// A branch in the merge queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeQueueEntry {
+    /// Unique identifier of the entry
+    pub id: String,
+    /// Branch to merge
+    pub branch: String,
+    /// Branch to merge into
+    pub target: String,
+    /// Current state
+    #[serde(flatten)]
+    pub status: MergeStatus,
+    /// Unix timestamp (seconds) when the branch was enqueued
+    pub enqueued_at: u64,
+    /// Unix timestamp (seconds) when the branch was merged or ejected
+    pub finished_at: Option<u64>,
+}
+
+/// Persistent queue of branches to integrate one at a time, stored as JSON in the server state directory
+///
+/// Branches are merged in a dedicated integration worktree, verified there, and the
+/// target branch is only fast-forwarded to the result if verification passes.
+/// Like the subagent registry, every change is made to the entries on disk under a lock,
+/// so servers sharing the state directory keep each other's entries.
+#[derive(Debug)]
+pub struct MergeQueue {
+    /// Path of the queue file
+    path: PathBuf,
+    /// Directory for logs of failed verifications
+    log_dir: PathBuf,
+    /// Entries in the order they were enqueued, as last read or written
+    entries: Mutex<Vec<MergeQueueEntry>>,
+    /// Wakes the worker when a branch is enqueued
+    wake: Notify,
+}
+
+impl MergeQueue {
+    /// Load the queue from the given state directory, creating an empty one if missing
+    ///
+    /// Entries that were running when the server stopped are queued again.
+    pub fn load(state_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(state_dir)
+            .context(format!("Failed to create state directory {}", state_dir.display()))?;
+
+        let mut queue = Self {
+            path: state_dir.join(QUEUE_FILE),
+            log_dir: state_dir.join(LOG_DIR),
+            entries: Mutex::new(Vec::new()),
+            wake: Notify::new(),
+        };
+        let mut entries = Vec::new();
+        queue.update_locked(&mut entries, |entries| {
+            for entry in entries.iter_mut() {
+                if entry.status == MergeStatus::Running {
+                    info!("Requeueing interrupted merge of '{}'", entry.branch);
+                    entry.status = MergeStatus::Queued;
+                }
+            }
+        })?;
+
+        debug!("Loaded {} merge queue entries from {}", entries.len(), queue.path.display());
+        queue.entries = Mutex::new(entries);
+        Ok(queue)
+    }
+
+    /// Apply a change to the latest entries on disk and write them back
+    ///
+    /// The lock keeps other processes from writing in between, so their changes are kept.
+    async fn update<T>(&self, change: impl FnOnce(&mut Vec<MergeQueueEntry>) -> T) -> Result<T> {
+        let mut entries = self.entries.lock().await;
+        self.update_locked(&mut entries, change)
+    }
+
+    /// Apply a change to the entries on disk under the file lock, refreshing `entries`
+    fn update_locked<T>(
+        &self,
+        entries: &mut Vec<MergeQueueEntry>,
+        change: impl FnOnce(&mut Vec<MergeQueueEntry>) -> T,
+    ) -> Result<T> {
+        let lock_path = self.path.with_file_name(QUEUE_LOCK_FILE);
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .context(format!("Failed to open {}", lock_path.display()))?;
+        lock.lock().context(format!("Failed to lock {}", lock_path.display()))?;
+
+        *entries = read_entries(&self.path)?;
+        let result = change(entries);
+        self.save(entries)?;
+        Ok(result)
+    }
+
+    /// Write the queue back to disk
+    fn save(&self, entries: &[MergeQueueEntry]) -> Result<()> {
+        let content = serde_json::to_string_pretty(entries)
+            .context("Failed to serialize merge queue")?;
+
+        // Write to a temporary file first so a crash never leaves a truncated queue
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)
+            .context(format!("Failed to write merge queue {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .context(format!("Failed to replace merge queue {}", self.path.display()))?;
+
+        Ok(())
+    }
+
+    /// Add a branch to the end of the queue and wake the worker
+    pub async fn enqueue(&self, branch: &str, target: &str) -> Result<MergeQueueEntry> {
+        let entry = self.update(|entries| {
+            let pending = entries.iter()
+                .any(|e| e.branch == branch && matches!(e.status, MergeStatus::Queued | MergeStatus::Running));
+            if pending {
+                return Err(anyhow::anyhow!("Branch '{}' is already in the merge queue", branch));
+            }
+
+            let entry = MergeQueueEntry {
+                id: generate_id(),
+                branch: branch.to_string(),
+                target: target.to_string(),
+                status: MergeStatus::Queued,
+                enqueued_at: unix_now(),
+                finished_at: None,
+            };
+            entries.push(entry.clone());
+            Ok(entry)
+        }).await??;
+
+        info!("Enqueued '{}' for merging into '{}'", branch, target);
+        self.wake.notify_one();
+        Ok(entry)
+    }
+
+    /// All entries, in the order they were enqueued
+    pub async fn entries(&self) -> Vec<MergeQueueEntry> {
+        self.entries.lock().await.clone()
+    }
+
+    /// Number of entries waiting or running
+    pub async fn pending(&self) -> usize {
+        self.entries.lock().await.iter()
+            .filter(|e| matches!(e.status, MergeStatus::Queued | MergeStatus::Running))
+            .count()
+    }
+
+    /// Update the status of an entry and persist the queue, returning the updated entry
+    async fn set_status(&self, id: &str, status: MergeStatus) -> Result<Option<MergeQueueEntry>> {
+        self.update(|entries| {
+            entries.iter_mut().find(|e| e.id == id).map(|entry| {
+                if !matches!(status, MergeStatus::Queued | MergeStatus::Running) {
+                    entry.finished_at = Some(unix_now());
+                }
+                entry.status = status;
+                entry.clone()
+            })
+        }).await
+    }
+
+    /// Integrate the next queued branch, returning its final entry, or `None` if the queue is empty
+    pub async fn process_next(
+        &self,
+        git_manager: &GitWorktreeManager,
+        settings: &MergeQueueSettings,
+    ) -> Result<Option<MergeQueueEntry>> {
+        // Claim the entry under the lock, so another server never integrates it as well
+        let next = self.update(|entries| {
+            let entry = entries.iter_mut().find(|e| e.status == MergeStatus::Queued)?;
+            entry.status = MergeStatus::Running;
+            Some(entry.clone())
+        }).await?;
+        let Some(entry) = next else {
+            return Ok(None);
+        };
+
+        info!("Merging '{}' into '{}'", entry.branch, entry.target);
+
+        let status = match self.integrate(git_manager, settings, &entry).await {
+            Ok(status) => status,
+            Err(e) => MergeStatus::Ejected {
+                reason: format!("{:#}", e),
+                log_path: None,
+            },
+        };
+        match &status {
+            MergeStatus::Merged { commit } => info!("Merged '{}' into '{}' as {}", entry.branch, entry.target, commit),
+            _ => warn!("Ejected '{}' from the merge queue: {}", entry.branch, status),
+        }
+
+        self.set_status(&entry.id, status).await
+    }
+
+    /// Merge, verify and fast-forward one branch
+    async fn integrate(
+        &self,
+        git_manager: &GitWorktreeManager,
+        settings: &MergeQueueSettings,
+        entry: &MergeQueueEntry,
+    ) -> Result<MergeStatus> {
+        let worktree_path = integration_worktree_path(git_manager, settings)?;
+        let target_commit = git_manager.prepare_integration_worktree(&worktree_path, &entry.target).await?;
+
+        let merged = match git_manager.integrate_upstream(&worktree_path, &entry.branch, SyncStrategy::Merge, true).await? {
+            SyncOutcome::UpToDate { head } => return Ok(MergeStatus::Merged { commit: head }),
+            SyncOutcome::Updated { new_head, .. } => new_head,
+            SyncOutcome::Conflicted { conflicted_files, .. } => {
+                return Ok(MergeStatus::Ejected {
+                    reason: format!("merge conflicts in {}", conflicted_files.join(", ")),
+                    log_path: None,
+                });
+            }
+        };
+
+        if let Some(command) = &settings.verify_command {
+            let timeout = Duration::from_secs(settings.verify_timeout_secs.unwrap_or(DEFAULT_COMMAND_TIMEOUT_SECS));
+            let output = command_runner::run_shell_command(command, &worktree_path, timeout).await?;
+            if !output.success() {
+                let log_path = self.write_log(&entry.id, &output.stdout, &output.stderr)?;
+                return Ok(MergeStatus::Ejected {
+                    reason: format!("verification `{}` {}", command, output.status_description()),
+                    log_path: Some(log_path),
+                });
+            }
+        }
+
+        git_manager.fast_forward_branch(&entry.target, &merged, &target_commit).await?;
+        Ok(MergeStatus::Merged { commit: merged })
+    }
+
+    /// Keep the output of a failed verification
+    fn write_log(&self, id: &str, stdout: &str, stderr: &str) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.log_dir)
+            .context(format!("Failed to create directory {}", self.log_dir.display()))?;
+        let log_path = self.log_dir.join(format!("{}.log", id));
+        std::fs::write(&log_path, format!("=== stdout ===\n{}\n=== stderr ===\n{}\n", stdout, stderr))
+            .context(format!("Failed to write log {}", log_path.display()))?;
+        Ok(log_path)
+    }
+
+    /// Start the worker that integrates queued branches one at a time
+    pub fn spawn_worker(self: Arc<Self>, git_manager: GitWorktreeManager, settings: MergeQueueSettings) {
+        tokio::spawn(async move {
+            loop {
+                match self.process_next(&git_manager, &settings).await {
+                    Ok(Some(_)) => {}
+                    Ok(None) => self.wake.notified().await,
+                    Err(e) => {
+                        warn!("Merge queue worker failed: {:#}", e);
+                        self.wake.notified().await;
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Read the entries of a queue file, if it exists
+fn read_entries(path: &Path) -> Result<Vec<MergeQueueEntry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(path)
+        .context(format!("Failed to read merge queue {}", path.display()))?;
+    serde_json::from_str(&content)
+        .context(format!("Failed to parse merge queue {}", path.display()))
+}
+
+/// Path of the integration worktree
+fn integration_worktree_path(git_manager: &GitWorktreeManager, settings: &MergeQueueSettings) -> Result<PathBuf> {
+    let dir = match &settings.integration_dir {
+        Some(dir) => dir.clone(),
+        None => {
+            let repo_name = git_manager.repo_path().file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "repository".to_string());
+            format!("{}-merge-queue", repo_name)
+        }
+    };
+    Ok(git_manager.worktree_root()?.join(dir))
+}
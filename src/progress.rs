@@ -0,0 +1,207 @@
+use anyhow::Result;
+use crate::mcp::server::RequestHandler;
+use crate::mcp::types::{CallToolRequest, CallToolResult};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex, Notify};
+use tracing::{debug, info};
+
+/// Cancellation signal of a tool call, shared by its clones
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    state: Arc<CancellationState>,
+}
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the call, waking everything waiting on [`cancelled`](Self::cancelled)
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolve once the call is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // Created before the check, so a cancellation in between is not missed
+            let notified = self.state.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Sends progress notifications for a request that carried a progress token
+#[derive(Debug)]
+pub struct ProgressReporter {
+    token: Value,
+    sink: mpsc::UnboundedSender<Value>,
+    progress: AtomicU64,
+}
+
+impl ProgressReporter {
+    /// Report progress on a request to the client, through the sink of its connection
+    pub fn new(token: Value, sink: mpsc::UnboundedSender<Value>) -> Self {
+        Self {
+            token,
+            sink,
+            progress: AtomicU64::new(0),
+        }
+    }
+
+    /// Send a notification that the request reached its next step
+    pub fn report(&self, message: &str) {
+        let progress = self.progress.fetch_add(1, Ordering::SeqCst) + 1;
+        // The client may be gone; the call carries on regardless
+        let _ = self.sink.send(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/progress",
+            "params": {
+                "progressToken": self.token,
+                "progress": progress,
+                "message": message,
+            },
+        }));
+    }
+}
+
+/// Progress reporting, cancellation and the client's roots of one tool call
+///
+/// Calls made without a progress token or over a transport without cancellation get the
+/// default context, which reports nothing, is never cancelled and has no roots.
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallContext {
+    progress: Option<Arc<ProgressReporter>>,
+    cancellation: Cancellation,
+    roots: Option<Vec<PathBuf>>,
+}
+
+impl ToolCallContext {
+    pub fn new(progress: Option<ProgressReporter>, cancellation: Cancellation) -> Self {
+        Self {
+            progress: progress.map(Arc::new),
+            cancellation,
+            roots: None,
+        }
+    }
+
+    /// Restrict the call to the roots the client declared
+    pub fn with_roots(mut self, roots: Option<Vec<PathBuf>>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// The client's roots, or `None` if the client does not declare any
+    pub fn roots(&self) -> Option<&[PathBuf]> {
+        self.roots.as_deref()
+    }
+
+    /// Report the phase the call entered
+    pub fn report(&self, phase: &str) {
+        debug!("Tool call phase: {}", phase);
+        if let Some(progress) = &self.progress {
+            progress.report(phase);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Resolve once the call is cancelled
+    pub async fn cancelled(&self) {
+        self.cancellation.cancelled().await;
+    }
+
+    /// Fail if the call was cancelled, for checks between steps that cannot be interrupted
+    pub fn check_cancelled(&self, phase: &str) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(cancelled_error(phase));
+        }
+        Ok(())
+    }
+
+    /// Report a phase and run it, stopping early if the call is cancelled
+    ///
+    /// The phase's future is dropped on cancellation, which kills child processes spawned
+    /// with `kill_on_drop`. Work in blocking tasks cannot be interrupted; use
+    /// [`check_cancelled`](Self::check_cancelled) after it instead.
+    pub async fn run<T, F>(&self, phase: &str, future: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        self.report(phase);
+        tokio::select! {
+            result = future => result,
+            _ = self.cancellation.cancelled() => Err(cancelled_error(phase)),
+        }
+    }
+}
+
+fn cancelled_error(phase: &str) -> anyhow::Error {
+    info!("Tool call cancelled while {}", phase.to_lowercase());
+    anyhow::anyhow!("Cancelled by the client while {}", phase.to_lowercase())
+}
+
+/// A request handler whose tool calls report progress and can be cancelled
+pub trait ToolCallHandler: RequestHandler {
+    fn call_tool_with_context(
+        &self,
+        request: CallToolRequest,
+        context: ToolCallContext,
+    ) -> impl Future<Output = Result<CallToolResult>> + Send;
+}
+
+/// Tool calls in progress on a connection, by request ID, so they can be cancelled
+#[derive(Debug, Default)]
+pub struct InFlightRequests {
+    requests: Mutex<HashMap<String, Cancellation>>,
+}
+
+impl InFlightRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track a request, returning the cancellation its call should honor
+    pub async fn start(&self, id: &Value) -> Cancellation {
+        let cancellation = Cancellation::new();
+        self.requests.lock().await.insert(id.to_string(), cancellation.clone());
+        cancellation
+    }
+
+    /// Stop tracking a finished request
+    pub async fn finish(&self, id: &Value) {
+        self.requests.lock().await.remove(&id.to_string());
+    }
+
+    /// Cancel a request, returning whether it was in progress
+    pub async fn cancel(&self, id: &Value) -> bool {
+        match self.requests.lock().await.get(&id.to_string()) {
+            Some(cancellation) => {
+                cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
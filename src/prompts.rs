@@ -0,0 +1,308 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::conflicts::{self, ConflictOptions};
+use crate::registry::SubagentRecord;
+use crate::repositories::RepoContext;
+
+/// Largest diff included in a prompt; longer diffs are cut off
+const MAX_DIFF_BYTES: usize = 32 * 1024;
+
+/// Argument every prompt takes to choose the repository
+const REPO_ARGUMENT: &str = "repo";
+
+/// Prompt templates shipped with the server
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("delegate_task", include_str!("prompts/delegate_task.md")),
+    ("review_subagent_diff", include_str!("prompts/review_subagent_diff.md")),
+    ("resolve_integration_conflicts", include_str!("prompts/resolve_integration_conflicts.md")),
+];
+
+/// An argument of a prompt template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptArgument {
+    pub name: String,
+    pub description: String,
+    pub required: bool,
+}
+
+/// A parameterized prompt, filled from its arguments and live data when requested
+///
+/// Templates are Markdown with a front matter block:
+///
+/// ```text
+/// ---
+/// description: Review a subagent's changes
+/// arguments:
+///   - worktree_name: Worktree to review
+///   - focus?: Area to pay special attention to
+/// ---
+/// Review `{{branch}}` with a focus on {{focus}}:
+///
+/// {{diff}}
+/// ```
+///
+/// A `?` after an argument name makes it optional. Besides the arguments, templates can use
+/// `{{repo}}` and `{{active_subagents}}`, and, given a `worktree_name` argument, `{{branch}}`,
+/// `{{prompt}}`, `{{base}}`, `{{diff_stat}}`, `{{diff}}`, `{{last_run}}` and `{{conflicts}}`.
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub description: String,
+    pub arguments: Vec<PromptArgument>,
+    /// Template text with `{{placeholder}}` markers
+    pub body: String,
+    /// File the template was loaded from, for templates provided by the repository
+    pub source: Option<PathBuf>,
+}
+
+impl PromptTemplate {
+    /// Parse a template from its file contents
+    pub fn parse(name: &str, content: &str) -> Result<Self> {
+        let mut description = String::new();
+        let mut arguments = Vec::new();
+
+        let body = match content.strip_prefix("---\n") {
+            Some(rest) => {
+                let (front_matter, body) = rest.split_once("\n---\n")
+                    .context(format!("Prompt '{}' has an unterminated front matter block", name))?;
+                for line in front_matter.lines() {
+                    if let Some(argument) = line.trim_start().strip_prefix("- ") {
+                        let (name, description) = argument.split_once(':').unwrap_or((argument, ""));
+                        let (name, required) = match name.trim().strip_suffix('?') {
+                            Some(name) => (name, false),
+                            None => (name.trim(), true),
+                        };
+                        arguments.push(PromptArgument {
+                            name: name.to_string(),
+                            description: description.trim().to_string(),
+                            required,
+                        });
+                    } else if let Some(value) = line.strip_prefix("description:") {
+                        description = value.trim().to_string();
+                    } else if !line.trim().is_empty() && line.trim() != "arguments:" {
+                        return Err(anyhow::anyhow!("Unexpected line in front matter of prompt '{}': {}", name, line));
+                    }
+                }
+                body
+            }
+            None => content,
+        };
+
+        if !arguments.iter().any(|a| a.name == REPO_ARGUMENT) {
+            arguments.push(PromptArgument {
+                name: REPO_ARGUMENT.to_string(),
+                description: "Repository: name or path (defaults to the repository the server was started in)".to_string(),
+                required: false,
+            });
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            description,
+            arguments,
+            body: body.trim().to_string(),
+            source: None,
+        })
+    }
+}
+
+/// The prompt templates shipped with the server
+pub fn builtin_templates() -> Result<Vec<PromptTemplate>> {
+    BUILTIN_TEMPLATES.iter()
+        .map(|(name, content)| PromptTemplate::parse(name, content))
+        .collect()
+}
+
+/// Load the `*.md` templates in a directory, named after their files
+pub fn load_templates(dir: &Path) -> Result<Vec<PromptTemplate>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .context(format!("Failed to read prompts directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .collect();
+    paths.sort();
+
+    let mut templates = Vec::new();
+    for path in paths {
+        let Some(name) = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        let content = std::fs::read_to_string(&path)
+            .context(format!("Failed to read prompt template {}", path.display()))?;
+        let mut template = PromptTemplate::parse(&name, &content)?;
+        template.source = Some(path);
+        templates.push(template);
+    }
+
+    debug!("Loaded {} prompt templates from {}", templates.len(), dir.display());
+    Ok(templates)
+}
+
+/// Templates available in a repository: the built-in ones and those in its prompts directory
+///
+/// A repository template replaces a built-in template of the same name.
+pub fn repo_templates(repo: &RepoContext) -> Result<Vec<PromptTemplate>> {
+    let mut templates = builtin_templates()?;
+    if let Some(prompts_dir) = &repo.config.prompts_dir {
+        for template in load_templates(&repo.git_manager.repo_path().join(prompts_dir))? {
+            match templates.iter_mut().find(|t| t.name == template.name) {
+                Some(existing) => *existing = template,
+                None => templates.push(template),
+            }
+        }
+    }
+    Ok(templates)
+}
+
+/// Fill a template from its arguments and the current state of the repository
+pub async fn render_prompt(
+    repo: &RepoContext,
+    template: &PromptTemplate,
+    arguments: &HashMap<String, String>,
+) -> Result<String> {
+    for argument in &template.arguments {
+        if argument.required && !arguments.contains_key(&argument.name) {
+            return Err(anyhow::anyhow!(
+                "Prompt '{}' requires the argument '{}'",
+                template.name,
+                argument.name
+            ));
+        }
+    }
+
+    let placeholders = placeholders(&template.body);
+    // Optional arguments that were not given are left empty; `{{repo}}` is the resolved
+    // repository name rather than the argument as given
+    let mut values: HashMap<String, String> = template.arguments.iter()
+        .map(|a| (a.name.clone(), String::new()))
+        .chain(arguments.iter().map(|(name, value)| (name.clone(), value.clone())))
+        .filter(|(name, _)| name != REPO_ARGUMENT)
+        .collect();
+
+    for placeholder in &placeholders {
+        if values.contains_key(placeholder) {
+            continue;
+        }
+        let value = live_value(repo, placeholder, arguments.get("worktree_name").map(String::as_str))
+            .await
+            .context(format!("Failed to fill {{{{{}}}}} in prompt '{}'", placeholder, template.name))?;
+        values.insert(placeholder.clone(), value);
+    }
+
+    Ok(substitute(&template.body, &values))
+}
+
+/// Replace the `{{placeholders}}` in a template in one pass, so filled-in values are never expanded
+fn substitute(body: &str, values: &HashMap<String, String>) -> String {
+    let mut rendered = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + end].trim();
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(values.get(name).map_or("", String::as_str));
+        rest = &rest[start + 2 + end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Names of the `{{placeholders}}` in a template
+fn placeholders(body: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + end].trim().to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+        rest = &rest[start + 2 + end + 2..];
+    }
+    names
+}
+
+/// Value of a placeholder that is filled from live data rather than an argument
+async fn live_value(repo: &RepoContext, placeholder: &str, worktree_name: Option<&str>) -> Result<String> {
+    match placeholder {
+        "repo" => return Ok(repo.name.clone()),
+        "active_subagents" => {
+            let records: Vec<SubagentRecord> = repo.registry.lock().await.records().cloned().collect();
+            if records.is_empty() {
+                return Ok("(none)".to_string());
+            }
+            return Ok(records.iter()
+                .map(|r| format!("- {} on `{}`: {}", r.id, r.branch, r.prompt.lines().next().unwrap_or("")))
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+        "branch" | "prompt" | "base" | "diff_stat" | "diff" | "last_run" | "conflicts" => {}
+        _ => return Err(anyhow::anyhow!("Unknown placeholder")),
+    }
+
+    let worktree_name = worktree_name.context("This placeholder needs a worktree_name argument")?;
+    let worktree = repo.resolve_worktree(worktree_name).await?;
+    let record = repo.registry.lock().await.find_by_worktree(&worktree.path).cloned();
+    let branch = worktree.branch.clone().unwrap_or_else(|| "detached HEAD".to_string());
+    let base_commit = record.as_ref().and_then(|r| r.base_commit.clone());
+
+    Ok(match placeholder {
+        "branch" => branch,
+        "prompt" => record.map_or_else(
+            || "(no prompt recorded; the worktree was not spawned by this server)".to_string(),
+            |r| r.prompt,
+        ),
+        "base" => match &record {
+            Some(SubagentRecord { base_ref: Some(base_ref), .. }) => base_ref.clone(),
+            _ => base_commit.unwrap_or_else(|| "HEAD".to_string()),
+        },
+        "diff_stat" => {
+            let status = repo.git_manager.worktree_status(&worktree.path, base_commit.as_deref()).await?;
+            if status.diff_stat.is_empty() { "(no changes)".to_string() } else { status.diff_stat }
+        }
+        "diff" => {
+            let diff = repo.git_manager.worktree_diff(&worktree.path, base_commit.as_deref().unwrap_or("HEAD")).await?;
+            truncate_diff(diff)
+        }
+        "last_run" => match record.and_then(|r| r.last_run) {
+            Some(run) => format!(
+                "`{}` {} on commit {}{}",
+                run.command,
+                if run.success { "passed" } else { "failed" },
+                run.commit.as_deref().map_or("unknown", |c| c.get(..8).unwrap_or(c)),
+                if run.dirty { " with uncommitted changes" } else { "" }
+            ),
+            None => "(none)".to_string(),
+        },
+        "conflicts" => {
+            let mut report = conflicts::predict_conflicts(&repo.git_manager, &repo.registry, &ConflictOptions::default()).await?;
+            report.between_subagents.retain(|p| p.left == branch || p.right == branch);
+            report.with_base.retain(|p| p.left == branch);
+            report.summary()
+        }
+        _ => unreachable!("placeholder was checked above"),
+    })
+}
+
+/// Cut off a diff that is too long to include in a prompt
+fn truncate_diff(diff: String) -> String {
+    if diff.trim().is_empty() {
+        return "(no changes)".to_string();
+    }
+    if diff.len() <= MAX_DIFF_BYTES {
+        return diff;
+    }
+    let mut end = MAX_DIFF_BYTES;
+    while !diff.is_char_boundary(end) {
+        end -= 1;
+    }
+    warn!("Diff of {} bytes cut off at {} bytes in prompt", diff.len(), end);
+    format!("{}\n[... {} bytes truncated ...]", &diff[..end], diff.len() - end)
+}
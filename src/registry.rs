@@ -0,0 +1,222 @@
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+
+use crate::git_operations::same_path;
+
+/// File name of the registry inside the state directory
+const REGISTRY_FILE: &str = "registry.json";
+
+/// File name of the lock held while the registry is rewritten
+const REGISTRY_LOCK_FILE: &str = "registry.json.lock";
+
+/// Record of a subagent spawned by this server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubagentRecord {
+    /// Unique identifier of the subagent
+    pub id: String,
+    /// Branch the subagent works on
+    pub branch: String,
+    /// Path of the subagent's worktree
+    pub worktree_path: PathBuf,
+    /// Base branch the subagent branch was created from, if its base was a branch
+    #[serde(alias = "base_branch")]
+    pub base_ref: Option<String>,
+    /// Commit the subagent branch forked from, used as the exact base for diffs and integration
+    #[serde(default)]
+    pub base_commit: Option<String>,
+    /// Agent type that was spawned
+    pub agent_type: String,
+    /// Initial prompt given to the subagent
+    pub prompt: String,
+    /// Unix timestamp (seconds) when the subagent was spawned
+    pub created_at: u64,
+    /// Unix timestamp (seconds) of the last recorded activity
+    pub last_activity: u64,
+    /// Latest command run in the worktree through `run_in_worktree`
+    #[serde(default)]
+    pub last_run: Option<RunRecord>,
+    /// Process ID of the agent spawned for the subagent, once it started
+    #[serde(default)]
+    pub agent_pid: Option<u32>,
+}
+
+/// Summary of a command run in a subagent's worktree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// Name of the configured task, if the command is one
+    pub task: Option<String>,
+    /// The command that was run
+    pub command: String,
+    /// Whether the command exited successfully
+    pub success: bool,
+    /// Exit code, or `None` if the command was killed or timed out
+    pub exit_code: Option<i32>,
+    /// Whether the command was killed for exceeding its timeout
+    pub timed_out: bool,
+    /// How long the command ran, in milliseconds
+    pub duration_ms: u64,
+    /// Commit checked out in the worktree when the command ran
+    pub commit: Option<String>,
+    /// Whether the worktree had uncommitted changes when the command ran
+    pub dirty: bool,
+    /// Unix timestamp (seconds) when the command finished
+    pub finished_at: u64,
+}
+
+/// Persistent registry of spawned subagents, stored as JSON in the server state directory
+///
+/// Several servers may serve the same repository, so every change is made to the records
+/// on disk under a lock, rather than by writing out this process's copy.
+#[derive(Debug)]
+pub struct SubagentRegistry {
+    /// Path of the registry file
+    path: PathBuf,
+    /// Records by subagent ID, in spawn order
+    records: IndexMap<String, SubagentRecord>,
+}
+
+impl SubagentRegistry {
+    /// Load the registry from the given state directory, creating an empty one if missing
+    pub fn load(state_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(state_dir)
+            .context(format!("Failed to create state directory {}", state_dir.display()))?;
+
+        let path = state_dir.join(REGISTRY_FILE);
+        let records = read_records(&path)?;
+
+        debug!("Loaded {} subagent records from {}", records.len(), path.display());
+        Ok(Self { path, records })
+    }
+
+    /// Apply a change to the latest records on disk and write them back
+    ///
+    /// The lock keeps other processes from writing in between, so their changes are kept.
+    fn update<T>(&mut self, change: impl FnOnce(&mut IndexMap<String, SubagentRecord>) -> T) -> Result<T> {
+        let lock_path = self.path.with_file_name(REGISTRY_LOCK_FILE);
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .context(format!("Failed to open {}", lock_path.display()))?;
+        lock.lock().context(format!("Failed to lock {}", lock_path.display()))?;
+
+        self.records = read_records(&self.path)?;
+        let result = change(&mut self.records);
+        self.save()?;
+        Ok(result)
+    }
+
+    /// Write the registry back to disk
+    fn save(&self) -> Result<()> {
+        let content = serde_json::to_string_pretty(&self.records)
+            .context("Failed to serialize registry")?;
+
+        // Write to a temporary file first so a crash never leaves a truncated registry
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)
+            .context(format!("Failed to write registry {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .context(format!("Failed to replace registry {}", self.path.display()))?;
+
+        Ok(())
+    }
+
+    /// Add a record and persist the registry
+    pub fn insert(&mut self, record: SubagentRecord) -> Result<()> {
+        info!("Registering subagent '{}' on branch '{}'", record.id, record.branch);
+        self.update(|records| {
+            records.insert(record.id.clone(), record);
+        })
+    }
+
+    /// Remove a record by ID and persist the registry
+    pub fn remove(&mut self, id: &str) -> Result<Option<SubagentRecord>> {
+        let removed = self.update(|records| records.shift_remove(id))?;
+        if removed.is_some() {
+            info!("Unregistered subagent '{}'", id);
+        }
+        Ok(removed)
+    }
+
+    /// Get a record by ID
+    pub fn get(&self, id: &str) -> Option<&SubagentRecord> {
+        self.records.get(id)
+    }
+
+    /// Find the record of the subagent working in the given worktree
+    pub fn find_by_worktree(&self, worktree_path: &Path) -> Option<&SubagentRecord> {
+        self.records.values().find(|r| same_path(&r.worktree_path, worktree_path))
+    }
+
+    /// All records, in spawn order
+    pub fn records(&self) -> impl Iterator<Item = &SubagentRecord> {
+        self.records.values()
+    }
+
+    /// Store the latest command run in a subagent's worktree and persist the registry
+    pub fn record_run(&mut self, id: &str, run: RunRecord) -> Result<()> {
+        self.update(|records| {
+            if let Some(record) = records.get_mut(id) {
+                record.last_run = Some(run);
+            }
+        })
+    }
+
+    /// Store the process ID of a subagent's agent and persist the registry
+    pub fn record_agent_pid(&mut self, id: &str, pid: u32) -> Result<()> {
+        self.update(|records| {
+            if let Some(record) = records.get_mut(id) {
+                record.agent_pid = Some(pid);
+            }
+        })
+    }
+
+    /// Update the last activity timestamp of a record
+    pub fn touch(&mut self, id: &str) -> Result<()> {
+        self.update(|records| {
+            if let Some(record) = records.get_mut(id) {
+                record.last_activity = unix_now();
+            }
+        })
+    }
+}
+
+/// Read the records of a registry file, if it exists
+fn read_records(path: &Path) -> Result<IndexMap<String, SubagentRecord>> {
+    if !path.exists() {
+        return Ok(IndexMap::new());
+    }
+    let content = std::fs::read_to_string(path)
+        .context(format!("Failed to read registry {}", path.display()))?;
+    serde_json::from_str(&content)
+        .context(format!("Failed to parse registry {}", path.display()))
+}
+
+/// Generate a short, unique subagent ID
+pub fn generate_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = DefaultHasher::new();
+    SystemTime::now().hash(&mut hasher);
+    std::process::id().hash(&mut hasher);
+    COUNTER.fetch_add(1, Ordering::Relaxed).hash(&mut hasher);
+
+    format!("{:08x}", hasher.finish() as u32)
+}
+
+/// Current time as seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
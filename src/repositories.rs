@@ -0,0 +1,288 @@
+use anyhow::{Context, Result};
+use git2::Repository;
+use indexmap::IndexMap;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::config::{RepositoryEntry, ServerConfig};
+use crate::gc::{self, GcOptions};
+use crate::git_operations::{same_path, GitWorktreeManager, WorktreeInfo};
+use crate::merge_queue::MergeQueue;
+use crate::registry::SubagentRegistry;
+
+/// Lock file in the state directory, held by the process that runs the repository's background tasks
+const BACKGROUND_LOCK_FILE: &str = "background.lock";
+
+/// A repository managed by the server, with its own configuration and subagent registry
+#[derive(Debug)]
+pub struct RepoContext {
+    /// Name used to refer to the repository in tool calls
+    pub name: String,
+    /// Worktree operations on the repository
+    pub git_manager: GitWorktreeManager,
+    /// Subagents spawned in the repository
+    pub registry: Arc<Mutex<SubagentRegistry>>,
+    /// Branches waiting to be merged into the target branch
+    pub merge_queue: Arc<MergeQueue>,
+    /// The repository's configuration, with any overrides from the entry applied
+    pub config: ServerConfig,
+    /// Whether background tasks were started, or are waiting for another process to stop them
+    background_started: AtomicBool,
+    /// Lock held while this process runs the background tasks
+    background_lock: Arc<OnceLock<File>>,
+}
+
+impl RepoContext {
+    /// Open a repository, loading its configuration and registry
+    pub fn open(entry: &RepositoryEntry) -> Result<Self> {
+        let repo_path = main_checkout_path(&entry.path)?;
+        let git_manager = GitWorktreeManager::new(repo_path)?;
+
+        let mut config = ServerConfig::load(git_manager.repo_path())?;
+        if let Some(worktree_root) = &entry.worktree_root {
+            config.worktree_root = Some(worktree_root.clone());
+        }
+        if let Some(agent_defaults) = &entry.agent_defaults {
+            config.agent_defaults = agent_defaults.clone();
+        }
+
+        let git_manager = match &config.worktree_root {
+            Some(worktree_root) => git_manager.with_worktree_root(worktree_root.clone()),
+            None => git_manager,
+        };
+
+        let name = match &entry.name {
+            Some(name) => name.clone(),
+            None => repo_dir_name(git_manager.repo_path())?,
+        };
+
+        let state_dir = git_manager.state_dir()?;
+        let registry = SubagentRegistry::load(&state_dir)?;
+        let merge_queue = MergeQueue::load(&state_dir)?;
+
+        Ok(Self {
+            name,
+            git_manager,
+            registry: Arc::new(Mutex::new(registry)),
+            merge_queue: Arc::new(merge_queue),
+            config,
+            background_started: AtomicBool::new(false),
+            background_lock: Arc::new(OnceLock::new()),
+        })
+    }
+
+    /// Whether this process runs the repository's background tasks
+    pub fn runs_background_tasks(&self) -> bool {
+        self.background_lock.get().is_some()
+    }
+
+    /// Resolve a worktree by branch name, directory name, path, or subagent ID
+    pub async fn resolve_worktree(&self, identifier: &str) -> Result<WorktreeInfo> {
+        let mut matches = self.git_manager.find_worktrees(identifier).await?;
+
+        let registered_path = self.registry.lock().await
+            .get(identifier)
+            .map(|r| r.worktree_path.clone());
+        if let Some(path) = registered_path {
+            let worktrees = self.git_manager.list_worktrees().await?;
+            matches.extend(worktrees.into_iter().skip(1).filter(|w| same_path(&w.path, &path)));
+        }
+
+        matches.sort_by(|a, b| a.path.cmp(&b.path));
+        matches.dedup_by(|a, b| a.path == b.path);
+
+        match matches.len() {
+            0 => Err(anyhow::anyhow!("Worktree '{}' does not exist", identifier)),
+            1 => Ok(matches.remove(0)),
+            _ => {
+                let candidates: Vec<String> = matches.iter()
+                    .map(|w| format!(
+                        "{} (branch: {})",
+                        w.path.display(),
+                        w.branch.as_deref().unwrap_or("detached")
+                    ))
+                    .collect();
+                Err(anyhow::anyhow!(
+                    "Worktree '{}' is ambiguous, it matches: {}",
+                    identifier,
+                    candidates.join(", ")
+                ))
+            }
+        }
+    }
+
+    /// Start background tasks enabled in the repository's configuration
+    ///
+    /// The daemon, HTTP and in-process servers can all serve the same repository, but only
+    /// one process runs its background tasks: the one holding the lock file in the state
+    /// directory. Other processes wait for the lock and take over once its holder exits.
+    pub fn start_background_tasks(&self) {
+        if self.background_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let lock = match self.git_manager.state_dir().and_then(|state_dir| open_lock_file(&state_dir)) {
+            Ok(lock) => lock,
+            Err(e) => {
+                warn!("Not running background tasks of {}: {:#}", self.name, e);
+                return;
+            }
+        };
+
+        let gc_settings = self.config.gc.clone();
+        let (git_manager, registry, merge_queue) = (self.git_manager.clone(), self.registry.clone(), self.merge_queue.clone());
+        let merge_queue_settings = self.config.merge_queue.clone();
+        let spawn_tasks = move || {
+            if let Some(interval_secs) = gc_settings.interval_secs {
+                let options = GcOptions {
+                    policy: gc_settings.policy,
+                    idle_ttl_secs: gc_settings.idle_ttl_secs,
+                    base_branch: None,
+                };
+                gc::spawn_background_gc(git_manager.clone(), registry, options, Duration::from_secs(interval_secs));
+            }
+            merge_queue.spawn_worker(git_manager, merge_queue_settings);
+        };
+
+        match lock.try_lock() {
+            Ok(()) => {
+                spawn_tasks();
+                let _ = self.background_lock.set(lock);
+            }
+            Err(TryLockError::WouldBlock) => {
+                info!("Background tasks of {} are run by another server, taking over when it exits", self.name);
+                let held = self.background_lock.clone();
+                let runtime = tokio::runtime::Handle::current();
+                let name = self.name.clone();
+                // Waiting blocks, so it gets a thread of its own that never holds up shutdown
+                std::thread::spawn(move || match lock.lock() {
+                    Ok(()) => {
+                        info!("Taking over background tasks of {}", name);
+                        let _runtime = runtime.enter();
+                        spawn_tasks();
+                        let _ = held.set(lock);
+                    }
+                    Err(e) => warn!("Not running background tasks of {}: failed to lock: {}", name, e),
+                });
+            }
+            Err(TryLockError::Error(e)) => warn!("Not running background tasks of {}: failed to lock: {}", self.name, e),
+        }
+    }
+}
+
+/// Open the lock file guarding a repository's background tasks
+fn open_lock_file(state_dir: &Path) -> Result<File> {
+    let path = state_dir.join(BACKGROUND_LOCK_FILE);
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .context(format!("Failed to open {}", path.display()))
+}
+
+/// The repositories managed by the server, in the order they were added
+///
+/// The first repository added is the default for tool calls that do not name one.
+#[derive(Debug, Default)]
+pub struct RepoRegistry {
+    repos: IndexMap<String, Arc<RepoContext>>,
+}
+
+impl RepoRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a repository
+    ///
+    /// Adding a repository that is already registered returns the existing entry,
+    /// so adding is idempotent.
+    pub fn add(&mut self, repo: RepoContext) -> Result<Arc<RepoContext>> {
+        if let Some(existing) = self.find_by_path(repo.git_manager.repo_path()) {
+            return Ok(existing);
+        }
+        if let Some(existing) = self.repos.get(&repo.name) {
+            return Err(anyhow::anyhow!(
+                "A repository named '{}' is already registered at {}",
+                repo.name,
+                existing.git_manager.repo_path().display()
+            ));
+        }
+
+        info!("Managing repository '{}' at {}", repo.name, repo.git_manager.repo_path().display());
+        let repo = Arc::new(repo);
+        self.repos.insert(repo.name.clone(), repo.clone());
+        Ok(repo)
+    }
+
+    /// Find a repository by name or path, or the default repository if none is given
+    pub fn resolve(&self, identifier: Option<&str>) -> Result<Arc<RepoContext>> {
+        let Some(identifier) = identifier else {
+            return self.repos.values().next().cloned().ok_or_else(|| anyhow::anyhow!(
+                "No repositories are managed by the server; add one with add_repository"
+            ));
+        };
+
+        if let Some(repo) = self.repos.get(identifier) {
+            return Ok(repo.clone());
+        }
+        if let Some(repo) = self.find_by_path(Path::new(identifier)) {
+            return Ok(repo);
+        }
+
+        Err(anyhow::anyhow!(
+            "Unknown repository '{}'; managed repositories: {}",
+            identifier,
+            self.names().join(", ")
+        ))
+    }
+
+    /// Find a repository by its path
+    pub fn find_by_path(&self, path: &Path) -> Option<Arc<RepoContext>> {
+        self.repos.values()
+            .find(|repo| same_path(repo.git_manager.repo_path(), path))
+            .cloned()
+    }
+
+    /// All repositories, in the order they were added
+    pub fn all(&self) -> Vec<Arc<RepoContext>> {
+        self.repos.values().cloned().collect()
+    }
+
+    /// Names of all repositories
+    pub fn names(&self) -> Vec<String> {
+        self.repos.keys().cloned().collect()
+    }
+}
+
+/// Resolve a repository path to its main checkout
+///
+/// Linked worktrees share the main checkout's git common directory, and so its state
+/// directory; a linked worktree path is managed as the main checkout rather than as a
+/// repository of its own.
+pub fn main_checkout_path(path: &Path) -> Result<PathBuf> {
+    let path = path.canonicalize()
+        .context(format!("Failed to resolve repository path {}", path.display()))?;
+    match Repository::open(&path) {
+        Ok(repo) if repo.is_worktree() => {
+            let main_checkout = GitWorktreeManager::discover(&path)?.repo_path().to_path_buf();
+            main_checkout.canonicalize()
+                .context(format!("Failed to resolve main checkout {}", main_checkout.display()))
+        }
+        _ => Ok(path),
+    }
+}
+
+/// Default name of a repository: the name of its directory
+fn repo_dir_name(repo_path: &Path) -> Result<String> {
+    repo_path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .context(format!("Repository path {} has no directory name", repo_path.display()))
+}
@@ -0,0 +1,419 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tracing::{debug, warn};
+
+use crate::command_runner::MAX_CAPTURED_OUTPUT;
+use crate::git_operations::same_path;
+use crate::registry::SubagentRecord;
+use crate::repositories::{RepoContext, RepoRegistry};
+use crate::subagent_spawner::AgentOutputStream;
+
+/// Directory inside the state directory where agent output is logged
+const LOG_DIR: &str = "agent-logs";
+
+/// How much of an agent's log the `agent://<id>` resource includes
+const RECENT_OUTPUT_BYTES: usize = 4 * 1024;
+
+/// How often subscribed worktrees and agents are checked for changes
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A resource exposed by the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceUri {
+    /// `worktree://<name>[?repo=<repo>]`: status and diff summary of a worktree
+    Worktree { name: String, repo: Option<String> },
+    /// `agent://<id>`: registry record and recent output of a subagent
+    Agent { id: String },
+    /// `agent://<id>/log`: everything the subagent printed
+    AgentLog { id: String },
+}
+
+impl ResourceUri {
+    /// Parse a resource URI
+    pub fn parse(uri: &str) -> Result<Self> {
+        if let Some(rest) = uri.strip_prefix("worktree://") {
+            let (name, repo) = match rest.split_once("?repo=") {
+                Some((name, repo)) => (name, Some(repo.to_string())),
+                None => (rest, None),
+            };
+            if name.is_empty() {
+                return Err(anyhow::anyhow!("Resource URI '{}' does not name a worktree", uri));
+            }
+            return Ok(ResourceUri::Worktree { name: name.to_string(), repo });
+        }
+
+        if let Some(rest) = uri.strip_prefix("agent://") {
+            let (id, log) = match rest.strip_suffix("/log") {
+                Some(id) => (id, true),
+                None => (rest, false),
+            };
+            if id.is_empty() || id.contains('/') {
+                return Err(anyhow::anyhow!("Resource URI '{}' does not name a subagent", uri));
+            }
+            let id = id.to_string();
+            return Ok(if log { ResourceUri::AgentLog { id } } else { ResourceUri::Agent { id } });
+        }
+
+        Err(anyhow::anyhow!("Unknown resource URI '{}'; expected worktree:// or agent://", uri))
+    }
+}
+
+impl fmt::Display for ResourceUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceUri::Worktree { name, repo: Some(repo) } => write!(f, "worktree://{}?repo={}", name, repo),
+            ResourceUri::Worktree { name, repo: None } => write!(f, "worktree://{}", name),
+            ResourceUri::Agent { id } => write!(f, "agent://{}", id),
+            ResourceUri::AgentLog { id } => write!(f, "agent://{}/log", id),
+        }
+    }
+}
+
+/// A resource as listed by `resources/list`
+#[derive(Debug, Clone)]
+pub struct ResourceEntry {
+    pub uri: String,
+    pub name: String,
+    pub description: String,
+    pub mime_type: String,
+}
+
+/// Contents of a resource as returned by `resources/read`
+#[derive(Debug, Clone)]
+pub struct ResourceContent {
+    pub uri: String,
+    pub mime_type: String,
+    pub text: String,
+}
+
+/// List the worktrees and subagents of all repositories as resources
+pub async fn list_resources(repos: &RepoRegistry) -> Result<Vec<ResourceEntry>> {
+    let all = repos.all();
+    let qualify = all.len() > 1;
+    let mut resources = Vec::new();
+
+    for repo in &all {
+        let records: Vec<SubagentRecord> = repo.registry.lock().await.records().cloned().collect();
+
+        // The main checkout is not a subagent worktree
+        for worktree in repo.git_manager.list_worktrees().await?.into_iter().skip(1) {
+            let name = match &worktree.branch {
+                Some(branch) => branch.clone(),
+                None => worktree.path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| worktree.path.display().to_string()),
+            };
+            let uri = ResourceUri::Worktree {
+                name: name.clone(),
+                repo: qualify.then(|| repo.name.clone()),
+            };
+            let owner = records.iter()
+                .find(|r| same_path(&r.worktree_path, &worktree.path))
+                .map_or_else(String::new, |r| format!(" of subagent {}", r.id));
+            resources.push(ResourceEntry {
+                uri: uri.to_string(),
+                name: format!("Worktree {}", name),
+                description: format!("Status and diff summary of worktree {}{}", worktree.path.display(), owner),
+                mime_type: "application/json".to_string(),
+            });
+        }
+
+        for record in &records {
+            resources.push(ResourceEntry {
+                uri: ResourceUri::Agent { id: record.id.clone() }.to_string(),
+                name: format!("Subagent {}", record.id),
+                description: format!("Registry record and recent output of the subagent on branch '{}'", record.branch),
+                mime_type: "application/json".to_string(),
+            });
+            resources.push(ResourceEntry {
+                uri: ResourceUri::AgentLog { id: record.id.clone() }.to_string(),
+                name: format!("Subagent {} log", record.id),
+                description: format!("Output of the subagent on branch '{}'", record.branch),
+                mime_type: "text/plain".to_string(),
+            });
+        }
+    }
+
+    Ok(resources)
+}
+
+/// Read a resource
+pub async fn read_resource(repos: &RepoRegistry, uri: &str) -> Result<ResourceContent> {
+    match ResourceUri::parse(uri)? {
+        ResourceUri::Worktree { name, repo } => {
+            let repo = repos.resolve(repo.as_deref())?;
+            let text = worktree_json(&repo, &name).await?;
+            Ok(ResourceContent {
+                uri: uri.to_string(),
+                mime_type: "application/json".to_string(),
+                text,
+            })
+        }
+        ResourceUri::Agent { id } => {
+            let (repo, record) = find_agent(repos, &id).await?;
+            let recent_output = read_log_tail(&agent_log_path(&repo.git_manager.state_dir()?, &id), RECENT_OUTPUT_BYTES)?;
+            let text = serde_json::to_string_pretty(&json!({
+                "repo": repo.name,
+                "record": record,
+                "recent_output": recent_output,
+            }))?;
+            Ok(ResourceContent {
+                uri: uri.to_string(),
+                mime_type: "application/json".to_string(),
+                text,
+            })
+        }
+        ResourceUri::AgentLog { id } => {
+            let (repo, _) = find_agent(repos, &id).await?;
+            let text = read_log_tail(&agent_log_path(&repo.git_manager.state_dir()?, &id), MAX_CAPTURED_OUTPUT)?;
+            Ok(ResourceContent {
+                uri: uri.to_string(),
+                mime_type: "text/plain".to_string(),
+                text,
+            })
+        }
+    }
+}
+
+/// Status and diff summary of a worktree as JSON
+async fn worktree_json(repo: &RepoContext, name: &str) -> Result<String> {
+    let worktree = repo.resolve_worktree(name).await?;
+    let record = repo.registry.lock().await.find_by_worktree(&worktree.path).cloned();
+    let base = record.as_ref().and_then(|r| r.base_commit.clone());
+    let status = repo.git_manager.worktree_status(&worktree.path, base.as_deref()).await?;
+
+    Ok(serde_json::to_string_pretty(&json!({
+        "repo": repo.name,
+        "path": worktree.path,
+        "branch": worktree.branch,
+        "head": status.head,
+        "locked": worktree.locked,
+        "subagent_id": record.as_ref().map(|r| &r.id),
+        "base_commit": base,
+        "changes": status.changes,
+        "diff_stat": status.diff_stat,
+    }))?)
+}
+
+/// Find a subagent by ID in any repository
+async fn find_agent(repos: &RepoRegistry, id: &str) -> Result<(Arc<RepoContext>, SubagentRecord)> {
+    for repo in repos.all() {
+        let record = repo.registry.lock().await.get(id).cloned();
+        if let Some(record) = record {
+            return Ok((repo, record));
+        }
+    }
+    Err(anyhow::anyhow!("Subagent '{}' does not exist", id))
+}
+
+/// Path of the log a subagent's output is written to
+pub fn agent_log_path(state_dir: &Path, id: &str) -> PathBuf {
+    state_dir.join(LOG_DIR).join(format!("{}.log", id))
+}
+
+/// Read the last `max_bytes` of a log, or nothing if there is no log yet
+fn read_log_tail(path: &Path, max_bytes: usize) -> Result<String> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(String::new()),
+        Err(e) => return Err(e).context(format!("Failed to read log {}", path.display())),
+    };
+    let start = bytes.len().saturating_sub(max_bytes);
+    Ok(String::from_utf8_lossy(&bytes[start..]).into_owned())
+}
+
+/// Tracks resource subscriptions and publishes updates of subscribed resources
+///
+/// Every client receives the published updates and keeps those it subscribed to in its
+/// [`ClientSubscriptions`]; the notifier only counts how many clients want each resource.
+#[derive(Debug)]
+pub struct ResourceNotifier {
+    /// Number of clients subscribed to each URI
+    subscriptions: Mutex<HashMap<String, usize>>,
+    /// URIs of subscribed resources that changed
+    updates: broadcast::Sender<String>,
+}
+
+impl Default for ResourceNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourceNotifier {
+    /// Create a notifier without subscriptions
+    pub fn new() -> Self {
+        let (updates, _) = broadcast::channel(256);
+        Self {
+            subscriptions: Mutex::new(HashMap::new()),
+            updates,
+        }
+    }
+
+    /// Subscribe a client to updates of a resource
+    pub async fn subscribe(&self, uri: &str) -> Result<()> {
+        ResourceUri::parse(uri)?;
+        debug!("Subscribed to {}", uri);
+        *self.subscriptions.lock().await.entry(uri.to_string()).or_default() += 1;
+        Ok(())
+    }
+
+    /// Unsubscribe a client from a resource, publishing no more updates once no client wants them
+    pub async fn unsubscribe(&self, uri: &str) {
+        debug!("Unsubscribed from {}", uri);
+        let mut subscriptions = self.subscriptions.lock().await;
+        if let Some(count) = subscriptions.get_mut(uri) {
+            *count -= 1;
+            if *count == 0 {
+                subscriptions.remove(uri);
+            }
+        }
+    }
+
+    /// URIs any client is subscribed to
+    pub async fn subscriptions(&self) -> Vec<String> {
+        self.subscriptions.lock().await.keys().cloned().collect()
+    }
+
+    /// Publish an update of a resource, if anyone subscribed to it
+    pub async fn notify(&self, uri: &str) {
+        if self.subscriptions.lock().await.contains_key(uri) {
+            // Sending only fails when nobody listens, which is fine
+            let _ = self.updates.send(uri.to_string());
+        }
+    }
+
+    /// Receive the URIs of subscribed resources as they change
+    pub fn updates(&self) -> broadcast::Receiver<String> {
+        self.updates.subscribe()
+    }
+}
+
+/// Resources one client subscribed to, so it only receives the updates it asked for
+#[derive(Debug, Default)]
+pub struct ClientSubscriptions {
+    uris: Mutex<HashSet<String>>,
+}
+
+impl ClientSubscriptions {
+    /// Record a subscription, returning whether it is new
+    pub async fn insert(&self, uri: &str) -> bool {
+        self.uris.lock().await.insert(uri.to_string())
+    }
+
+    /// Forget a subscription, returning whether there was one
+    pub async fn remove(&self, uri: &str) -> bool {
+        self.uris.lock().await.remove(uri)
+    }
+
+    /// Whether the client subscribed to a resource
+    pub async fn contains(&self, uri: &str) -> bool {
+        self.uris.lock().await.contains(uri)
+    }
+
+    /// Forget every subscription, returning their URIs
+    pub async fn clear(&self) -> Vec<String> {
+        self.uris.lock().await.drain().collect()
+    }
+}
+
+/// Append an agent's output to its log, publishing an update of its resources as output arrives
+///
+/// The returned task finishes once the stream ends and everything read is in the log.
+pub fn capture_agent_output(
+    mut stream: AgentOutputStream,
+    log_path: PathBuf,
+    id: String,
+    notifier: Arc<ResourceNotifier>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        if let Some(dir) = log_path.parent() {
+            if let Err(e) = tokio::fs::create_dir_all(dir).await {
+                warn!("Failed to create log directory {}: {}", dir.display(), e);
+            }
+        }
+        let mut log = match tokio::fs::OpenOptions::new().create(true).append(true).open(&log_path).await {
+            Ok(log) => Some(log),
+            Err(e) => {
+                warn!("Failed to open agent log {}: {}", log_path.display(), e);
+                None
+            }
+        };
+
+        let uris = [
+            ResourceUri::Agent { id: id.clone() }.to_string(),
+            ResourceUri::AgentLog { id }.to_string(),
+        ];
+        // Keep draining the stream even without a log, so the agent never blocks on a full pipe
+        let mut buffer = vec![0u8; 8 * 1024];
+        loop {
+            let read = match stream.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) => {
+                    warn!("Failed to read agent output: {}", e);
+                    break;
+                }
+            };
+            if let Some(file) = log.as_mut() {
+                if let Err(e) = file.write_all(&buffer[..read]).await {
+                    warn!("Failed to write agent log {}: {}", log_path.display(), e);
+                    log = None;
+                }
+            }
+            for uri in &uris {
+                notifier.notify(uri).await;
+            }
+        }
+        // Writes to a tokio file complete in the background until flushed
+        if let Some(file) = log.as_mut() {
+            if let Err(e) = file.flush().await {
+                warn!("Failed to write agent log {}: {}", log_path.display(), e);
+            }
+        }
+    })
+}
+
+/// Watch subscribed worktree and agent resources, publishing an update when their contents change
+///
+/// Agent logs are not polled; they are published as output arrives.
+pub fn spawn_resource_watcher(repos: Arc<RwLock<RepoRegistry>>, notifier: Arc<ResourceNotifier>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut fingerprints: HashMap<String, u64> = HashMap::new();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            let subscriptions = notifier.subscriptions().await;
+            fingerprints.retain(|uri, _| subscriptions.contains(uri));
+
+            for uri in subscriptions {
+                if matches!(ResourceUri::parse(&uri), Ok(ResourceUri::AgentLog { .. })) {
+                    continue;
+                }
+                // A resource that cannot be read (such as a removed worktree) changed too
+                let content = read_resource(&*repos.read().await, &uri).await
+                    .map(|content| content.text)
+                    .unwrap_or_default();
+                let mut hasher = DefaultHasher::new();
+                content.hash(&mut hasher);
+                let fingerprint = hasher.finish();
+
+                if let Some(previous) = fingerprints.insert(uri.clone(), fingerprint) {
+                    if previous != fingerprint {
+                        notifier.notify(&uri).await;
+                    }
+                }
+            }
+        }
+    });
+}
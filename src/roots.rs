@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::{Notify, RwLock};
+use tracing::{debug, warn};
+
+/// How long a tool call waits for the client to answer `roots/list`
+const ROOTS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The roots a client declared, as known to the connection it uses
+#[derive(Debug)]
+enum RootsState {
+    /// The client does not support roots, so calls are not restricted
+    Unsupported,
+    /// The roots were requested and the client has not answered yet
+    Requested,
+    Known(Vec<PathBuf>),
+}
+
+/// Roots of the client on a connection
+///
+/// Tool calls wait for roots that were requested but not yet received, so they never run
+/// unrestricted between initialization and the client's answer.
+#[derive(Debug)]
+pub struct ClientRoots {
+    state: RwLock<RootsState>,
+    changed: Notify,
+}
+
+impl Default for ClientRoots {
+    fn default() -> Self {
+        Self {
+            state: RwLock::new(RootsState::Unsupported),
+            changed: Notify::new(),
+        }
+    }
+}
+
+impl ClientRoots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that the roots were requested from the client
+    pub async fn requested(&self) {
+        let mut state = self.state.write().await;
+        // Calls keep using the known roots until the client sends the new ones
+        if matches!(*state, RootsState::Unsupported) {
+            *state = RootsState::Requested;
+        }
+    }
+
+    /// Record the roots the client declared
+    pub async fn set(&self, roots: Vec<PathBuf>) {
+        debug!("Client roots: {:?}", roots);
+        *self.state.write().await = RootsState::Known(roots);
+        self.changed.notify_waiters();
+    }
+
+    /// The client's roots, or `None` if the client does not declare any
+    pub async fn current(&self) -> Result<Option<Vec<PathBuf>>> {
+        let deadline = tokio::time::Instant::now() + ROOTS_TIMEOUT;
+        loop {
+            // Created before the check, so roots set in between are not missed
+            let changed = self.changed.notified();
+            match &*self.state.read().await {
+                RootsState::Unsupported => return Ok(None),
+                RootsState::Known(roots) => return Ok(Some(roots.clone())),
+                RootsState::Requested => {}
+            }
+            if tokio::time::timeout_at(deadline, changed).await.is_err() {
+                return Err(anyhow::anyhow!(
+                    "The client did not answer roots/list within {}s",
+                    ROOTS_TIMEOUT.as_secs()
+                ));
+            }
+        }
+    }
+}
+
+/// Local path of a `file://` root URI
+pub fn root_path(uri: &str) -> Result<PathBuf> {
+    let path = uri.strip_prefix("file://")
+        .context(format!("Root '{}' is not a file:// URI", uri))?;
+    // The authority is empty or localhost for local files
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    if !path.starts_with('/') {
+        return Err(anyhow::anyhow!("Root '{}' is not a local path", uri));
+    }
+    Ok(PathBuf::from(percent_decode(path)?))
+}
+
+/// Decode `%XX` escapes in a URI path
+fn percent_decode(path: &str) -> Result<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = path.get(i + 1..i + 3).context(format!("Truncated escape in '{}'", path))?;
+            decoded.push(u8::from_str_radix(hex, 16).context(format!("Invalid escape in '{}'", path))?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).context(format!("Path '{}' is not valid UTF-8", path))
+}
+
+/// Paths of the roots in a `roots/list` result; roots that are not local paths are skipped
+pub fn parse_roots(result: &Value) -> Result<Vec<PathBuf>> {
+    let roots = result.get("roots")
+        .and_then(Value::as_array)
+        .context("roots/list result has no roots")?;
+
+    let mut paths = Vec::new();
+    for root in roots {
+        let Some(uri) = root.get("uri").and_then(Value::as_str) else {
+            warn!("Ignoring root without a URI: {}", root);
+            continue;
+        };
+        match root_path(uri) {
+            Ok(path) => paths.push(path),
+            Err(e) => warn!("Ignoring root: {:#}", e),
+        }
+    }
+    Ok(paths)
+}
+
+/// Whether a path is one of the roots or inside one
+pub fn is_within(path: &Path, roots: &[PathBuf]) -> bool {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    roots.iter().any(|root| {
+        let root = root.canonicalize().unwrap_or_else(|_| root.clone());
+        path.starts_with(root)
+    })
+}
@@ -0,0 +1,208 @@
+use anyhow::Result;
+use indexmap::IndexMap;
+use schemars::generate::SchemaSettings;
+use schemars::transform::{transform_subschemas, Transform};
+use schemars::{JsonSchema, Schema};
+use serde_json::{json, Value};
+
+/// Input schema of a tool whose arguments deserialize into `T`
+///
+/// Field doc comments become the property descriptions. Subschemas are inlined and
+/// optional fields are described by their type alone, so clients and the documentation
+/// generator see one plain object schema.
+pub fn input_schema<T: JsonSchema>() -> Value {
+    let generator = SchemaSettings::draft07()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+            settings.transforms.push(Box::new(Simplify));
+        })
+        .into_generator();
+
+    let mut schema = generator.into_root_schema_for::<T>();
+    // The tool's own name and description take the place of the type's
+    schema.remove("title");
+    schema.remove("description");
+    schema.to_value()
+}
+
+/// Schema of a string property restricted to the given values
+///
+/// An empty list leaves the property unrestricted, since an empty enum accepts nothing.
+pub fn string_enum(description: &str, values: &[&str]) -> Value {
+    let mut schema = json!({
+        "type": "string",
+        "description": description,
+    });
+    if !values.is_empty() {
+        schema["enum"] = json!(values);
+    }
+    schema
+}
+
+/// Schema of options that differ by agent, accepting the options of any of the agents
+///
+/// Each alternative is titled with the name of the agent it belongs to.
+pub fn agent_options(description: &str, agents: Vec<(&str, Value)>) -> Value {
+    let mut alternatives: Vec<Value> = agents.into_iter()
+        .map(|(name, mut schema)| {
+            schema["title"] = json!(name);
+            schema
+        })
+        .collect();
+
+    match alternatives.len() {
+        0 => json!({
+            "type": "object",
+            "description": description,
+        }),
+        1 => {
+            let mut schema = alternatives.remove(0);
+            schema["description"] = json!(description);
+            schema
+        }
+        _ => json!({
+            "type": "object",
+            "description": description,
+            "anyOf": alternatives,
+        }),
+    }
+}
+
+/// Check an agent's custom options against the object schema it declares
+///
+/// Covers what agents use to declare options: the type and allowed values of each property,
+/// and whether properties outside the declared ones are accepted.
+pub fn validate_options(agent: &str, schema: &Value, options: &IndexMap<String, Value>) -> Result<()> {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let accepts_others = schema.get("additionalProperties") != Some(&Value::Bool(false));
+
+    for (key, value) in options {
+        let Some(property) = properties.and_then(|properties| properties.get(key)) else {
+            if accepts_others {
+                continue;
+            }
+            let supported: Vec<&str> = properties
+                .map(|properties| properties.keys().map(String::as_str).collect())
+                .unwrap_or_default();
+            if supported.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Agent '{}' takes no custom options, got '{}'; pass raw arguments in extra_args",
+                    agent,
+                    key
+                ));
+            }
+            return Err(anyhow::anyhow!(
+                "Unknown option '{}' for agent '{}' (supported: {}); pass raw arguments in extra_args",
+                key,
+                agent,
+                supported.join(", ")
+            ));
+        };
+
+        if let Some(expected) = property.get("type").and_then(Value::as_str) {
+            if !has_type(value, expected) {
+                return Err(anyhow::anyhow!(
+                    "Option '{}' of agent '{}' must be of type {}, got {}",
+                    key,
+                    agent,
+                    expected,
+                    value
+                ));
+            }
+        }
+
+        if let Some(allowed) = property.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                return Err(anyhow::anyhow!(
+                    "Invalid value {} for option '{}' of agent '{}' (expected one of: {})",
+                    value,
+                    key,
+                    agent,
+                    allowed.iter().map(Value::to_string).collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether a value is of a JSON schema type
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        // Leave types this check does not know to the agent
+        _ => true,
+    }
+}
+
+/// Drops `null` from the schemas of `Option` fields and unwraps descriptions
+///
+/// Omitting an optional argument is how clients leave it unset; advertising `null` as well
+/// only produces type arrays and `anyOf` wrappers that many clients do not understand.
+#[derive(Clone)]
+struct Simplify;
+
+impl Transform for Simplify {
+    fn transform(&mut self, schema: &mut Schema) {
+        transform_subschemas(self, schema);
+
+        let Some(object) = schema.as_object_mut() else {
+            return;
+        };
+
+        // Doc comments are wrapped in the source, descriptions are read as one paragraph
+        if let Some(Value::String(description)) = object.get_mut("description") {
+            *description = description.replace('\n', " ");
+        }
+
+        if object.get("default").is_some_and(Value::is_null) {
+            object.remove("default");
+        }
+
+        if let Some(Value::Array(types)) = object.get_mut("type") {
+            types.retain(|t| t != "null");
+            if types.len() == 1 {
+                let single = types.remove(0);
+                object.insert("type".to_string(), single);
+            }
+        }
+
+        if let Some(Value::Array(values)) = object.get_mut("enum") {
+            values.retain(|v| !v.is_null());
+        }
+
+        // Optional structs and enums are wrapped as `anyOf` the type and null
+        if let Some(Value::Array(variants)) = object.get("anyOf") {
+            let is_null = |variant: &Value| variant.get("type").and_then(Value::as_str) == Some("null");
+            if variants.len() == 2 && variants.iter().any(is_null) {
+                let inner = variants.iter().find(|v| !is_null(v)).cloned();
+                object.remove("anyOf");
+                if let Some(Value::Object(inner)) = inner {
+                    for (key, value) in inner {
+                        // Keep the field's own description over the type's
+                        object.entry(key).or_insert(value);
+                    }
+                }
+            }
+        }
+
+        // Enums with documented variants are a `oneOf` of constants; a plain enum says the same
+        if let Some(Value::Array(variants)) = object.get("oneOf") {
+            let values: Option<Vec<Value>> = variants.iter().map(|variant| variant.get("const").cloned()).collect();
+            let variant_type = variants.first().and_then(|variant| variant.get("type")).cloned();
+            let same_type = variants.iter().all(|variant| variant.get("type") == variant_type.as_ref());
+            if let (Some(values), Some(variant_type), true) = (values, variant_type, same_type) {
+                object.remove("oneOf");
+                object.insert("type".to_string(), variant_type);
+                object.insert("enum".to_string(), Value::Array(values));
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::http_transport;
+use crate::http_transport::ClientSession;
+use crate::progress::ToolCallHandler;
+use crate::resources::ResourceNotifier;
+use crate::roots;
+
+/// Prefix of the IDs of `roots/list` requests sent to clients
+const ROOTS_REQUEST_PREFIX: &str = "roots/list-";
+
+/// Serve one client until it disconnects
+///
+/// Used for clients attached to the daemon and for `--in-process` stdio alike. Clients
+/// supporting roots are asked for them once initialized and whenever they change. Requests are handled concurrently, so a slow tool call can be cancelled by a later message.
+pub async fn serve_client<H, R, W>(
+    reader: R,
+    mut writer: W,
+    handler: Arc<H>,
+    notifier: Arc<ResourceNotifier>,
+) -> Result<()>
+where
+    H: ToolCallHandler,
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    // Responses and notifications are written in the order they are sent here
+    let (outgoing, mut to_write) = mpsc::unbounded_channel::<Value>();
+    let writer_task = tokio::spawn(async move {
+        while let Some(message) = to_write.recv().await {
+            if write_message(&mut writer, &message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let session = ClientSession {
+        progress: Some(outgoing.clone()),
+        ..ClientSession::default()
+    };
+
+    // Forward updates of the resources the client subscribed to alongside responses
+    let mut updates = notifier.updates();
+    let notifications = outgoing.clone();
+    let subscriptions = session.subscriptions.clone();
+    let forwarder = tokio::spawn(async move {
+        loop {
+            match updates.recv().await {
+                Ok(uri) => {
+                    if !subscriptions.contains(&uri).await {
+                        continue;
+                    }
+                    if notifications.send(http_transport::resource_updated(&uri)).is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Client fell behind, skipped {} resource updates", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    let mut supports_roots = false;
+    let mut roots_requests = 0u64;
+    let mut lines = BufReader::new(reader).lines();
+    let result = async {
+        while let Some(line) = lines.next_line().await.context("Failed to read from client")? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    let _ = outgoing.send(json!({
+                        "jsonrpc": "2.0",
+                        "id": null,
+                        "error": { "code": -32700, "message": format!("Parse error: {}", e) },
+                    }));
+                    continue;
+                }
+            };
+
+            // The client's roots are requested once it is initialized and again whenever they change
+            match message.get("method").and_then(Value::as_str) {
+                Some("initialize") => {
+                    supports_roots = message.pointer("/params/capabilities/roots").is_some();
+                }
+                Some("notifications/initialized" | "notifications/roots/list_changed") if supports_roots => {
+                    roots_requests += 1;
+                    session.roots.requested().await;
+                    let _ = outgoing.send(json!({
+                        "jsonrpc": "2.0",
+                        "id": format!("{}{}", ROOTS_REQUEST_PREFIX, roots_requests),
+                        "method": "roots/list",
+                    }));
+                }
+                None if is_roots_response(&message) => {
+                    handle_roots_response(&session, &message).await;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let handler = handler.clone();
+            let session = session.clone();
+            tokio::spawn(async move {
+                if let Some(response) = http_transport::dispatch(&*handler, message, &session).await {
+                    if let Some(outgoing) = &session.progress {
+                        let _ = outgoing.send(response);
+                    }
+                }
+            });
+        }
+        Ok(())
+    }.await;
+
+    // Calls still in progress finish on their own; the writer stops once they are done
+    forwarder.abort();
+    http_transport::release_subscriptions(&*handler, &session).await;
+    drop(outgoing);
+    drop(writer_task);
+    result
+}
+
+/// Whether a message is the client's answer to a `roots/list` request
+fn is_roots_response(message: &Value) -> bool {
+    message.get("id")
+        .and_then(Value::as_str)
+        .is_some_and(|id| id.starts_with(ROOTS_REQUEST_PREFIX))
+}
+
+/// Record the roots from the client's answer to a `roots/list` request
+async fn handle_roots_response(session: &ClientSession, message: &Value) {
+    let roots = match message.get("result").map(roots::parse_roots) {
+        Some(Ok(roots)) => roots,
+        Some(Err(e)) => {
+            warn!("Client sent invalid roots: {:#}", e);
+            Vec::new()
+        }
+        None => {
+            warn!("Client failed to list its roots: {}", message.get("error").unwrap_or(&serde_json::Value::Null));
+            Vec::new()
+        }
+    };
+    info!("Client roots: {}", roots.iter().map(|r| r.display().to_string()).collect::<Vec<_>>().join(", "));
+    session.roots.set(roots).await;
+}
+
+/// Write one newline-delimited JSON-RPC message
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Value) -> Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
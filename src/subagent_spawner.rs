@@ -1,9 +1,13 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::path::Path;
 use std::process::Stdio;
+use tokio::io::AsyncRead;
 use tokio::process::Command as TokioCommand;
+use tokio::sync::oneshot;
 use tracing::{debug, error, info, warn};
 
 /// Trait for different types of agents that can be spawned
@@ -13,17 +17,52 @@ pub trait AgentSpawner: Send + Sync {
     async fn is_available(&self) -> Result<bool>;
     
     /// Spawn the agent in the specified directory with the given prompt
-    async fn spawn(&self, worktree_path: &Path, prompt: &str, options: &AgentOptions) -> Result<()>;
+    ///
+    /// Returns as soon as the agent process is started; use [`SpawnedAgent::wait`] to wait for it.
+    async fn spawn(&self, worktree_path: &Path, prompt: &str, options: &AgentOptions) -> Result<SpawnedAgent>;
     
     /// Get information about this agent type
     async fn get_info(&self) -> Result<AgentInfo>;
     
     /// Get the name of this agent type
     fn name(&self) -> &'static str;
+
+    /// What this agent supports
+    fn capabilities(&self) -> AgentCapabilities {
+        AgentCapabilities::default()
+    }
+
+    /// JSON schema of the `custom_options` this agent accepts
+    ///
+    /// An object schema whose properties give the type, default and allowed values of each
+    /// option. Agents without options of their own accept none.
+    fn custom_options_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {},
+            "additionalProperties": false,
+        })
+    }
+
+    /// JSON schema of the options this agent accepts, advertised in the `spawn_subagent` tool
+    fn options_schema(&self) -> Value {
+        let mut schema = crate::schema::input_schema::<AgentOptions>();
+        let description = schema["properties"]["custom_options"]["description"].clone();
+        let mut custom_options = self.custom_options_schema();
+        custom_options["description"] = description;
+        schema["properties"]["custom_options"] = custom_options;
+        schema
+    }
+
+    /// Check options against [`custom_options_schema`](Self::custom_options_schema) before spawning
+    fn validate_options(&self, options: &AgentOptions) -> Result<()> {
+        crate::schema::validate_options(self.name(), &self.custom_options_schema(), &options.custom_options)
+    }
 }
 
 /// Configuration options for agent spawning
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
 pub struct AgentOptions {
     /// Whether to open in a new window/instance
     pub new_window: bool,
@@ -31,8 +70,17 @@ pub struct AgentOptions {
     pub wait: bool,
     /// Whether to detach the process (don't wait for completion)
     pub detach: bool,
-    /// Additional custom options specific to the agent type
-    pub custom_options: indexmap::IndexMap<String, String>,
+    /// Options specific to the agent type, as declared by its options schema
+    pub custom_options: indexmap::IndexMap<String, Value>,
+    /// Raw arguments passed to the agent command as given, after the ones derived from the other options
+    pub extra_args: Vec<String>,
+    /// Model for the agent to use (requires model selection)
+    pub model: Option<String>,
+    /// Session of an earlier run to resume (requires resumable sessions)
+    pub resume_session: Option<String>,
+    /// Have the agent report as JSON lines, so its result and session are parsed from its output
+    /// (requires JSON output)
+    pub json_output: bool,
 }
 
 impl Default for AgentOptions {
@@ -42,19 +90,144 @@ impl Default for AgentOptions {
             wait: true,
             detach: false,
             custom_options: indexmap::IndexMap::new(),
+            extra_args: Vec::new(),
+            model: None,
+            resume_session: None,
+            json_output: false,
         }
     }
 }
 
+/// An output stream of an agent process
+pub type AgentOutputStream = Box<dyn AsyncRead + Send + Unpin>;
+
+/// A started agent process
+pub struct SpawnedAgent {
+    /// Process ID of the agent, if the OS reported one
+    pub pid: Option<u32>,
+    /// Resolves to the exit code when the process exits (`None` if killed by a signal)
+    completion: tokio::task::JoinHandle<Result<Option<i32>>>,
+    /// Output streams of the process that have not been taken yet
+    output: Vec<AgentOutputStream>,
+    /// Asks the wait task to kill the process, if the agent supports it
+    kill: Option<oneshot::Sender<()>>,
+}
+
+impl std::fmt::Debug for SpawnedAgent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpawnedAgent")
+            .field("pid", &self.pid)
+            .field("output_streams", &self.output.len())
+            .finish()
+    }
+}
+
+impl SpawnedAgent {
+    /// Create a spawned agent from its process ID and a task that waits for it to exit
+    pub fn new(pid: Option<u32>, completion: tokio::task::JoinHandle<Result<Option<i32>>>) -> Self {
+        Self { pid, completion, output: Vec::new(), kill: None }
+    }
+
+    /// Attach a channel the wait task listens on to kill the process
+    pub fn with_kill_switch(mut self, kill: oneshot::Sender<()>) -> Self {
+        self.kill = Some(kill);
+        self
+    }
+
+    /// Take the kill switch of the process, to kill it while something else waits for it
+    ///
+    /// Sending on the switch kills the process; dropping it does not.
+    pub fn take_kill_switch(&mut self) -> Option<oneshot::Sender<()>> {
+        self.kill.take()
+    }
+
+    /// Attach an output stream of the process, such as its stdout
+    pub fn with_output(mut self, stream: impl AsyncRead + Send + Unpin + 'static) -> Self {
+        self.output.push(Box::new(stream));
+        self
+    }
+
+    /// Take the output streams of the process
+    ///
+    /// Piped output must be read, or the agent blocks once the pipe buffer is full.
+    pub fn take_output(&mut self) -> Vec<AgentOutputStream> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Wait for the agent process to exit, returning its exit code
+    pub async fn wait(self) -> Result<Option<i32>> {
+        self.completion.await
+            .context("Agent wait task failed")?
+    }
+}
+
+/// What an agent type supports, so callers can choose between agents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentCapabilities {
+    /// Runs attached to a pseudo-terminal
+    pub pty: bool,
+    /// Runs without a window or terminal for the user
+    pub headless: bool,
+    /// Accepts further messages after the initial prompt
+    pub follow_up_messages: bool,
+    /// Lets the caller choose the model it uses
+    pub model_selection: bool,
+    /// Can report its progress and result as JSON lines on stdout
+    pub json_output: bool,
+    /// Can resume an earlier session instead of starting a new one
+    pub resumable_sessions: bool,
+}
+
+impl AgentCapabilities {
+    /// Capabilities the options ask for that the agent lacks
+    pub fn missing_for(&self, options: &AgentOptions) -> Vec<String> {
+        let mut missing = Vec::new();
+        if let Some(model) = &options.model {
+            if !self.model_selection {
+                missing.push(format!("model selection (requested model '{}')", model));
+            }
+        }
+        if let Some(session) = &options.resume_session {
+            if !self.resumable_sessions {
+                missing.push(format!("resumable sessions (requested session '{}')", session));
+            }
+        }
+        if options.json_output && !self.json_output {
+            missing.push("JSON output".to_string());
+        }
+        missing
+    }
+}
+
 /// Information about an agent type
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentInfo {
+    /// Name of the agent type, as given in `agent_type`
+    pub name: String,
     /// Whether the agent is available
     pub available: bool,
     /// Version string of the agent
     pub version: String,
     /// Description of the agent
     pub description: String,
+    /// What the agent supports
+    pub capabilities: AgentCapabilities,
+    /// JSON schema of the options the agent accepts
+    pub options_schema: Value,
+}
+
+impl AgentInfo {
+    /// Information about an agent, with the name, capabilities and options it declares
+    pub fn for_agent(agent: &dyn AgentSpawner, available: bool, version: String, description: String) -> Self {
+        Self {
+            name: agent.name().to_string(),
+            available,
+            version,
+            description,
+            capabilities: agent.capabilities(),
+            options_schema: agent.options_schema(),
+        }
+    }
 }
 
 /// Cursor CLI agent implementation
@@ -72,7 +245,7 @@ impl AgentSpawner for CursorCliAgent {
         Ok(result.status.success())
     }
 
-    async fn spawn(&self, worktree_path: &Path, prompt: &str, options: &AgentOptions) -> Result<()> {
+    async fn spawn(&self, worktree_path: &Path, prompt: &str, options: &AgentOptions) -> Result<SpawnedAgent> {
         if !self.is_available().await? {
             return Err(anyhow::anyhow!("cursor-cli is not available in PATH"));
         }
@@ -91,11 +264,7 @@ impl AgentSpawner for CursorCliAgent {
             cmd.arg("--wait");
         }
         
-        // Add custom options as arguments
-        for (key, value) in &options.custom_options {
-            cmd.arg(format!("--{}", key));
-            cmd.arg(value);
-        }
+        cmd.args(cursor_args(options));
         
         // Add the worktree path
         cmd.arg(worktree_path);
@@ -121,42 +290,49 @@ impl AgentSpawner for CursorCliAgent {
             });
         }
 
-        // Handle process completion based on options
-        if options.detach {
-            // Detach the process - don't wait for it
-            tokio::spawn(async move {
-                match process.wait().await {
-                    Ok(status) => {
-                        if status.success() {
-                            info!("Detached cursor-cli process completed successfully");
-                        } else {
-                            warn!("Detached cursor-cli process exited with non-zero status: {:?}", status.code());
-                        }
-                    }
-                    Err(e) => {
-                        error!("Error waiting for detached cursor-cli process: {}", e);
+        let pid = process.id();
+        let stdout = process.stdout.take();
+        let stderr = process.stderr.take();
+
+        // Wait for the process in the background; whether to wait for it is up to the caller
+        let (kill, killed) = oneshot::channel();
+        let completion = tokio::spawn(async move {
+            let status = tokio::select! {
+                status = process.wait() => status,
+                // A dropped kill switch leaves the process running
+                Ok(()) = killed => {
+                    warn!("Killing cursor-cli process");
+                    if let Err(e) = process.start_kill() {
+                        warn!("Failed to kill cursor-cli process: {}", e);
                     }
+                    process.wait().await
                 }
-            });
-        } else {
-            // Wait for the process to complete
-            match process.wait().await {
+            };
+            match status {
                 Ok(status) => {
                     if status.success() {
                         info!("cursor-cli process completed successfully");
                     } else {
                         warn!("cursor-cli process exited with non-zero status: {:?}", status.code());
                     }
+                    Ok(status.code())
                 }
                 Err(e) => {
                     error!("Error waiting for cursor-cli process: {}", e);
-                    return Err(anyhow::anyhow!("Failed to wait for cursor-cli process: {}", e));
+                    Err(anyhow::anyhow!("Failed to wait for cursor-cli process: {}", e))
                 }
             }
-        }
+        });
 
-        info!("Successfully spawned cursor-cli subagent");
-        Ok(())
+        info!("Successfully spawned cursor-cli subagent (PID: {:?})", pid);
+        let mut spawned = SpawnedAgent::new(pid, completion).with_kill_switch(kill);
+        if let Some(stdout) = stdout {
+            spawned = spawned.with_output(stdout);
+        }
+        if let Some(stderr) = stderr {
+            spawned = spawned.with_output(stderr);
+        }
+        Ok(spawned)
     }
 
     async fn get_info(&self) -> Result<AgentInfo> {
@@ -178,21 +354,79 @@ impl AgentSpawner for CursorCliAgent {
                         None
                     }
                 })
+                .map(|version| version.trim().to_string())
                 .unwrap_or_else(|| "Unknown version".to_string())
         } else {
             "Not available".to_string()
         };
 
-        Ok(AgentInfo {
-            available,
-            version,
-            description: "Cursor CLI - AI-powered code editor".to_string(),
-        })
+        Ok(AgentInfo::for_agent(self, available, version, "Cursor CLI - AI-powered code editor".to_string()))
     }
 
     fn name(&self) -> &'static str {
         "cursor-cli"
     }
+
+    fn capabilities(&self) -> AgentCapabilities {
+        // An editor window the user works in, given the prompt once on stdin
+        AgentCapabilities {
+            pty: false,
+            headless: false,
+            follow_up_messages: false,
+            model_selection: false,
+            json_output: false,
+            resumable_sessions: false,
+        }
+    }
+
+    fn custom_options_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "profile": {
+                    "type": "string",
+                    "description": "Profile to open the worktree with",
+                },
+                "user_data_dir": {
+                    "type": "string",
+                    "description": "Directory for user data, to run an instance separate from the user's",
+                },
+                "disable_extensions": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Start without extensions",
+                },
+                "log_level": {
+                    "type": "string",
+                    "enum": ["trace", "debug", "info", "warn", "error", "critical", "off"],
+                    "description": "Log level of the instance",
+                },
+            },
+            "additionalProperties": false,
+        })
+    }
+}
+
+/// Arguments of `cursor-cli` for its custom options and raw arguments
+///
+/// The options are validated before spawning, so values of the wrong type are not expected here.
+fn cursor_args(options: &AgentOptions) -> Vec<String> {
+    let custom = &options.custom_options;
+    let mut args = Vec::new();
+    if let Some(profile) = custom.get("profile").and_then(Value::as_str) {
+        args.extend(["--profile".to_string(), profile.to_string()]);
+    }
+    if let Some(dir) = custom.get("user_data_dir").and_then(Value::as_str) {
+        args.extend(["--user-data-dir".to_string(), dir.to_string()]);
+    }
+    if custom.get("disable_extensions").and_then(Value::as_bool).unwrap_or(false) {
+        args.push("--disable-extensions".to_string());
+    }
+    if let Some(level) = custom.get("log_level").and_then(Value::as_str) {
+        args.extend(["--log".to_string(), level.to_string()]);
+    }
+    args.extend(options.extra_args.iter().cloned());
+    args
 }
 
 /// Handles spawning of subagent processes with support for multiple agent types
@@ -218,6 +452,50 @@ impl SubagentSpawner {
         &self.agents
     }
 
+    /// Registered agents that are available on the system
+    pub async fn available_agents(&self) -> Vec<&dyn AgentSpawner> {
+        let mut available = Vec::new();
+        for agent in &self.agents {
+            match agent.is_available().await {
+                Ok(true) => available.push(agent.as_ref()),
+                Ok(false) => debug!("Agent '{}' is not available", agent.name()),
+                Err(e) => warn!("Failed to check whether agent '{}' is available: {:#}", agent.name(), e),
+            }
+        }
+        available
+    }
+
+    /// Check that an agent is registered and available on the system
+    pub async fn ensure_agent_available(&self, agent_name: &str) -> Result<()> {
+        let agent = self.agents.iter()
+            .find(|a| a.name() == agent_name)
+            .ok_or_else(|| anyhow::anyhow!("Agent '{}' not found", agent_name))?;
+
+        if !agent.is_available().await? {
+            return Err(anyhow::anyhow!("Agent '{}' is not available on this system", agent_name));
+        }
+
+        Ok(())
+    }
+
+    /// Check options for an agent before spawning it, including that the agent supports what they ask for
+    pub fn validate_options(&self, agent_name: &str, options: &AgentOptions) -> Result<()> {
+        let agent = self.agents.iter()
+            .find(|a| a.name() == agent_name)
+            .ok_or_else(|| anyhow::anyhow!("Agent '{}' not found", agent_name))?;
+
+        check_capabilities(agent.as_ref(), options)?;
+        agent.validate_options(options)
+    }
+
+    /// Capabilities of a registered agent
+    pub fn capabilities(&self, agent_name: &str) -> Result<AgentCapabilities> {
+        self.agents.iter()
+            .find(|a| a.name() == agent_name)
+            .map(|agent| agent.capabilities())
+            .ok_or_else(|| anyhow::anyhow!("Agent '{}' not found", agent_name))
+    }
+
     /// Spawn an agent by name
     pub async fn spawn_agent(
         &self,
@@ -225,24 +503,76 @@ impl SubagentSpawner {
         worktree_path: &Path,
         prompt: &str,
         options: &AgentOptions,
-    ) -> Result<()> {
+    ) -> Result<SpawnedAgent> {
         let agent = self.agents.iter()
             .find(|a| a.name() == agent_name)
             .ok_or_else(|| anyhow::anyhow!("Agent '{}' not found", agent_name))?;
 
+        check_capabilities(agent.as_ref(), options)?;
+        agent.validate_options(options)?;
         agent.spawn(worktree_path, prompt, options).await
     }
 
-    /// List all available agents
+    /// Describe all registered agents, including the ones that are not available on the system
     pub async fn list_available_agents(&self) -> Result<Vec<AgentInfo>> {
-        let mut available_agents = Vec::new();
+        let mut agents = Vec::new();
         
         for agent in &self.agents {
-            if let Ok(info) = agent.get_info().await {
-                available_agents.push(info);
-            }
+            let info = match agent.get_info().await {
+                Ok(info) => info,
+                Err(e) => {
+                    warn!("Failed to get information about agent '{}': {:#}", agent.name(), e);
+                    AgentInfo::for_agent(
+                        agent.as_ref(),
+                        false,
+                        "Unknown version".to_string(),
+                        format!("Failed to get information about the agent: {:#}", e),
+                    )
+                }
+            };
+            agents.push(info);
         }
         
-        Ok(available_agents)
+        Ok(agents)
     }
 }
+
+/// Fail if the options ask for something the agent does not support
+fn check_capabilities(agent: &dyn AgentSpawner, options: &AgentOptions) -> Result<()> {
+    let missing = agent.capabilities().missing_for(options);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "Agent '{}' does not support {}; use list_agents to find an agent that does",
+        agent.name(),
+        missing.join(", ")
+    ))
+}
+
+/// Result of an agent run, parsed from its JSON output
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentRunResult {
+    /// Final message of the agent
+    pub result: Option<String>,
+    /// Session the run can be resumed from
+    pub session_id: Option<String>,
+    /// Whether the agent reported the run as failed
+    pub is_error: bool,
+}
+
+/// Parse the result of a run from the JSON lines an agent wrote
+///
+/// The last line with a `result` or `session_id` wins; lines that are not JSON objects, such
+/// as log messages on stderr, are ignored. Returns `None` if no line reports a result.
+pub fn parse_json_output(output: &str) -> Option<AgentRunResult> {
+    output.lines().rev()
+        .filter_map(|line| serde_json::from_str::<Value>(line.trim()).ok())
+        .filter(|event| event.get("result").is_some() || event.get("session_id").is_some())
+        .map(|event| AgentRunResult {
+            result: event.get("result").and_then(Value::as_str).map(str::to_string),
+            session_id: event.get("session_id").and_then(Value::as_str).map(str::to_string),
+            is_error: event.get("is_error").and_then(Value::as_bool).unwrap_or(false),
+        })
+        .next()
+}
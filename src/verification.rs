@@ -0,0 +1,94 @@
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+use tracing::info;
+
+use crate::command_runner::{self, CommandOutput, DEFAULT_COMMAND_TIMEOUT_SECS};
+use crate::config::CommandSettings;
+use crate::git_operations::GitWorktreeManager;
+use crate::registry::{unix_now, RunRecord};
+
+/// What to run in a worktree: a named task from the configuration, or an allow-listed command
+#[derive(Debug, Clone)]
+pub enum RunTarget {
+    Task(String),
+    Command(String),
+}
+
+/// Result of running a command in a worktree
+#[derive(Debug, Clone)]
+pub struct WorktreeRun {
+    /// Summary of the run, as stored in the registry
+    pub record: RunRecord,
+    /// Captured output of the command
+    pub output: CommandOutput,
+}
+
+/// Resolve what to run to a shell command, refusing commands that are not allow-listed
+///
+/// Returns the task name, if any, and the command.
+pub fn resolve_command(settings: &CommandSettings, target: &RunTarget) -> Result<(Option<String>, String)> {
+    match target {
+        RunTarget::Task(name) => match settings.tasks.get(name) {
+            Some(command) => Ok((Some(name.clone()), command.clone())),
+            None => Err(anyhow::anyhow!(
+                "Unknown task '{}'; configured tasks: {}",
+                name,
+                if settings.tasks.is_empty() {
+                    "none".to_string()
+                } else {
+                    settings.tasks.keys().cloned().collect::<Vec<_>>().join(", ")
+                }
+            )),
+        },
+        RunTarget::Command(command) => {
+            // Commands run through the shell, so only exact matches are safe to allow
+            if let Some((name, _)) = settings.tasks.iter().find(|(_, task)| *task == command) {
+                return Ok((Some(name.clone()), command.clone()));
+            }
+            if settings.allowed_commands.iter().any(|allowed| allowed == command) {
+                return Ok((None, command.clone()));
+            }
+            Err(anyhow::anyhow!(
+                "Command '{}' is not allowed; add it to commands.allowed_commands or run a configured task",
+                command
+            ))
+        }
+    }
+}
+
+/// Run an allow-listed command in a worktree, capturing its output
+///
+/// A failing or timed-out command is reported in the result, not as an error.
+pub async fn run_in_worktree(
+    git_manager: &GitWorktreeManager,
+    worktree_path: &Path,
+    settings: &CommandSettings,
+    target: &RunTarget,
+    timeout_secs: Option<u64>,
+) -> Result<WorktreeRun> {
+    let (task, command) = resolve_command(settings, target)?;
+
+    // Record what was tested, so a green run can be told apart from one on older commits
+    let commit = git_manager.resolve_commit(worktree_path, "HEAD").await.ok();
+    let dirty = git_manager.has_uncommitted_changes(worktree_path).await?;
+
+    let timeout = Duration::from_secs(
+        timeout_secs.or(settings.timeout_secs).unwrap_or(DEFAULT_COMMAND_TIMEOUT_SECS),
+    );
+    info!("Running '{}' in {}", command, worktree_path.display());
+    let output = command_runner::run_shell_command(&command, worktree_path, timeout).await?;
+
+    let record = RunRecord {
+        task,
+        command,
+        success: output.success(),
+        exit_code: output.exit_code,
+        timed_out: output.timed_out,
+        duration_ms: output.duration_ms,
+        commit,
+        dirty,
+        finished_at: unix_now(),
+    };
+    Ok(WorktreeRun { record, output })
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+
+use subagent_worktree_mcp::branch_naming::{slugify, BranchNamingPolicy};
+
+#[test]
+fn test_generate_branch_name_from_default_template() -> Result<()> {
+    // Test: Verify branch names are generated from the agent, prompt and subagent ID
+    // This test ensures branch_name can be omitted when spawning
+    
+    let policy = BranchNamingPolicy::default();
+    
+    let name = policy.generate("cursor-cli", "Implement user authentication (OAuth2)!", "1a2b3c4d")?;
+    
+    assert_eq!(name, "subagent/cursor-cli/implement-user-authentication-oauth2-1a2b3c4d");
+    
+    Ok(())
+}
+
+#[test]
+fn test_generate_branch_name_respects_max_length() -> Result<()> {
+    // Test: Verify generated names are shortened to the maximum length by trimming the slug
+    // This test ensures generated names always satisfy the length policy
+    
+    let policy = BranchNamingPolicy {
+        max_length: Some(40),
+        ..Default::default()
+    };
+    
+    let name = policy.generate("cursor-cli", "Refactor the entire billing module for clarity", "1a2b3c4d")?;
+    
+    assert!(name.len() <= 40, "'{}' should fit in 40 characters", name);
+    assert!(name.starts_with("subagent/cursor-cli/refactor"), "Slug should be shortened, not dropped: {}", name);
+    assert!(name.ends_with("-1a2b3c4d"), "Short ID should be kept: {}", name);
+    
+    Ok(())
+}
+
+#[test]
+fn test_generate_branch_name_with_empty_slug() -> Result<()> {
+    // Test: Verify prompts without usable characters don't produce malformed names
+    // This test ensures generated names are always valid refnames
+    
+    let policy = BranchNamingPolicy::default();
+    
+    let name = policy.generate("cursor-cli", "???", "1a2b3c4d")?;
+    
+    assert_eq!(name, "subagent/cursor-cli/1a2b3c4d");
+    
+    Ok(())
+}
+
+#[test]
+fn test_custom_template_and_unknown_placeholder() -> Result<()> {
+    // Test: Verify custom templates are rendered and unknown placeholders are rejected
+    // This test ensures template typos are reported instead of ending up in branch names
+    
+    let policy = BranchNamingPolicy {
+        template: "ai/{short_id}/{slug(prompt)}".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(policy.generate("cursor-cli", "Fix bug", "abcd")?, "ai/abcd/fix-bug");
+    
+    let policy = BranchNamingPolicy {
+        template: "ai/{ticket}".to_string(),
+        ..Default::default()
+    };
+    assert!(policy.generate("cursor-cli", "Fix bug", "abcd").is_err(), "Unknown placeholder should fail");
+    
+    Ok(())
+}
+
+#[test]
+fn test_validate_branch_name() -> Result<()> {
+    // Test: Verify invalid refnames and policy violations are rejected before touching git
+    // This test ensures bad branch names fail early with a clear message
+    
+    let policy = BranchNamingPolicy::default();
+    assert!(policy.validate("feature/login").is_ok());
+    for invalid in ["", "has space", "double..dot", "ends.lock", "trailing/", "-leading-dash", "tilde~1"] {
+        assert!(policy.validate(invalid).is_err(), "'{}' should be rejected", invalid);
+    }
+    
+    let policy = BranchNamingPolicy {
+        required_prefix: Some("ai/".to_string()),
+        max_length: Some(12),
+        ..Default::default()
+    };
+    assert!(policy.validate("ai/login").is_ok());
+    assert!(policy.validate("feature/login").is_err(), "Missing prefix should be rejected");
+    assert!(policy.validate("ai/very-long-name").is_err(), "Too long name should be rejected");
+    
+    Ok(())
+}
+
+#[test]
+fn test_slugify() {
+    // Test: Verify free text is turned into short lowercase slugs
+    // This test ensures prompts produce readable branch name components
+    
+    assert_eq!(slugify("Add  a NEW feature: caching"), "add-a-new-feature-caching");
+    assert_eq!(slugify("one two three four five six seven eight"), "one-two-three-four-five-six");
+    assert!(slugify(&"x".repeat(100)).len() <= 40);
+    assert_eq!(slugify(""), "");
+}
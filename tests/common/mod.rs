@@ -0,0 +1,68 @@
+// Helpers shared by the integration tests; not every test file uses all of them
+#![allow(dead_code)]
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Content of the README committed by [`init_git_repo`]
+const README: &str = "# Test Repository\n";
+
+/// Test helper to create a temporary git repository
+pub fn create_temp_git_repo() -> Result<(TempDir, PathBuf)> {
+    create_temp_git_repo_with_readme(README)
+}
+
+/// Test helper to create a temporary git repository whose initial commit has the given README
+pub fn create_temp_git_repo_with_readme(readme: &str) -> Result<(TempDir, PathBuf)> {
+    let temp_dir = TempDir::new()?;
+    let repo_path = temp_dir.path().join("test_repo");
+    init_git_repo_with_readme(&repo_path, readme)?;
+    Ok((temp_dir, repo_path))
+}
+
+/// Test helper to initialize a git repository with an initial commit
+pub fn init_git_repo(repo_path: &Path) -> Result<()> {
+    init_git_repo_with_readme(repo_path, README)
+}
+
+fn init_git_repo_with_readme(repo_path: &Path, readme: &str) -> Result<()> {
+    // Create directory
+    std::fs::create_dir_all(repo_path)?;
+
+    // Initialize git repository
+    let output = std::process::Command::new("git")
+        .args(["init", "-b", "main"])
+        .current_dir(repo_path)
+        .output()?;
+
+    assert!(output.status.success(), "Failed to initialize git repository");
+
+    for (key, value) in [("user.name", "Test User"), ("user.email", "test@example.com")] {
+        let output = std::process::Command::new("git")
+            .args(["config", key, value])
+            .current_dir(repo_path)
+            .output()?;
+
+        assert!(output.status.success(), "Failed to configure git identity");
+    }
+
+    // Create initial commit
+    std::fs::write(repo_path.join("README.md"), readme)?;
+
+    let output = std::process::Command::new("git")
+        .args(["add", "README.md"])
+        .current_dir(repo_path)
+        .output()?;
+
+    assert!(output.status.success(), "Failed to add README.md");
+
+    let output = std::process::Command::new("git")
+        .args(["commit", "-m", "Initial commit"])
+        .current_dir(repo_path)
+        .output()?;
+
+    assert!(output.status.success(), "Failed to create initial commit");
+
+    Ok(())
+}
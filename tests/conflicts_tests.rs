@@ -0,0 +1,205 @@
+mod common;
+
+use anyhow::Result;
+use std::path::Path;
+use tokio::sync::Mutex;
+
+use subagent_worktree_mcp::conflicts::{predict_conflicts, ConflictOptions};
+use subagent_worktree_mcp::git_operations::GitWorktreeManager;
+use subagent_worktree_mcp::registry::{self, SubagentRecord, SubagentRegistry};
+
+use common::create_temp_git_repo_with_readme;
+
+/// README content with enough lines for separate hunks
+const NUMBERED_LINES: &str = "line 1\nline 2\nline 3\nline 4\nline 5\nline 6\nline 7\nline 8\nline 9\nline 10\n";
+
+/// Test helper to create a worktree and register it as a subagent forked from main
+async fn spawn_worktree(
+    manager: &GitWorktreeManager,
+    registry: &Mutex<SubagentRegistry>,
+    branch: &str,
+) -> Result<std::path::PathBuf> {
+    let created = manager
+        .create_worktree_with_options(branch, Some("main"), None, &Default::default())
+        .await?;
+
+    let now = registry::unix_now();
+    registry.lock().await.insert(SubagentRecord {
+        id: registry::generate_id(),
+        branch: branch.to_string(),
+        worktree_path: created.path.clone(),
+        base_ref: created.base_ref,
+        base_commit: Some(created.base_commit),
+        agent_type: "cursor-cli".to_string(),
+        prompt: "test prompt".to_string(),
+        created_at: now,
+        last_activity: now,
+        last_run: None,
+        agent_pid: None,
+    })?;
+
+    Ok(created.path)
+}
+
+/// Test helper to replace one line of the README
+fn edit_line(dir: &Path, line: usize, content: &str) -> Result<()> {
+    let readme = std::fs::read_to_string(dir.join("README.md"))?;
+    let mut lines: Vec<String> = readme.lines().map(String::from).collect();
+    lines[line - 1] = content.to_string();
+    std::fs::write(dir.join("README.md"), lines.join("\n") + "\n")?;
+    Ok(())
+}
+
+/// Test helper to commit all changes in a directory
+fn commit_all(dir: &Path, message: &str) -> Result<()> {
+    for args in [vec!["add", "-A"], vec!["commit", "-m", message]] {
+        let output = std::process::Command::new("git")
+            .args(&args)
+            .current_dir(dir)
+            .output()?;
+        assert!(output.status.success(), "Failed to commit in {}", dir.display());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_predict_conflicts_between_subagents() -> Result<()> {
+    // Test: Verify two subagents editing the same line are reported as conflicting, including uncommitted work
+    // This test ensures the orchestrator learns about clashes before the work is integrated
+
+    let (_temp_dir, repo_path) = create_temp_git_repo_with_readme(NUMBERED_LINES)?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let first = spawn_worktree(&manager, &registry, "first").await?;
+    let second = spawn_worktree(&manager, &registry, "second").await?;
+    edit_line(&first, 3, "first agent")?;
+    commit_all(&first, "First agent work")?;
+    edit_line(&second, 3, "second agent")?;
+
+    let report = predict_conflicts(&manager, &registry, &ConflictOptions::default()).await?;
+
+    assert_eq!(report.subagents.len(), 2);
+    let second_changes = report.subagents.iter().find(|s| s.branch == "second").unwrap();
+    assert!(second_changes.uncommitted, "Uncommitted changes should be detected");
+    assert_eq!(second_changes.changed_files, vec!["README.md".to_string()]);
+
+    assert_eq!(report.between_subagents.len(), 1);
+    let prediction = &report.between_subagents[0];
+    assert_eq!(prediction.overlapping_files.len(), 1);
+    assert_eq!(prediction.overlapping_files[0].hunks_overlap, Some(true));
+    assert_eq!(prediction.conflicting_files, vec!["README.md".to_string()]);
+    assert!(report.with_base.is_empty(), "The base has not moved");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_predict_conflicts_separate_lines_merge_cleanly() -> Result<()> {
+    // Test: Verify edits to distant lines of the same file are reported as an overlap without conflicts
+    // This test ensures shared files are flagged without crying wolf about conflicts
+
+    let (_temp_dir, repo_path) = create_temp_git_repo_with_readme(NUMBERED_LINES)?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let first = spawn_worktree(&manager, &registry, "first").await?;
+    let second = spawn_worktree(&manager, &registry, "second").await?;
+    edit_line(&first, 1, "first agent")?;
+    std::fs::write(first.join("first.txt"), "only first\n")?;
+    edit_line(&second, 10, "second agent")?;
+
+    let report = predict_conflicts(&manager, &registry, &ConflictOptions::default()).await?;
+
+    assert_eq!(report.between_subagents.len(), 1);
+    let prediction = &report.between_subagents[0];
+    assert_eq!(prediction.overlapping_files.len(), 1, "Only the shared file should overlap");
+    assert_eq!(prediction.overlapping_files[0].path, "README.md");
+    assert_eq!(prediction.overlapping_files[0].hunks_overlap, Some(false));
+    assert!(prediction.conflicting_files.is_empty(), "Separate lines should merge cleanly");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_predict_conflicts_with_moved_base() -> Result<()> {
+    // Test: Verify a subagent's changes are checked against new commits on its base branch
+    // This test ensures conflicts with work integrated since the spawn are predicted too
+
+    let (_temp_dir, repo_path) = create_temp_git_repo_with_readme(NUMBERED_LINES)?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let worktree = spawn_worktree(&manager, &registry, "feature").await?;
+    edit_line(&worktree, 5, "agent change")?;
+    edit_line(&repo_path, 5, "upstream change")?;
+    commit_all(&repo_path, "Upstream work")?;
+
+    let report = predict_conflicts(&manager, &registry, &ConflictOptions::default()).await?;
+
+    assert!(report.between_subagents.is_empty());
+    assert_eq!(report.with_base.len(), 1);
+    assert_eq!(report.with_base[0].left, "feature");
+    assert_eq!(report.with_base[0].right, "main");
+    assert_eq!(report.with_base[0].conflicting_files, vec!["README.md".to_string()]);
+    assert!(report.summary().contains("CONFLICTS"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_predict_conflicts_skips_unrelated_pair() -> Result<()> {
+    // Test: Verify a pair of subagents without common history is skipped while other pairs are compared
+    // This test ensures one unrelated branch does not hide the conflicts between the others
+
+    let (_temp_dir, repo_path) = create_temp_git_repo_with_readme(NUMBERED_LINES)?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let first = spawn_worktree(&manager, &registry, "first").await?;
+    let second = spawn_worktree(&manager, &registry, "second").await?;
+    edit_line(&first, 3, "first agent")?;
+    edit_line(&second, 3, "second agent")?;
+
+    // A subagent that forked from a commit with no history in common with main
+    let created = manager
+        .create_worktree_with_options("unrelated", Some("main"), None, &Default::default())
+        .await?;
+    let output = std::process::Command::new("git")
+        .args(["checkout", "--orphan", "unrelated-root"])
+        .current_dir(&created.path)
+        .output()?;
+    assert!(output.status.success(), "Failed to create orphan branch");
+    commit_all(&created.path, "Unrelated root")?;
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(&created.path)
+        .output()?;
+    let root_commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    edit_line(&created.path, 3, "unrelated agent")?;
+
+    let now = registry::unix_now();
+    registry.lock().await.insert(SubagentRecord {
+        id: registry::generate_id(),
+        branch: "unrelated".to_string(),
+        worktree_path: created.path.clone(),
+        base_ref: created.base_ref,
+        base_commit: Some(root_commit),
+        agent_type: "cursor-cli".to_string(),
+        prompt: "test prompt".to_string(),
+        created_at: now,
+        last_activity: now,
+        last_run: None,
+        agent_pid: None,
+    })?;
+
+    let report = predict_conflicts(&manager, &registry, &ConflictOptions::default()).await?;
+
+    assert_eq!(report.subagents.len(), 3);
+    assert_eq!(report.between_subagents.len(), 1, "The related pair should still be compared");
+    assert_eq!(report.between_subagents[0].conflicting_files, vec!["README.md".to_string()]);
+    let unrelated_pairs = report.skipped.iter().filter(|skipped| skipped.contains("unrelated")).count();
+    assert_eq!(unrelated_pairs, 2, "Both pairs with the unrelated subagent should be skipped: {:?}", report.skipped);
+
+    Ok(())
+}
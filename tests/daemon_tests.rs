@@ -0,0 +1,190 @@
+// The daemon is only available on Unix
+#![cfg(unix)]
+
+mod common;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::UnixStream;
+
+use subagent_worktree_mcp::daemon::{self, SOCKET_FILE_NAME};
+use subagent_worktree_mcp::server::SubagentWorktreeServer;
+
+use common::create_temp_git_repo;
+
+/// Test helper to start a daemon for a repository, returning its socket
+async fn start_daemon(repo_path: std::path::PathBuf, state_dir: &std::path::Path) -> Result<(std::path::PathBuf, Arc<SubagentWorktreeServer>)> {
+    let server = Arc::new(SubagentWorktreeServer::new(repo_path)?);
+    let socket_path = daemon::socket_path(state_dir);
+    let listener = daemon::bind(&socket_path).await?;
+    tokio::spawn(daemon::serve(listener, server.clone(), server.resource_notifier()));
+    Ok((socket_path, server))
+}
+
+/// A client attached to a daemon
+struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: tokio::net::unix::OwnedWriteHalf,
+}
+
+impl Client {
+    async fn attach(socket_path: &std::path::Path) -> Result<Self> {
+        let (reader, writer) = UnixStream::connect(socket_path).await?.into_split();
+        Ok(Self { lines: BufReader::new(reader).lines(), writer })
+    }
+
+    async fn send(&mut self, message: &Value) -> Result<()> {
+        self.writer.write_all(format!("{}\n", message).as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Value> {
+        let line = tokio::time::timeout(Duration::from_secs(5), self.lines.next_line()).await??;
+        Ok(serde_json::from_str(&line.ok_or_else(|| anyhow::anyhow!("Daemon closed the connection"))?)?)
+    }
+
+    async fn call(&mut self, id: u64, method: &str, params: Value) -> Result<Value> {
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).await?;
+        let response = self.receive().await?;
+        assert_eq!(response["id"], id, "Unexpected response: {}", response);
+        Ok(response["result"].clone())
+    }
+}
+
+#[tokio::test]
+async fn test_clients_attach_and_detach() -> Result<()> {
+    // Test: Clients attach to the daemon over its socket, and the daemon keeps serving after they detach
+    // This test ensures restarting the MCP client does not stop the server that owns the subagents
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let (socket_path, _server) = start_daemon(repo_path, temp_dir.path()).await?;
+    assert_eq!(socket_path, temp_dir.path().join(SOCKET_FILE_NAME));
+
+    let mut first = Client::attach(&socket_path).await?;
+    let initialized = first.call(1, "initialize", json!({ "protocolVersion": "2025-03-26", "capabilities": {} })).await?;
+    assert_eq!(initialized["serverInfo"]["name"], "subagent-worktree-mcp");
+    let result = first.call(2, "tools/call", json!({ "name": "list_worktrees", "arguments": {} })).await?;
+    assert_eq!(result["isError"], false);
+    drop(first);
+
+    let mut second = Client::attach(&socket_path).await?;
+    let tools = second.call(1, "tools/list", json!({})).await?;
+    assert_eq!(tools["tools"].as_array().map(Vec::len), Some(SubagentWorktreeServer::get_tools().len()));
+
+    // Malformed lines are answered with a parse error rather than dropping the client
+    second.writer.write_all(b"not json\n").await?;
+    assert_eq!(second.receive().await?["error"]["code"], -32700);
+    assert_eq!(second.call(2, "ping", json!({})).await?, json!({}));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resource_updates_reach_clients() -> Result<()> {
+    // Test: Updates of subscribed resources are sent to attached clients as notifications
+    // This test ensures clients of the daemon are notified of changes like clients of the stdio server
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let (socket_path, server) = start_daemon(repo_path, temp_dir.path()).await?;
+
+    let mut client = Client::attach(&socket_path).await?;
+    client.call(1, "resources/subscribe", json!({ "uri": "agent://1a2b3c4d/log" })).await?;
+    server.resource_notifier().notify("agent://1a2b3c4d/log").await;
+
+    let notification = client.receive().await?;
+    assert_eq!(notification["method"], "notifications/resources/updated");
+    assert_eq!(notification["params"]["uri"], "agent://1a2b3c4d/log");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resource_subscriptions_are_per_client() -> Result<()> {
+    // Test: Each client only receives updates it subscribed to, and unsubscribing leaves other clients subscribed
+    // This test ensures clients sharing the daemon do not see or cancel each other's subscriptions
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let (socket_path, server) = start_daemon(repo_path, temp_dir.path()).await?;
+    let notifier = server.resource_notifier();
+
+    let mut first = Client::attach(&socket_path).await?;
+    let mut second = Client::attach(&socket_path).await?;
+    first.call(1, "resources/subscribe", json!({ "uri": "agent://1a2b3c4d/log" })).await?;
+    second.call(1, "resources/subscribe", json!({ "uri": "agent://9f8e7d6c/log" })).await?;
+    second.call(2, "resources/subscribe", json!({ "uri": "agent://1a2b3c4d/log" })).await?;
+    second.call(3, "resources/unsubscribe", json!({ "uri": "agent://1a2b3c4d/log" })).await?;
+
+    notifier.notify("agent://9f8e7d6c/log").await;
+    notifier.notify("agent://1a2b3c4d/log").await;
+    assert_eq!(first.receive().await?["params"]["uri"], "agent://1a2b3c4d/log", "Updates of other clients' subscriptions should not be sent");
+    assert_eq!(second.receive().await?["params"]["uri"], "agent://9f8e7d6c/log");
+
+    // A detached client's subscriptions are released
+    drop(second);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while notifier.subscriptions().await.contains(&"agent://9f8e7d6c/log".to_string()) {
+        assert!(tokio::time::Instant::now() < deadline, "Subscriptions of a detached client should be released");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(notifier.subscriptions().await, vec!["agent://1a2b3c4d/log".to_string()]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bind_replaces_stale_socket_only() -> Result<()> {
+    // Test: A socket left behind by an exited daemon is replaced, but a live daemon's socket is not
+    // This test ensures only one daemon owns a repository's subagents at a time
+
+    let temp_dir = TempDir::new()?;
+    let socket_path = daemon::socket_path(temp_dir.path());
+
+    // A socket nobody listens on any more
+    drop(daemon::bind(&socket_path).await?);
+    assert!(socket_path.exists());
+
+    let _listener = daemon::bind(&socket_path).await?;
+    let error = daemon::bind(&socket_path).await.unwrap_err().to_string();
+    assert!(error.contains("already listening"), "Unexpected error: {}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connect_or_start() -> Result<()> {
+    // Test: The proxy starts a daemon when none is listening, and attaches to a running one otherwise
+    // This test ensures the first client starts the daemon and later clients share it
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let socket_path = daemon::socket_path(temp_dir.path());
+
+    let started = std::sync::atomic::AtomicUsize::new(0);
+    let start = || {
+        started.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let repo_path = repo_path.clone();
+        let state_dir = temp_dir.path().to_path_buf();
+        tokio::spawn(async move {
+            // Start listening a little later, as a real daemon would
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let server = Arc::new(SubagentWorktreeServer::new(repo_path)?);
+            let listener = daemon::bind(&daemon::socket_path(&state_dir)).await?;
+            daemon::serve(listener, server.clone(), server.resource_notifier()).await
+        });
+        Ok(())
+    };
+    let stream = daemon::connect_or_start(&socket_path, start).await?;
+    drop(stream);
+    assert_eq!(started.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    daemon::connect_or_start(&socket_path, || Err(anyhow::anyhow!("should not start again"))).await?;
+
+    let error = daemon::connect_or_start(&temp_dir.path().join("other.sock"), || Ok(())).await.unwrap_err().to_string();
+    assert!(error.contains("did not start"), "Unexpected error: {}", error);
+
+    Ok(())
+}
@@ -184,6 +184,16 @@ fn get_implemented_tools() -> HashSet<String> {
     tools.insert("monitor_agents".to_string());
     tools.insert("cleanup_worktree".to_string());
     tools.insert("list_worktrees".to_string());
+    tools.insert("list_agents".to_string());
+    tools.insert("gc_worktrees".to_string());
+    tools.insert("widen_sparse_checkout".to_string());
+    tools.insert("sync_worktree".to_string());
+    tools.insert("add_repository".to_string());
+    tools.insert("checkpoint_worktree".to_string());
+    tools.insert("predict_conflicts".to_string());
+    tools.insert("enqueue_merge".to_string());
+    tools.insert("list_merge_queue".to_string());
+    tools.insert("run_in_worktree".to_string());
     
     tools
 }
@@ -191,17 +201,23 @@ fn get_implemented_tools() -> HashSet<String> {
 fn test_subagent_config_fields() {
     // Test that SubagentConfig has all documented fields
     let config = SubagentConfig {
-        branch_name: "test".to_string(),
-        base_branch: Some("main".to_string()),
+        branch_name: Some("test".to_string()),
+        base_ref: Some("main".to_string()),
         prompt: "test prompt".to_string(),
         worktree_dir: Some("custom-dir".to_string()),
         agent_type: Some("cursor-cli".to_string()),
         agent_options: Some(AgentOptions::default()),
+        sparse_paths: Some(vec!["src".to_string()]),
+        sparse_cone: Some(true),
+        submodules: Some(true),
+        bootstrap: None,
+        keep_on_failure: false,
+        repo: None,
     };
     
     // Verify all documented fields exist
-    assert!(!config.branch_name.is_empty(), "branch_name should be present");
-    assert!(config.base_branch.is_some(), "base_branch should be optional");
+    assert!(config.branch_name.is_some(), "branch_name should be optional");
+    assert!(config.base_ref.is_some(), "base_ref should be optional");
     assert!(!config.prompt.is_empty(), "prompt should be present");
     assert!(config.worktree_dir.is_some(), "worktree_dir should be optional");
     assert!(config.agent_type.is_some(), "agent_type should be optional");
@@ -215,6 +231,7 @@ fn test_cleanup_config_fields() {
         force: true,
         remove_branch: true,
         kill_agents: true,
+        repo: None,
     };
     
     // Verify all documented fields exist
@@ -243,20 +260,26 @@ fn test_agent_monitor_config_fields() {
 fn test_spawn_subagent_schema() {
     // Test that spawn_subagent parameters are correctly typed
     let config = SubagentConfig {
-        branch_name: "required-field".to_string(), // Required
-        base_branch: None, // Optional
+        branch_name: None, // Optional, generated if omitted
+        base_ref: None, // Optional
         prompt: "required-field".to_string(), // Required
         worktree_dir: None, // Optional
         agent_type: None, // Optional
         agent_options: None, // Optional
+        sparse_paths: None, // Optional
+        sparse_cone: None, // Optional
+        submodules: None, // Optional
+        bootstrap: None, // Optional
+        keep_on_failure: false, // Optional
+        repo: None, // Optional
     };
     
     // Verify required fields are not optional
-    assert!(!config.branch_name.is_empty(), "branch_name should be required");
     assert!(!config.prompt.is_empty(), "prompt should be required");
     
     // Verify optional fields can be None
-    assert!(config.base_branch.is_none(), "base_branch should be optional");
+    assert!(config.branch_name.is_none(), "branch_name should be optional");
+    assert!(config.base_ref.is_none(), "base_ref should be optional");
     assert!(config.worktree_dir.is_none(), "worktree_dir should be optional");
     assert!(config.agent_type.is_none(), "agent_type should be optional");
     assert!(config.agent_options.is_none(), "agent_options should be optional");
@@ -269,6 +292,7 @@ fn test_cleanup_worktree_schema() {
         force: false, // Optional with default
         remove_branch: false, // Optional with default
         kill_agents: false, // Optional with default
+        repo: None, // Optional
     };
     
     // Verify required field is not optional
@@ -0,0 +1,318 @@
+mod common;
+
+use anyhow::Result;
+use subagent_worktree_mcp::mcp::server::RequestHandler;
+use subagent_worktree_mcp::mcp::types::CallToolRequest;
+use serde_json::json;
+use tempfile::TempDir;
+use tokio::sync::Mutex;
+
+use subagent_worktree_mcp::gc::{collect_garbage, GcOptions, GcOutcome, GcPolicy, GcReason};
+use subagent_worktree_mcp::git_operations::{subagent_lock_reason, GitWorktreeManager};
+use subagent_worktree_mcp::registry::{self, SubagentRecord, SubagentRegistry};
+use subagent_worktree_mcp::server::SubagentWorktreeServer;
+
+use common::create_temp_git_repo;
+
+/// Test helper to build a registry record for a worktree
+fn record_for(branch: &str, worktree_path: &std::path::Path) -> SubagentRecord {
+    let now = registry::unix_now();
+    SubagentRecord {
+        id: registry::generate_id(),
+        branch: branch.to_string(),
+        worktree_path: worktree_path.to_path_buf(),
+        base_ref: Some("main".to_string()),
+        base_commit: None,
+        agent_type: "cursor-cli".to_string(),
+        prompt: "test prompt".to_string(),
+        created_at: now,
+        last_activity: now,
+        last_run: None,
+        agent_pid: None,
+    }
+}
+
+fn options(policy: GcPolicy) -> GcOptions {
+    GcOptions {
+        policy,
+        idle_ttl_secs: None,
+        base_branch: None,
+    }
+}
+
+#[tokio::test]
+async fn test_registry_persists_records() -> Result<()> {
+    // Test: Verify registry records survive reloading from the state directory
+    // This test ensures spawned subagents are remembered across server restarts
+
+    let temp_dir = TempDir::new()?;
+    let record = record_for("feature", &temp_dir.path().join("feature"));
+    let id = record.id.clone();
+
+    let mut registry = SubagentRegistry::load(temp_dir.path())?;
+    registry.insert(record)?;
+
+    let mut reloaded = SubagentRegistry::load(temp_dir.path())?;
+    assert!(reloaded.get(&id).is_some(), "Record should be persisted");
+
+    reloaded.remove(&id)?;
+    let reloaded = SubagentRegistry::load(temp_dir.path())?;
+    assert!(reloaded.get(&id).is_none(), "Removed record should stay removed");
+
+    Ok(())
+}
+
+#[test]
+fn test_registry_writers_keep_each_others_records() -> Result<()> {
+    // Test: Verify registries of the same state directory loaded by different servers do not overwrite each other
+    // This test ensures a daemon and an HTTP server serving one repository never lose subagent records
+
+    let temp_dir = TempDir::new()?;
+    let mut first = SubagentRegistry::load(temp_dir.path())?;
+    let mut second = SubagentRegistry::load(temp_dir.path())?;
+
+    let (one, two, three) = (
+        record_for("one", &temp_dir.path().join("one")),
+        record_for("two", &temp_dir.path().join("two")),
+        record_for("three", &temp_dir.path().join("three")),
+    );
+    let ids = [one.id.clone(), two.id.clone(), three.id.clone()];
+    first.insert(one)?;
+    second.insert(two)?;
+    first.insert(three)?;
+    second.remove(&ids[0])?;
+
+    let reloaded = SubagentRegistry::load(temp_dir.path())?;
+    assert!(reloaded.get(&ids[0]).is_none(), "Record removed by the second writer should stay removed");
+    assert!(reloaded.get(&ids[1]).is_some() && reloaded.get(&ids[2]).is_some(), "Records of both writers should be kept");
+    assert_eq!(second.records().count(), 2, "A writer should see the records of the other after writing");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gc_report_only_reports_orphaned_worktree() -> Result<()> {
+    // Test: Verify report-only collection detects unregistered worktrees without touching them
+    // This test ensures the default policy is non-destructive
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let worktree_path = manager.create_worktree("orphan", None, None).await?;
+
+    let report = collect_garbage(&manager, &registry, &options(GcPolicy::ReportOnly)).await?;
+
+    assert_eq!(report.actions.len(), 1, "Orphaned worktree should be reported");
+    assert_eq!(report.actions[0].reasons, vec![GcReason::Orphaned]);
+    assert_eq!(report.actions[0].outcome, GcOutcome::Reported);
+    assert!(worktree_path.exists(), "Report-only must not remove the worktree");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gc_remove_keeps_unmerged_branch() -> Result<()> {
+    // Test: Verify the remove policy removes orphaned worktrees but keeps unmerged branches
+    // This test ensures committed work is never lost by garbage collection
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let worktree_path = manager.create_worktree("orphan", None, None).await?;
+    std::fs::write(worktree_path.join("work.txt"), "work\n")?;
+    for args in [vec!["add", "work.txt"], vec!["commit", "-m", "Work"]] {
+        let output = std::process::Command::new("git")
+            .args(&args)
+            .current_dir(&worktree_path)
+            .output()?;
+        assert!(output.status.success(), "Failed to commit in worktree");
+    }
+
+    let report = collect_garbage(&manager, &registry, &options(GcPolicy::Remove)).await?;
+
+    assert_eq!(report.actions.len(), 1, "Orphaned worktree should be collected");
+    assert_eq!(report.actions[0].outcome, GcOutcome::Removed { branch_deleted: false });
+    assert!(!worktree_path.exists(), "Worktree should be removed");
+
+    let output = std::process::Command::new("git")
+        .args(&["rev-parse", "--verify", "orphan"])
+        .current_dir(&repo_path)
+        .output()?;
+    assert!(output.status.success(), "Unmerged branch should be kept");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gc_ignores_active_registered_worktree() -> Result<()> {
+    // Test: Verify registered, recently active, unmerged worktrees are left alone
+    // This test ensures garbage collection does not interfere with working subagents
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let worktree_path = manager.create_worktree("active", None, None).await?;
+    registry.lock().await.insert(record_for("active", &worktree_path))?;
+
+    let mut gc_options = options(GcPolicy::Remove);
+    gc_options.idle_ttl_secs = Some(3600);
+    let report = collect_garbage(&manager, &registry, &gc_options).await?;
+
+    assert!(report.actions.is_empty(), "Active worktree should not be collected");
+    assert!(worktree_path.exists(), "Active worktree should still exist");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gc_prunes_deleted_worktree_and_stale_record() -> Result<()> {
+    // Test: Verify worktrees whose directories were deleted are pruned with their registry entries
+    // This test ensures the equivalent of `git worktree prune` is run
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let worktree_path = manager.create_worktree("deleted", None, None).await?;
+    let record = record_for("deleted", &worktree_path);
+    let id = record.id.clone();
+    registry.lock().await.insert(record)?;
+    std::fs::remove_dir_all(&worktree_path)?;
+
+    let report = collect_garbage(&manager, &registry, &options(GcPolicy::Remove)).await?;
+
+    assert_eq!(report.pruned.len(), 1, "Deleted worktree should be pruned");
+    assert_eq!(report.stale_records, vec![id.clone()]);
+    assert!(registry.lock().await.get(&id).is_none(), "Stale record should be removed");
+    assert_eq!(manager.list_worktrees().await?.len(), 1, "Only the main worktree should remain");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gc_ignores_user_worktrees() -> Result<()> {
+    // Test: Verify worktrees not created by the server are never collected
+    // This test ensures a developer's own worktrees are safe
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let user_worktree = temp_dir.path().join("user-worktree");
+    let output = std::process::Command::new("git")
+        .args(&["worktree", "add", "-b", "user-branch"])
+        .arg(&user_worktree)
+        .current_dir(&repo_path)
+        .output()?;
+    assert!(output.status.success(), "Failed to create user worktree");
+
+    let report = collect_garbage(&manager, &registry, &options(GcPolicy::Remove)).await?;
+
+    assert!(report.actions.is_empty(), "User worktree should not be collected");
+    assert!(user_worktree.exists(), "User worktree should still exist");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gc_skips_locked_worktree() -> Result<()> {
+    // Test: Verify locked worktrees are reported but never removed
+    // This test ensures garbage collection respects locks held by running agents
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let worktree_path = manager.create_worktree("locked-orphan", None, None).await?;
+    // The test process stands in for a running agent
+    let reason = subagent_lock_reason("abcd1234", Some(std::process::id()));
+    manager.lock_worktree(&worktree_path, &reason).await?;
+
+    let report = collect_garbage(&manager, &registry, &options(GcPolicy::Remove)).await?;
+
+    assert_eq!(report.actions.len(), 1, "Orphaned worktree should be considered");
+    assert!(
+        matches!(&report.actions[0].outcome, GcOutcome::Skipped { reason } if reason.contains("locked")),
+        "Locked worktree should be skipped: {:?}",
+        report.actions[0].outcome
+    );
+    assert!(worktree_path.exists(), "Locked worktree should still exist");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gc_releases_lock_of_exited_agent() -> Result<()> {
+    // Test: Verify a worktree locked for an agent that is no longer running is collected
+    // This test ensures locks left behind by a server restart do not keep worktrees forever
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let registry = Mutex::new(SubagentRegistry::load(&manager.state_dir()?)?);
+
+    let mut exited = std::process::Command::new("true").spawn()?;
+    let pid = exited.id();
+    exited.wait()?;
+
+    let worktree_path = manager.create_worktree("stale-lock", None, None).await?;
+    manager.lock_worktree(&worktree_path, &subagent_lock_reason("abcd1234", Some(pid))).await?;
+
+    let worktree = manager.list_worktrees().await?.into_iter()
+        .find(|w| w.branch.as_deref() == Some("stale-lock"))
+        .expect("worktree should be listed");
+    assert_eq!(worktree.lock_pid(), Some(pid));
+    assert!(worktree.has_stale_lock(), "Lock of an exited agent should be stale");
+
+    let report = collect_garbage(&manager, &registry, &options(GcPolicy::ReportOnly)).await?;
+    assert!(matches!(report.actions[0].outcome, GcOutcome::Reported), "Stale lock should not block collection");
+    assert!(worktree_path.exists(), "Report only should leave the worktree");
+
+    let report = collect_garbage(&manager, &registry, &options(GcPolicy::Remove)).await?;
+    assert!(
+        matches!(report.actions[0].outcome, GcOutcome::Removed { .. }),
+        "Worktree with a stale lock should be removed: {:?}",
+        report.actions[0].outcome
+    );
+    assert!(!worktree_path.exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cleanup_kills_only_recorded_agent() -> Result<()> {
+    // Test: Verify cleanup with kill_agents kills the subagent's recorded agent and nothing else in the worktree
+    // This test ensures cleaning up a worktree never kills the user's own programs running there
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    let worktree_path = manager.create_worktree("kill-agent", None, None).await?;
+
+    let mut agent = std::process::Command::new("sleep").arg("30").current_dir(&worktree_path).spawn()?;
+    let mut other = std::process::Command::new("sleep").arg("30").current_dir(&worktree_path).spawn()?;
+    let mut record = record_for("kill-agent", &worktree_path);
+    record.agent_pid = Some(agent.id());
+    SubagentRegistry::load(&manager.state_dir()?)?.insert(record)?;
+
+    let server = SubagentWorktreeServer::new(repo_path)?;
+    let result = server.call_tool(CallToolRequest {
+        name: "cleanup_worktree".to_string(),
+        arguments: json!({ "worktree_name": "kill-agent", "kill_agents": true, "force": true }),
+    }).await;
+    let other_status = other.try_wait()?;
+    let _ = other.kill();
+    let _ = other.wait();
+    assert!(result.is_ok(), "Cleanup should succeed");
+
+    assert!(other_status.is_none(), "Processes that are not the recorded agent should be left running");
+    let started = std::time::Instant::now();
+    while agent.try_wait()?.is_none() {
+        assert!(started.elapsed().as_secs() < 5, "Recorded agent should be killed");
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert!(!worktree_path.exists());
+
+    Ok(())
+}
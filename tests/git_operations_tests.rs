@@ -1,44 +1,14 @@
+mod common;
+
 use anyhow::Result;
 use tempfile::TempDir;
 use std::path::Path;
 
-use subagent_worktree_mcp::git_operations::GitWorktreeManager;
+use subagent_worktree_mcp::git_operations::{
+    CheckpointOptions, GitIdentity, GitWorktreeManager, SparseCheckout, SyncOutcome, SyncStrategy, WorktreeOptions,
+};
 
-/// Test helper to create a temporary git repository
-fn create_temp_git_repo() -> Result<(TempDir, std::path::PathBuf)> {
-    let temp_dir = TempDir::new()?;
-    let repo_path = temp_dir.path().join("test_repo");
-    
-    // Create directory
-    std::fs::create_dir(&repo_path)?;
-    
-    // Initialize git repository
-    let output = std::process::Command::new("git")
-        .arg("init")
-        .current_dir(&repo_path)
-        .output()?;
-    
-    assert!(output.status.success(), "Failed to initialize git repository");
-    
-    // Create initial commit
-    std::fs::write(repo_path.join("README.md"), "# Test Repository\n")?;
-    
-    let output = std::process::Command::new("git")
-        .args(&["add", "README.md"])
-        .current_dir(&repo_path)
-        .output()?;
-    
-    assert!(output.status.success(), "Failed to add README.md");
-    
-    let output = std::process::Command::new("git")
-        .args(&["commit", "-m", "Initial commit"])
-        .current_dir(&repo_path)
-        .output()?;
-    
-    assert!(output.status.success(), "Failed to create initial commit");
-    
-    Ok((temp_dir, repo_path))
-}
+use common::create_temp_git_repo;
 
 #[tokio::test]
 async fn test_git_worktree_manager_invalid_path() -> Result<()> {
@@ -184,7 +154,7 @@ async fn test_list_worktrees_corrupted_repo() -> Result<()> {
     // This test ensures proper error handling for repository corruption
     
     let (_temp_dir, repo_path) = create_temp_git_repo()?;
-    let manager = GitWorktreeManager::new(repo_path)?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
     
     // Corrupt the git directory by removing important files
     let git_dir = repo_path.join(".git");
@@ -307,3 +277,720 @@ async fn test_git_operations_malformed_git_config() -> Result<()> {
     
     Ok(())
 }
+
+#[tokio::test]
+async fn test_find_worktrees_by_branch_directory_and_path() -> Result<()> {
+    // Test: Verify worktrees can be found by branch name, directory name, or path
+    // This test ensures cleanup resolves worktrees via git rather than guessing paths
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    
+    let worktree_path = manager.create_worktree("find-branch", None, Some("find-dir")).await?;
+    
+    for identifier in ["find-branch", "find-dir", worktree_path.to_str().unwrap_or_default()] {
+        let found = manager.find_worktrees(identifier).await?;
+        assert_eq!(found.len(), 1, "Should find exactly one worktree for '{}'", identifier);
+        assert_eq!(found[0].path, worktree_path);
+    }
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_find_worktrees_reports_all_matches() -> Result<()> {
+    // Test: Verify an identifier matching several worktrees returns all of them
+    // This test ensures ambiguous cleanup targets can be reported instead of guessed
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    
+    // The directory of one worktree is named like the branch of the other
+    manager.create_worktree("alpha", None, Some("beta")).await?;
+    manager.create_worktree("beta", None, Some("gamma")).await?;
+    
+    let found = manager.find_worktrees("beta").await?;
+    assert_eq!(found.len(), 2, "Both worktrees should match 'beta'");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_find_worktrees_excludes_main_worktree() -> Result<()> {
+    // Test: Verify the main worktree is never returned as a cleanup target
+    // This test ensures cleanup cannot remove the main checkout
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    let found = manager.find_worktrees(repo_path.to_str().unwrap_or_default()).await?;
+    assert!(found.is_empty(), "Main worktree should not be matched");
+    
+    let found = manager.find_worktrees("nonexistent").await?;
+    assert!(found.is_empty(), "Unknown identifier should match nothing");
+    
+    Ok(())
+}
+
+/// Test helper to commit a monorepo-like layout with several top-level directories
+fn commit_monorepo_layout(repo_path: &Path) -> Result<()> {
+    for dir in ["frontend", "backend", "docs"] {
+        std::fs::create_dir_all(repo_path.join(dir))?;
+        std::fs::write(repo_path.join(dir).join("file.txt"), format!("{}\n", dir))?;
+    }
+    
+    for args in [vec!["add", "."], vec!["commit", "-m", "Add monorepo layout"]] {
+        let output = std::process::Command::new("git")
+            .args(&args)
+            .current_dir(repo_path)
+            .output()?;
+        assert!(output.status.success(), "Failed to commit monorepo layout");
+    }
+    
+    Ok(())
+}
+
+fn sparse_options(paths: &[&str]) -> WorktreeOptions {
+    WorktreeOptions {
+        sparse: Some(SparseCheckout {
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            cone: true,
+        }),
+        submodules: false,
+    }
+}
+
+#[tokio::test]
+async fn test_create_sparse_worktree_checks_out_only_selected_paths() -> Result<()> {
+    // Test: Verify a sparse worktree only materializes the requested directories
+    // This test ensures subagents in large monorepos get a small, focused checkout
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    commit_monorepo_layout(&repo_path)?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    
+    let worktree_path = manager
+        .create_worktree_with_options("sparse", None, None, &sparse_options(&["frontend"]))
+        .await?
+        .path;
+    
+    assert!(worktree_path.join("frontend/file.txt").exists(), "Selected path should be checked out");
+    assert!(worktree_path.join("README.md").exists(), "Cone mode keeps top-level files");
+    assert!(!worktree_path.join("backend").exists(), "Unselected path should not be checked out");
+    assert!(!worktree_path.join("docs").exists(), "Unselected path should not be checked out");
+    
+    let worktrees = manager.list_worktrees().await?;
+    let sparse = worktrees
+        .iter()
+        .find(|w| w.branch.as_deref() == Some("sparse"))
+        .expect("Sparse worktree should be listed");
+    assert_eq!(sparse.sparse_paths, Some(vec!["frontend".to_string()]));
+    
+    let main = &worktrees[0];
+    assert_eq!(main.sparse_paths, None, "Full checkouts should not report sparse paths");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_widen_sparse_worktree() -> Result<()> {
+    // Test: Verify paths can be added to a sparse worktree and sparse-checkout disabled later
+    // This test ensures subagents can widen their checkout when they need more of the tree
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    commit_monorepo_layout(&repo_path)?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    
+    let worktree_path = manager
+        .create_worktree_with_options("widen", None, None, &sparse_options(&["frontend"]))
+        .await?
+        .path;
+    
+    manager.add_sparse_paths(&worktree_path, &["backend".to_string()]).await?;
+    assert!(worktree_path.join("backend/file.txt").exists(), "Added path should be checked out");
+    assert!(!worktree_path.join("docs").exists(), "Other paths should stay excluded");
+    
+    manager.disable_sparse_checkout(&worktree_path).await?;
+    assert!(worktree_path.join("docs/file.txt").exists(), "Full tree should be checked out");
+    
+    let worktrees = manager.list_worktrees().await?;
+    let widened = worktrees
+        .iter()
+        .find(|w| w.branch.as_deref() == Some("widen"))
+        .expect("Worktree should be listed");
+    assert_eq!(widened.sparse_paths, None, "Worktree should no longer be sparse");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_worktree_reports_what_was_created() -> Result<()> {
+    // Test: Verify worktree creation records whether the branch and worktree were created
+    // This test ensures a failed spawn only rolls back what it created
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    let created = manager
+        .create_worktree_with_options("fresh", None, None, &WorktreeOptions::default())
+        .await?;
+    assert!(created.created_branch, "New branch should be reported as created");
+    assert!(created.created_worktree, "New worktree should be reported as created");
+    
+    let output = std::process::Command::new("git")
+        .args(&["branch", "existing"])
+        .current_dir(&repo_path)
+        .output()?;
+    assert!(output.status.success(), "Failed to create branch");
+    
+    let created = manager
+        .create_worktree_with_options("existing", None, None, &WorktreeOptions::default())
+        .await?;
+    assert!(!created.created_branch, "Existing branch should not be reported as created");
+    assert!(created.created_worktree, "New worktree should be reported as created");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_worktree_creation_deletes_created_branch() -> Result<()> {
+    // Test: Verify a branch created for a worktree is deleted if the worktree cannot be added
+    // This test ensures failed creation does not leave stray branches behind
+    
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    // A regular file where the worktree's parent directory would have to be
+    std::fs::write(temp_dir.path().join("blocker"), "not a directory\n")?;
+    
+    let result = manager
+        .create_worktree_with_options("doomed", None, Some("blocker/doomed"), &WorktreeOptions::default())
+        .await;
+    assert!(result.is_err(), "Worktree creation should fail");
+    
+    let output = std::process::Command::new("git")
+        .args(&["rev-parse", "--verify", "--quiet", "refs/heads/doomed"])
+        .current_dir(&repo_path)
+        .output()?;
+    assert!(!output.status.success(), "Branch created for the failed worktree should be deleted");
+    
+    Ok(())
+}
+
+/// Test helper to commit a file with the given content in a checkout
+fn commit_file(dir: &Path, file: &str, content: &str, message: &str) -> Result<()> {
+    std::fs::write(dir.join(file), content)?;
+    
+    // Clones and fresh repositories don't share the test repository's identity
+    let identity = ["-c", "user.name=Test User", "-c", "user.email=test@example.com"];
+    for args in [vec!["add", file], vec!["commit", "-m", message]] {
+        let output = std::process::Command::new("git")
+            .args(identity)
+            .args(&args)
+            .current_dir(dir)
+            .output()?;
+        assert!(output.status.success(), "Failed to commit {}", file);
+    }
+    
+    Ok(())
+}
+
+/// Test helper to get the name of the branch checked out in the main repository
+fn current_branch(repo_path: &Path) -> Result<String> {
+    let output = std::process::Command::new("git")
+        .args(&["rev-parse", "--abbrev-ref", "HEAD"])
+        .current_dir(repo_path)
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[tokio::test]
+async fn test_sync_worktree_rebases_onto_base() -> Result<()> {
+    // Test: Verify a subagent branch is rebased onto new commits of its base branch
+    // This test ensures long-running subagents can catch up with the base branch
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let base = current_branch(&repo_path)?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    let worktree_path = manager.create_worktree("behind", None, None).await?;
+    commit_file(&worktree_path, "feature.txt", "feature\n", "Feature work")?;
+    commit_file(&repo_path, "base.txt", "base\n", "Base work")?;
+    
+    let upstream = manager.resolve_commit(&worktree_path, &base).await?;
+    let outcome = manager.integrate_upstream(&worktree_path, &upstream, SyncStrategy::Rebase, true).await?;
+    
+    assert!(matches!(outcome, SyncOutcome::Updated { .. }), "Branch should be updated: {:?}", outcome);
+    assert!(worktree_path.join("base.txt").exists(), "Base commit should be in the worktree");
+    assert!(worktree_path.join("feature.txt").exists(), "Feature commit should be kept");
+    
+    let outcome = manager.integrate_upstream(&worktree_path, &upstream, SyncStrategy::Rebase, true).await?;
+    assert!(matches!(outcome, SyncOutcome::UpToDate { .. }), "Second sync should be a no-op");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_worktree_fetches_from_named_remote() -> Result<()> {
+    // Test: Verify the base branch can be fetched from another repository and merged
+    // This test ensures syncing works against a configured remote
+    
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    // The upstream repository is a clone that receives new commits
+    let upstream_path = temp_dir.path().join("upstream");
+    let output = std::process::Command::new("git")
+        .arg("clone")
+        .arg(&repo_path)
+        .arg(&upstream_path)
+        .output()?;
+    assert!(output.status.success(), "Failed to clone upstream");
+    
+    let output = std::process::Command::new("git")
+        .args(["remote", "add", "upstream"])
+        .arg(&upstream_path)
+        .current_dir(&repo_path)
+        .output()?;
+    assert!(output.status.success(), "Failed to add upstream remote");
+    
+    let worktree_path = manager.create_worktree("remote-sync", None, None).await?;
+    commit_file(&upstream_path, "upstream.txt", "upstream\n", "Upstream work")?;
+    let upstream_branch = current_branch(&upstream_path)?;
+    
+    let fetched = manager.fetch_ref(&worktree_path, "upstream", &upstream_branch).await?;
+    let outcome = manager.integrate_upstream(&worktree_path, &fetched, SyncStrategy::Merge, true).await?;
+    
+    assert!(matches!(outcome, SyncOutcome::Updated { .. }), "Branch should be updated: {:?}", outcome);
+    assert!(worktree_path.join("upstream.txt").exists(), "Fetched commit should be merged");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_ref_accepts_remote_tracking_name() -> Result<()> {
+    // Test: Verify a base named by its remote-tracking branch is fetched as the remote's branch
+    // This test ensures subagents spawned from origin/* can be synced with their remote
+    
+    let (temp_dir, upstream_path) = create_temp_git_repo()?;
+    let repo_path = temp_dir.path().join("clone");
+    let output = std::process::Command::new("git")
+        .arg("clone")
+        .arg(&upstream_path)
+        .arg(&repo_path)
+        .output()?;
+    assert!(output.status.success(), "Failed to clone repository");
+    let branch = current_branch(&upstream_path)?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    let remote_branch = format!("origin/{}", branch);
+    let worktree_path = manager.create_worktree("remote-base", Some(&remote_branch), None).await?;
+    commit_file(&upstream_path, "upstream.txt", "upstream\n", "Upstream work")?;
+    let upstream_head = git_output(&upstream_path, &["rev-parse", "HEAD"])?;
+    
+    let fetched = manager.fetch_ref(&worktree_path, "origin", &remote_branch).await?;
+    assert_eq!(fetched, upstream_head, "The remote's branch should be fetched");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_ref_refuses_unsafe_remotes() -> Result<()> {
+    // Test: Verify option-like and unconfigured remotes and refs are refused
+    // This test ensures a sync request cannot make git fetch run an arbitrary command
+    
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    let worktree_path = manager.create_worktree("unsafe-fetch", None, None).await?;
+    let marker = temp_dir.path().join("pwned");
+    let upload_pack = format!("--upload-pack=touch {}", marker.display());
+    
+    assert!(manager.fetch_ref(&worktree_path, &upload_pack, "main").await.is_err(), "Option remote should be refused");
+    assert!(manager.fetch_ref(&worktree_path, "origin", &upload_pack).await.is_err(), "Option ref should be refused");
+    let path_remote = repo_path.to_str().unwrap_or_default();
+    assert!(manager.fetch_ref(&worktree_path, path_remote, "main").await.is_err(), "Unconfigured remote should be refused");
+    assert!(!marker.exists(), "No command should have been run");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_worktree_stops_on_conflicts() -> Result<()> {
+    // Test: Verify conflicts are reported and the rebase is aborted or left in progress as requested
+    // This test ensures a sync never leaves a worktree in an unexpected state
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let base = current_branch(&repo_path)?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    let worktree_path = manager.create_worktree("conflicting", None, None).await?;
+    commit_file(&worktree_path, "README.md", "subagent version\n", "Subagent edit")?;
+    commit_file(&repo_path, "README.md", "base version\n", "Base edit")?;
+    let upstream = manager.resolve_commit(&worktree_path, &base).await?;
+    
+    let outcome = manager.integrate_upstream(&worktree_path, &upstream, SyncStrategy::Rebase, true).await?;
+    assert_eq!(outcome, SyncOutcome::Conflicted {
+        strategy: SyncStrategy::Rebase,
+        conflicted_files: vec!["README.md".to_string()],
+        in_progress: false,
+    });
+    assert_eq!(std::fs::read_to_string(worktree_path.join("README.md"))?, "subagent version\n");
+    assert!(!manager.has_uncommitted_changes(&worktree_path).await?, "Aborted sync should leave a clean worktree");
+    
+    let outcome = manager.integrate_upstream(&worktree_path, &upstream, SyncStrategy::Merge, false).await?;
+    assert!(
+        matches!(outcome, SyncOutcome::Conflicted { in_progress: true, .. }),
+        "Merge should be left in progress: {:?}",
+        outcome
+    );
+    assert!(manager.has_uncommitted_changes(&worktree_path).await?, "Conflicts should be left for resolution");
+    
+    Ok(())
+}
+
+/// Test helper to run a git command and return its trimmed stdout
+fn git_output(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()?;
+    assert!(output.status.success(), "git {} failed", args.join(" "));
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[tokio::test]
+async fn test_create_worktree_from_arbitrary_revisions() -> Result<()> {
+    // Test: Verify worktrees can be based on tags, commit SHAs and revision expressions
+    // This test ensures the base is resolved like any git revision, not only as a branch name
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let first = git_output(&repo_path, &["rev-parse", "HEAD"])?;
+    git_output(&repo_path, &["tag", "v1"])?;
+    commit_file(&repo_path, "second.txt", "second\n", "Second commit")?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    for (branch, base) in [("from-tag", "v1"), ("from-sha", first.as_str()), ("from-expr", "HEAD~1")] {
+        let created = manager
+            .create_worktree_with_options(branch, Some(base), None, &WorktreeOptions::default())
+            .await?;
+        
+        assert_eq!(created.base_ref, None, "Only branches should be recorded as the base");
+        assert_eq!(created.base_commit, first, "Base '{}' should resolve to the first commit", base);
+        assert!(!created.path.join("second.txt").exists(), "Worktree should be at the base commit");
+    }
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_worktree_defaults_to_head() -> Result<()> {
+    // Test: Verify the current branch and commit are recorded when no base is given
+    // This test ensures later syncs know which branch the subagent forked from
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let head = git_output(&repo_path, &["rev-parse", "HEAD"])?;
+    let branch = current_branch(&repo_path)?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    
+    let created = manager
+        .create_worktree_with_options("from-head", None, None, &WorktreeOptions::default())
+        .await?;
+    
+    assert_eq!(created.base_ref, Some(branch.clone()));
+    assert_eq!(created.base_commit, head);
+    
+    // Symbolic names of the same branch are recorded by its short name
+    let full_name = format!("refs/heads/{}", branch);
+    for (name, base) in [("from-symbolic-head", "HEAD"), ("from-full-name", full_name.as_str())] {
+        let created = manager
+            .create_worktree_with_options(name, Some(base), None, &WorktreeOptions::default())
+            .await?;
+        assert_eq!(created.base_ref.as_deref(), Some(branch.as_str()), "Base '{}' should be recorded as the branch", base);
+    }
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_worktree_from_remote_branch_sets_upstream() -> Result<()> {
+    // Test: Verify a branch based on a remote-tracking branch tracks it as upstream
+    // This test ensures subagent branches started from origin/* behave like `git checkout -b --track`
+    
+    let (temp_dir, upstream_path) = create_temp_git_repo()?;
+    let repo_path = temp_dir.path().join("clone");
+    let output = std::process::Command::new("git")
+        .arg("clone")
+        .arg(&upstream_path)
+        .arg(&repo_path)
+        .output()?;
+    assert!(output.status.success(), "Failed to clone repository");
+    let remote_branch = format!("origin/{}", current_branch(&upstream_path)?);
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    manager.create_worktree("tracking", Some(&remote_branch), None).await?;
+    
+    let upstream = git_output(&repo_path, &["rev-parse", "--abbrev-ref", "tracking@{upstream}"])?;
+    assert_eq!(upstream, remote_branch, "Branch should track the remote base");
+    
+    manager.create_worktree("local-base", Some("HEAD"), None).await?;
+    let output = std::process::Command::new("git")
+        .args(&["rev-parse", "--abbrev-ref", "local-base@{upstream}"])
+        .current_dir(&repo_path)
+        .output()?;
+    assert!(!output.status.success(), "Branch from a local base should have no upstream");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_worktree_existing_branch_records_fork_point() -> Result<()> {
+    // Test: Verify the fork point of an existing branch is recorded, not the current base tip
+    // This test ensures diffs against the base only contain the branch's own changes
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let fork_point = git_output(&repo_path, &["rev-parse", "HEAD"])?;
+    let base = current_branch(&repo_path)?;
+    git_output(&repo_path, &["branch", "existing-work"])?;
+    commit_file(&repo_path, "later.txt", "later\n", "Later base commit")?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    
+    let created = manager
+        .create_worktree_with_options("existing-work", Some(&base), None, &WorktreeOptions::default())
+        .await?;
+    
+    assert!(!created.created_branch);
+    assert_eq!(created.base_commit, fork_point, "Fork point should be the merge base");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lock_and_unlock_worktree() -> Result<()> {
+    // Test: Verify locked worktrees report their lock reason and are protected from removal
+    // This test ensures worktrees in use by agents cannot be pruned or removed by accident
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let worktree_path = manager.create_worktree("locked", None, None).await?;
+    
+    manager.lock_worktree(&worktree_path, "subagent abcd1234 pid 42").await?;
+    
+    let worktrees = manager.list_worktrees().await?;
+    let locked = worktrees
+        .iter()
+        .find(|w| w.branch.as_deref() == Some("locked"))
+        .expect("Worktree should be listed");
+    assert_eq!(locked.locked.as_deref(), Some("subagent abcd1234 pid 42"));
+    assert_eq!(worktrees[0].locked, None, "Main worktree should not be locked");
+    
+    assert!(manager.remove_worktree(&worktree_path).await.is_err(), "Locked worktree should not be removable");
+    
+    manager.unlock_worktree(&worktree_path).await?;
+    let worktrees = manager.list_worktrees().await?;
+    assert!(worktrees.iter().all(|w| w.locked.is_none()), "Worktree should be unlocked");
+    
+    manager.remove_worktree(&worktree_path).await?;
+    
+    Ok(())
+}
+
+/// A `git daemon` serving the repositories of a directory, stopped when dropped
+///
+/// Git refuses to clone submodules from local paths by default, but allows git:// URLs,
+/// so submodules served this way work without changing any configuration.
+struct GitDaemon {
+    child: std::process::Child,
+    url: String,
+}
+
+impl GitDaemon {
+    fn start(base_path: &Path) -> Result<Self> {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let child = std::process::Command::new("git")
+            .args(["daemon", "--export-all", "--reuseaddr", "--listen=127.0.0.1"])
+            .arg(format!("--port={}", port))
+            .arg(format!("--base-path={}", base_path.display()))
+            .arg(base_path)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+        let daemon = Self { child, url: format!("git://127.0.0.1:{}", port) };
+        
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(std::time::Instant::now() < deadline, "git daemon did not start");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        Ok(daemon)
+    }
+}
+
+impl Drop for GitDaemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Test helper to add a submodule with one committed file to the repository
+///
+/// The returned daemon serves the submodule and must be kept until the test is done.
+fn add_submodule(repo_path: &Path, submodule_path: &str) -> Result<GitDaemon> {
+    let base_path = repo_path.parent().unwrap();
+    let name = submodule_path.replace('/', "-");
+    let source = base_path.join(&name);
+    std::fs::create_dir(&source)?;
+    git_output(&source, &["init", "-b", "main"])?;
+    commit_file(&source, "lib.rs", "pub fn shared() {}\n", "Add shared library")?;
+    
+    let daemon = GitDaemon::start(base_path)?;
+    git_output(repo_path, &["submodule", "add", &format!("{}/{}", daemon.url, name), submodule_path])?;
+    git_output(repo_path, &["commit", "-m", "Add submodule"])?;
+    
+    Ok(daemon)
+}
+
+#[tokio::test]
+async fn test_create_worktree_initializes_submodules_with_shared_objects() -> Result<()> {
+    // Test: Verify submodules are initialized in new worktrees, reusing the main checkout's module store
+    // This test ensures builds in subagent worktrees see submodule content without fetching it again
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let _daemon = add_submodule(&repo_path, "libs/shared")?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    let plain = manager
+        .create_worktree_with_options("plain", None, None, &WorktreeOptions::default())
+        .await?;
+    assert!(plain.submodules.is_empty(), "Submodules should only be initialized on request");
+    assert!(!plain.path.join("libs/shared/lib.rs").exists());
+    
+    let options = WorktreeOptions { submodules: true, ..WorktreeOptions::default() };
+    let created = manager.create_worktree_with_options("with-submodules", None, None, &options).await?;
+    
+    assert_eq!(created.submodules.len(), 1);
+    let submodule = &created.submodules[0];
+    assert_eq!(submodule.path, "libs/shared");
+    assert_eq!(submodule.error, None);
+    assert_eq!(submodule.reference, Some(repo_path.join(".git/modules/libs/shared")));
+    assert!(created.path.join("libs/shared/lib.rs").exists(), "Submodule should be checked out");
+    
+    let git_dir = git_output(&created.path.join("libs/shared"), &["rev-parse", "--absolute-git-dir"])?;
+    let alternates = std::fs::read_to_string(Path::new(&git_dir).join("objects/info/alternates"))?;
+    assert!(alternates.contains(".git/modules/libs/shared"), "Objects should be borrowed from the main checkout");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_worktree_reports_failed_submodules() -> Result<()> {
+    // Test: Verify a submodule that cannot be cloned is reported without failing the worktree
+    // This test ensures one broken submodule does not cost the agent its worktree
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let head = git_output(&repo_path, &["rev-parse", "HEAD"])?;
+    git_output(&repo_path, &["config", "--file", ".gitmodules", "submodule.broken.path", "libs/broken"])?;
+    git_output(&repo_path, &["config", "--file", ".gitmodules", "submodule.broken.url", "/nonexistent/broken.git"])?;
+    git_output(&repo_path, &["update-index", "--add", "--cacheinfo", &format!("160000,{},libs/broken", head)])?;
+    git_output(&repo_path, &["add", ".gitmodules"])?;
+    git_output(&repo_path, &["commit", "-m", "Add broken submodule"])?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    
+    let options = WorktreeOptions { submodules: true, ..WorktreeOptions::default() };
+    let created = manager.create_worktree_with_options("broken-submodule", None, None, &options).await?;
+    
+    assert!(created.created_worktree);
+    assert_eq!(created.submodules.len(), 1);
+    assert_eq!(created.submodules[0].path, "libs/broken");
+    assert_eq!(created.submodules[0].reference, None);
+    assert!(created.submodules[0].error.is_some(), "Failure should be reported for the submodule");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_worktree_runs_lfs_checkout_only_when_used() -> Result<()> {
+    // Test: Verify LFS checkout is attempted only in repositories whose attributes use LFS
+    // This test ensures LFS pointers are smudged in worktrees, and other repositories are unaffected
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    
+    let created = manager
+        .create_worktree_with_options("no-lfs", None, None, &WorktreeOptions::default())
+        .await?;
+    assert_eq!(created.lfs, None);
+    
+    commit_file(&repo_path, ".gitattributes", "*.bin filter=lfs diff=lfs merge=lfs -text\n", "Track binaries with LFS")?;
+    let created = manager
+        .create_worktree_with_options("lfs", None, None, &WorktreeOptions::default())
+        .await?;
+    // Whether it succeeds depends on git-lfs being installed; either way it is reported
+    assert!(created.lfs.is_some(), "LFS checkout should be attempted");
+    assert!(created.created_worktree);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_checkpoint_worktree_commits_all_changes() -> Result<()> {
+    // Test: Verify a checkpoint stages new, modified and deleted files and commits them with the given identity
+    // This test ensures work an agent forgot to commit is preserved before cleanup
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let worktree_path = manager.create_worktree("checkpoint", None, None).await?;
+    commit_file(&worktree_path, "obsolete.txt", "old\n", "Add obsolete file")?;
+    
+    std::fs::write(worktree_path.join("README.md"), "# Changed\n")?;
+    std::fs::write(worktree_path.join("new.txt"), "new\n")?;
+    std::fs::remove_file(worktree_path.join("obsolete.txt"))?;
+    
+    let options = CheckpointOptions {
+        message: "Save agent work".to_string(),
+        author: Some(GitIdentity { name: "Agent".to_string(), email: "agent@example.com".to_string() }),
+        committer: None,
+        trailers: vec![("Subagent-Id".to_string(), "abcd1234".to_string())],
+    };
+    let checkpoint = manager.checkpoint_worktree(&worktree_path, &options).await?
+        .expect("Changes should be committed");
+    
+    assert_eq!(checkpoint.files_changed, 3);
+    assert_eq!(git_output(&worktree_path, &["rev-parse", "HEAD"])?, checkpoint.commit);
+    assert_eq!(git_output(&worktree_path, &["status", "--porcelain"])?, "", "Worktree should be clean");
+    assert_eq!(git_output(&worktree_path, &["log", "-1", "--format=%an <%ae> / %cn"])?, "Agent <agent@example.com> / Agent");
+    assert_eq!(
+        git_output(&worktree_path, &["log", "-1", "--format=%(trailers:key=Subagent-Id,valueonly)"])?,
+        "abcd1234"
+    );
+    
+    assert_eq!(manager.checkpoint_worktree(&worktree_path, &options).await?, None, "Nothing left to commit");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_checkpoint_sparse_worktree_keeps_paths_outside_sparse_set() -> Result<()> {
+    // Test: Verify a checkpoint of a sparse worktree does not delete files that are not checked out
+    // This test ensures checkpoints only record what the agent changed
+    
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    commit_monorepo_layout(&repo_path)?;
+    let manager = GitWorktreeManager::new(repo_path)?;
+    let worktree_path = manager
+        .create_worktree_with_options("sparse-checkpoint", None, None, &sparse_options(&["frontend"]))
+        .await?
+        .path;
+    
+    std::fs::write(worktree_path.join("frontend/file.txt"), "changed\n")?;
+    let options = CheckpointOptions { message: "Checkpoint".to_string(), ..CheckpointOptions::default() };
+    let checkpoint = manager.checkpoint_worktree(&worktree_path, &options).await?
+        .expect("Changes should be committed");
+    
+    assert_eq!(checkpoint.files_changed, 1);
+    let files = git_output(&worktree_path, &["ls-tree", "-r", "--name-only", "HEAD"])?;
+    assert!(files.contains("backend/file.txt"), "Files outside the sparse set should be kept");
+    assert!(files.contains("docs/file.txt"), "Files outside the sparse set should be kept");
+    
+    Ok(())
+}
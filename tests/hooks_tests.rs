@@ -0,0 +1,217 @@
+mod common;
+
+use anyhow::Result;
+use subagent_worktree_mcp::mcp::server::RequestHandler;
+use subagent_worktree_mcp::mcp::types::CallToolRequest;
+use serde_json::json;
+use tempfile::TempDir;
+
+use subagent_worktree_mcp::hooks::{run_bootstrap, BootstrapConfig};
+use subagent_worktree_mcp::server::SubagentWorktreeServer;
+
+use common::create_temp_git_repo;
+
+/// Test helper to create a main checkout and an empty worktree directory
+fn create_source_and_worktree() -> Result<(TempDir, std::path::PathBuf, std::path::PathBuf)> {
+    let temp_dir = TempDir::new()?;
+    let source = temp_dir.path().join("main");
+    let worktree = temp_dir.path().join("worktree");
+
+    std::fs::create_dir_all(source.join("config/local"))?;
+    std::fs::write(source.join(".env"), "SECRET=1\n")?;
+    std::fs::write(source.join("config/local/settings.json"), "{}\n")?;
+    std::fs::create_dir_all(source.join("cache"))?;
+    std::fs::create_dir(&worktree)?;
+
+    Ok((temp_dir, source, worktree))
+}
+
+#[tokio::test]
+async fn test_bootstrap_copies_symlinks_and_runs_commands() -> Result<()> {
+    // Test: Verify all bootstrap step kinds run in the new worktree
+    // This test ensures a worktree is prepared before its agent starts
+
+    let (_temp_dir, source, worktree) = create_source_and_worktree()?;
+    let config = BootstrapConfig {
+        copy: vec![".env".to_string(), "config".to_string()],
+        symlink: vec!["cache".to_string()],
+        commands: vec!["echo ready > bootstrapped.txt".to_string()],
+        ..Default::default()
+    };
+
+    let report = run_bootstrap(&source, &worktree, &config).await?;
+
+    assert!(report.failure().is_none(), "All steps should succeed: {}", report.summary());
+    assert_eq!(report.steps.len(), 4);
+    assert_eq!(std::fs::read_to_string(worktree.join(".env"))?, "SECRET=1\n");
+    assert!(worktree.join("config/local/settings.json").exists(), "Directories should be copied recursively");
+    assert!(worktree.join("cache").symlink_metadata()?.file_type().is_symlink(), "cache should be a symlink");
+    assert!(worktree.join("bootstrapped.txt").exists(), "Commands should run in the worktree");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bootstrap_stops_at_failed_command() -> Result<()> {
+    // Test: Verify a failing command stops the bootstrap and its output is captured
+    // This test ensures hook failures can be diagnosed from the spawn result
+
+    let (_temp_dir, source, worktree) = create_source_and_worktree()?;
+    let config = BootstrapConfig {
+        commands: vec![
+            "echo 'missing dependency' >&2; exit 3".to_string(),
+            "touch should-not-run".to_string(),
+        ],
+        ..Default::default()
+    };
+
+    let report = run_bootstrap(&source, &worktree, &config).await?;
+
+    let failure = report.failure().expect("Bootstrap should fail");
+    let output = failure.output.as_ref().expect("Command output should be captured");
+    assert_eq!(output.exit_code, Some(3));
+    assert!(output.stderr.contains("missing dependency"), "stderr should be captured");
+    assert_eq!(report.steps.len(), 1, "Later steps should not run");
+    assert!(!worktree.join("should-not-run").exists());
+    assert!(report.summary().contains("missing dependency"), "Summary should include failure output");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bootstrap_missing_copy_source_fails() -> Result<()> {
+    // Test: Verify copying a file that does not exist in the main checkout fails
+    // This test ensures misconfigured hooks are reported instead of silently ignored
+
+    let (_temp_dir, source, worktree) = create_source_and_worktree()?;
+    let config = BootstrapConfig {
+        copy: vec!["does-not-exist".to_string()],
+        ..Default::default()
+    };
+
+    let report = run_bootstrap(&source, &worktree, &config).await?;
+
+    let failure = report.failure().expect("Bootstrap should fail");
+    assert!(failure.error.as_deref().unwrap_or_default().contains("does not exist"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_bootstrap_paths_stay_inside_roots() -> Result<()> {
+    // Test: Verify copy and symlink paths cannot lead outside the main checkout or the worktree
+    // This test ensures a bootstrap config cannot read or overwrite arbitrary files on the host
+
+    let (temp_dir, source, worktree) = create_source_and_worktree()?;
+    let outside = temp_dir.path().join("outside");
+    std::fs::create_dir(&outside)?;
+    std::fs::write(outside.join("secret.txt"), "outside\n")?;
+    std::os::unix::fs::symlink(&outside, source.join("escape"))?;
+
+    for (copy, symlink) in [
+        (vec!["../outside/secret.txt".to_string()], vec![]),
+        (vec![outside.join("secret.txt").display().to_string()], vec![]),
+        (vec!["escape/secret.txt".to_string()], vec![]),
+        (vec![], vec!["config/../../outside".to_string()]),
+        (vec![".".to_string()], vec![]),
+    ] {
+        let config = BootstrapConfig { copy, symlink, ..Default::default() };
+        let report = run_bootstrap(&source, &worktree, &config).await?;
+
+        let failure = report.failure().expect("Path outside the roots should be refused");
+        assert!(failure.error.is_some(), "Refusal should be reported: {}", report.summary());
+    }
+    assert_eq!(std::fs::read_dir(&worktree)?.count(), 0, "Nothing should be written to the worktree");
+
+    // Nested relative paths are still accepted
+    let config = BootstrapConfig { copy: vec!["./config/local/settings.json".to_string()], ..Default::default() };
+    let report = run_bootstrap(&source, &worktree, &config).await?;
+    assert!(report.failure().is_none(), "Relative path should be copied: {}", report.summary());
+    assert!(worktree.join("config/local/settings.json").exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bootstrap_command_timeout() -> Result<()> {
+    // Test: Verify commands exceeding the timeout are killed and reported
+    // This test ensures a hung hook cannot block a spawn forever
+
+    let (_temp_dir, source, worktree) = create_source_and_worktree()?;
+    let config = BootstrapConfig {
+        commands: vec!["sleep 30".to_string()],
+        timeout_secs: Some(1),
+        ..Default::default()
+    };
+
+    let started = std::time::Instant::now();
+    let report = run_bootstrap(&source, &worktree, &config).await?;
+
+    let failure = report.failure().expect("Bootstrap should time out");
+    assert!(failure.output.as_ref().is_some_and(|output| output.timed_out));
+    assert!(started.elapsed().as_secs() < 10, "Command should be killed at the timeout");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_timed_out_command_group_killed() -> Result<()> {
+    // Test: Verify a timed out command is killed with everything it started, keeping its output
+    // This test ensures background processes do not outlive a hook and the output explains the hang
+
+    let (_temp_dir, source, worktree) = create_source_and_worktree()?;
+    let config = BootstrapConfig {
+        commands: vec!["echo installing; echo waiting >&2; (sleep 2; touch late) & sleep 30".to_string()],
+        timeout_secs: Some(1),
+        ..Default::default()
+    };
+
+    let started = std::time::Instant::now();
+    let report = run_bootstrap(&source, &worktree, &config).await?;
+    assert!(started.elapsed().as_secs() < 10, "Command should be killed at the timeout");
+
+    let output = report.failure().and_then(|failure| failure.output.as_ref()).expect("Command output should be kept");
+    assert!(output.timed_out);
+    assert!(output.stdout.contains("installing"), "stdout before the timeout should be kept: {:?}", output);
+    assert!(output.stderr.contains("waiting"), "stderr before the timeout should be kept: {:?}", output);
+
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    assert!(!worktree.join("late").exists(), "Background processes should be killed with the command");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_spawn_bootstrap_commands_must_be_allowed() -> Result<()> {
+    // Test: Bootstrap commands passed with a spawn request are held to the command allow-list
+    // This test ensures a tool caller cannot run arbitrary shell commands through the bootstrap override
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let server = SubagentWorktreeServer::new(repo_path.clone())?;
+
+    let result = server.call_tool(CallToolRequest {
+        name: "spawn_subagent".to_string(),
+        arguments: json!({
+            "prompt": "Do the task",
+            "branch_name": "bootstrap-refused",
+            "bootstrap": { "commands": ["touch pwned"] }
+        }),
+    }).await;
+    let Err(error) = result else {
+        panic!("Spawn should be refused");
+    };
+    let error = format!("{:#}", error);
+    assert!(error.contains("Bootstrap command refused") && error.contains("not allowed"), "Unexpected error: {}", error);
+
+    // Nothing was created for the refused spawn
+    let output = std::process::Command::new("git")
+        .args(&["branch", "--list", "bootstrap-refused"])
+        .current_dir(&repo_path)
+        .output()?;
+    assert!(String::from_utf8_lossy(&output.stdout).trim().is_empty(), "No branch should be created");
+    assert!(!repo_path.join("pwned").exists());
+
+    Ok(())
+}
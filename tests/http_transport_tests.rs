@@ -0,0 +1,282 @@
+mod common;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use subagent_worktree_mcp::config::CONFIG_FILE_NAME;
+use subagent_worktree_mcp::http_transport::{self, MCP_PATH};
+use subagent_worktree_mcp::server::SubagentWorktreeServer;
+
+use common::create_temp_git_repo;
+
+/// Token the test servers require
+const TOKEN: &str = "test-token";
+
+/// Test helper to serve a repository over HTTP on a loopback port
+async fn start_server(repo_path: std::path::PathBuf) -> Result<(SocketAddr, Arc<SubagentWorktreeServer>)> {
+    let server = Arc::new(SubagentWorktreeServer::new(repo_path)?);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(http_transport::serve(listener, server.clone(), server.resource_notifier(), Some(TOKEN.to_string())));
+    Ok((addr, server))
+}
+
+/// Test helper to send a request and read the status and body of the response
+async fn post(addr: SocketAddr, token: Option<&str>, body: &Value) -> Result<(u16, String)> {
+    let body = body.to_string();
+    let auth = token.map(|t| format!("Authorization: Bearer {}\r\n", t)).unwrap_or_default();
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\n\r\n{}",
+        MCP_PATH, addr, auth, body.len(), body
+    ).as_bytes()).await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    let status = response.split_whitespace().nth(1).unwrap_or_default().parse()?;
+    let body = response.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+    Ok((status, body))
+}
+
+/// Test helper to send a request in a session and read the status, head and body of the response
+async fn send_in_session(addr: SocketAddr, method: &str, session: Option<&str>, body: &Value) -> Result<(u16, String, String)> {
+    let body = body.to_string();
+    let session = session.map(|id| format!("Mcp-Session-Id: {}\r\n", id)).unwrap_or_default();
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\n{}Content-Length: {}\r\n\r\n{}",
+        method, MCP_PATH, addr, TOKEN, session, body.len(), body
+    ).as_bytes()).await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    let status = response.split_whitespace().nth(1).unwrap_or_default().parse()?;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((response.as_str(), ""));
+    Ok((status, head.to_string(), body.to_string()))
+}
+
+/// Test helper to start a session, returning its ID
+async fn initialize_session(addr: SocketAddr) -> Result<String> {
+    let (status, head, _) = send_in_session(addr, "POST", None, &json!({
+        "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {}
+    })).await?;
+    assert_eq!(status, 200);
+    let id = head.lines()
+        .find_map(|line| line.strip_prefix("Mcp-Session-Id: "))
+        .expect("initialize should return a session ID");
+    Ok(id.to_string())
+}
+
+/// Test helper to call a JSON-RPC method and return its result
+async fn call(addr: SocketAddr, method: &str, params: Value) -> Result<Value> {
+    let (status, body) = post(addr, Some(TOKEN), &json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params })).await?;
+    assert_eq!(status, 200, "Unexpected status for {}: {}", method, body);
+    let response: Value = serde_json::from_str(&body)?;
+    assert_eq!(response["id"], 1);
+    Ok(response["result"].clone())
+}
+
+#[tokio::test]
+async fn test_requests_need_bearer_token() -> Result<()> {
+    // Test: Requests without the configured bearer token are rejected
+    // This test ensures a listening server cannot be driven by other local users or processes
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let (addr, _server) = start_server(repo_path).await?;
+    let ping = json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" });
+
+    assert_eq!(post(addr, None, &ping).await?.0, 401);
+    assert_eq!(post(addr, Some("wrong-token"), &ping).await?.0, 401);
+    assert_eq!(post(addr, Some(TOKEN), &ping).await?.0, 200);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_initialize_and_list_tools() -> Result<()> {
+    // Test: Clients can initialize and list tools over HTTP
+    // This test ensures the HTTP transport exposes the same tools as stdio
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let (addr, _server) = start_server(repo_path).await?;
+
+    let initialized = call(addr, "initialize", json!({ "protocolVersion": "2025-03-26", "capabilities": {} })).await?;
+    assert_eq!(initialized["capabilities"]["resources"]["subscribe"], true);
+    assert_eq!(initialized["serverInfo"]["name"], "subagent-worktree-mcp");
+
+    // Notifications are accepted without a response
+    let (status, _) = post(addr, Some(TOKEN), &json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await?;
+    assert_eq!(status, 202);
+
+    let tools = call(addr, "tools/list", json!({})).await?;
+    let names: Vec<&str> = tools["tools"].as_array().unwrap().iter().filter_map(|t| t["name"].as_str()).collect();
+    assert_eq!(names.len(), SubagentWorktreeServer::get_tools().len());
+    assert!(names.contains(&"spawn_subagent"));
+    assert!(tools["tools"][0]["inputSchema"].is_object());
+
+    let (_, body) = post(addr, Some(TOKEN), &json!({ "jsonrpc": "2.0", "id": 2, "method": "no/such/method" })).await?;
+    let response: Value = serde_json::from_str(&body)?;
+    assert_eq!(response["error"]["code"], -32601);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_call_tool() -> Result<()> {
+    // Test: Tools are called over HTTP and failures are reported as tool errors
+    // This test ensures HTTP clients get the same tool results as stdio clients
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let (addr, _server) = start_server(repo_path).await?;
+
+    let result = call(addr, "tools/call", json!({ "name": "list_worktrees", "arguments": {} })).await?;
+    assert_eq!(result["isError"], false);
+    assert!(result["content"][0]["text"].as_str().unwrap().contains("main"), "Unexpected result: {}", result);
+
+    let result = call(addr, "tools/call", json!({ "name": "no_such_tool", "arguments": {} })).await?;
+    assert_eq!(result["isError"], true);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_notification_stream() -> Result<()> {
+    // Test: Updates of the resources a session subscribed to are sent on its server-sent event stream
+    // This test ensures HTTP clients are notified of changes like stdio clients, and only of their own
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let (addr, server) = start_server(repo_path).await?;
+    let session = initialize_session(addr).await?;
+    let other = initialize_session(addr).await?;
+
+    let mut stream = BufReader::new(TcpStream::connect(addr).await?);
+    stream.get_mut().write_all(format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nAccept: text/event-stream\r\nAuthorization: Bearer {}\r\nMcp-Session-Id: {}\r\n\r\n",
+        MCP_PATH, addr, TOKEN, session
+    ).as_bytes()).await?;
+    let mut status_line = String::new();
+    stream.read_line(&mut status_line).await?;
+    assert!(status_line.contains("200"), "Unexpected status: {}", status_line);
+
+    for (session, uri) in [(&other, "agent://9f8e7d6c/log"), (&session, "agent://1a2b3c4d/log")] {
+        let (status, _, body) = send_in_session(addr, "POST", Some(session), &json!({
+            "jsonrpc": "2.0", "id": 1, "method": "resources/subscribe", "params": { "uri": uri }
+        })).await?;
+        assert_eq!(status, 200, "Subscribing failed: {}", body);
+    }
+    server.resource_notifier().notify("agent://9f8e7d6c/log").await;
+    server.resource_notifier().notify("agent://1a2b3c4d/log").await;
+
+    let event = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await? == 0 {
+                return Err(anyhow::anyhow!("Stream closed"));
+            }
+            if let Some(data) = line.strip_prefix("data: ") {
+                return Ok::<Value, anyhow::Error>(serde_json::from_str(data)?);
+            }
+        }
+    }).await??;
+    assert_eq!(event["method"], "notifications/resources/updated");
+    assert_eq!(event["params"]["uri"], "agent://1a2b3c4d/log");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_progress_streamed_with_response() -> Result<()> {
+    // Test: A post that asks for progress and accepts event streams is answered with a stream
+    // This test ensures HTTP clients can receive progress notifications before the response
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let (addr, _server) = start_server(repo_path).await?;
+
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "tools/call",
+        "params": { "name": "list_worktrees", "arguments": {}, "_meta": { "progressToken": "list-1" } },
+    }).to_string();
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nAccept: application/json, text/event-stream\r\nAuthorization: Bearer {}\r\nContent-Length: {}\r\n\r\n{}",
+        MCP_PATH, addr, TOKEN, body.len(), body
+    ).as_bytes()).await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    assert!(response.contains("Content-Type: text/event-stream"), "Unexpected response: {}", response);
+
+    let events: Vec<Value> = response.lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let last = events.last().cloned().unwrap_or_default();
+    assert_eq!(last["id"], 3, "The response should be the last event: {:?}", events);
+    assert_eq!(last["result"]["isError"], false);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sessions_keep_cancellations_apart() -> Result<()> {
+    // Test: Each initialized client gets its own session, and cancellations only reach calls of the same session
+    // This test ensures one HTTP client cannot cancel another client's call that happens to use the same request ID
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let output = std::process::Command::new("git")
+        .args(["worktree", "add", "-b", "feature", "../feature"])
+        .current_dir(&repo_path)
+        .output()?;
+    assert!(output.status.success(), "Failed to add worktree");
+    std::fs::write(repo_path.join(CONFIG_FILE_NAME), r#"{"commands": {"tasks": {"slow": "sleep 2"}}}"#)?;
+    let (addr, _server) = start_server(repo_path).await?;
+
+    let first = initialize_session(addr).await?;
+    let second = initialize_session(addr).await?;
+    assert_ne!(first, second, "Every client should get its own session");
+
+    let run_slow = json!({
+        "jsonrpc": "2.0",
+        "id": 7,
+        "method": "tools/call",
+        "params": { "name": "run_in_worktree", "arguments": { "worktree_name": "feature", "task": "slow" } },
+    });
+    let cancel = json!({ "jsonrpc": "2.0", "method": "notifications/cancelled", "params": { "requestId": 7 } });
+
+    // Another client's cancellation of the same request ID leaves the call alone
+    let call = tokio::spawn({
+        let (first, run_slow) = (first.clone(), run_slow.clone());
+        async move { send_in_session(addr, "POST", Some(&first), &run_slow).await }
+    });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(send_in_session(addr, "POST", Some(&second), &cancel).await?.0, 202);
+    let (status, _, body) = call.await??;
+    assert_eq!(status, 200);
+    let response: Value = serde_json::from_str(&body)?;
+    assert_eq!(response["result"]["isError"], false, "Call should not be cancelled: {}", body);
+
+    // The client that posted the call can cancel it
+    let call = tokio::spawn({
+        let (first, run_slow) = (first.clone(), run_slow.clone());
+        async move { send_in_session(addr, "POST", Some(&first), &run_slow).await }
+    });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    send_in_session(addr, "POST", Some(&first), &cancel).await?;
+    let (_, _, body) = call.await??;
+    let response: Value = serde_json::from_str(&body)?;
+    assert_eq!(response["result"]["isError"], true, "Call should be cancelled: {}", body);
+
+    // Ended and unknown sessions are refused
+    assert_eq!(send_in_session(addr, "DELETE", Some(&first), &json!(null)).await?.0, 200);
+    let (status, _, _) = send_in_session(addr, "POST", Some(&first), &json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" })).await?;
+    assert_eq!(status, 404, "Ended session should be unknown");
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use subagent_worktree_mcp::mcp::server::RequestHandler;
+use subagent_worktree_mcp::mcp::types::CallToolRequest;
+use serde_json::{json, Value};
+use std::path::Path;
+
+use subagent_worktree_mcp::server::SubagentWorktreeServer;
+use subagent_worktree_mcp::subagent_spawner::{
+    parse_json_output, AgentCapabilities, AgentInfo, AgentOptions, AgentRunResult, AgentSpawner, CursorCliAgent,
+    SpawnedAgent, SubagentSpawner,
+};
+
+use common::create_temp_git_repo;
+
+/// A headless agent whose information cannot be read
+struct BrokenAgent;
+
+#[async_trait]
+impl AgentSpawner for BrokenAgent {
+    async fn is_available(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn spawn(&self, _worktree_path: &Path, _prompt: &str, _options: &AgentOptions) -> Result<SpawnedAgent> {
+        Err(anyhow::anyhow!("not spawned in tests"))
+    }
+
+    async fn get_info(&self) -> Result<AgentInfo> {
+        Err(anyhow::anyhow!("version check timed out"))
+    }
+
+    fn name(&self) -> &'static str {
+        "broken"
+    }
+
+    fn capabilities(&self) -> AgentCapabilities {
+        AgentCapabilities {
+            headless: true,
+            ..AgentCapabilities::default()
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_every_registered_agent_listed() -> Result<()> {
+    // Test: Every registered agent is listed by name, including ones that are unavailable or fail to describe themselves
+    // This test ensures the orchestrator sees all agent types and why one cannot be used
+
+    let mut spawner = SubagentSpawner::new()?;
+    spawner.register_agent(Box::new(CursorCliAgent));
+    spawner.register_agent(Box::new(BrokenAgent));
+
+    let agents = spawner.list_available_agents().await?;
+    let names: Vec<&str> = agents.iter().map(|agent| agent.name.as_str()).collect();
+    assert_eq!(names, vec!["cursor-cli", "broken"]);
+
+    let cursor = &agents[0];
+    assert_eq!(cursor.capabilities, CursorCliAgent.capabilities());
+    assert_eq!(cursor.options_schema, CursorCliAgent.options_schema());
+
+    let broken = &agents[1];
+    assert!(!broken.available);
+    assert!(broken.description.contains("version check timed out"), "Unexpected description: {}", broken.description);
+    assert!(broken.capabilities.headless);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_list_agents_tool() -> Result<()> {
+    // Test: The list_agents tool returns the agents with their capabilities and options schema
+    // This test ensures MCP clients can choose an agent and build valid agent_options for it
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let server = SubagentWorktreeServer::new(repo_path)?;
+
+    let result = server.call_tool(CallToolRequest { name: "list_agents".to_string(), arguments: json!({}) }).await?;
+    assert!(!result.is_error);
+    let listed: Value = serde_json::from_str(&result.content[0].text)?;
+    let agents = listed["agents"].as_array().expect("agents should be listed");
+    let cursor = agents.iter().find(|agent| agent["name"] == "cursor-cli").expect("cursor-cli should be listed");
+    assert!(cursor["available"].is_boolean());
+    for capability in ["pty", "headless", "follow_up_messages", "model_selection", "json_output", "resumable_sessions"] {
+        assert!(cursor["capabilities"][capability].is_boolean(), "{} should be reported", capability);
+    }
+    assert_eq!(cursor["options_schema"]["properties"]["custom_options"]["properties"]["log_level"]["type"], "string");
+
+    // Clients may send no arguments at all
+    let result = server.call_tool(CallToolRequest { name: "list_agents".to_string(), arguments: Value::Null }).await?;
+    assert!(!result.is_error);
+
+    let result = server.call_tool(CallToolRequest {
+        name: "list_agents".to_string(),
+        arguments: json!({ "only_available": true }),
+    }).await?;
+    let listed: Value = serde_json::from_str(&result.content[0].text)?;
+    assert!(listed["agents"].as_array().expect("agents should be listed").iter().all(|agent| agent["available"] == true));
+
+    Ok(())
+}
+
+/// A headless agent that can pick a model and report as JSON
+struct ScriptedAgent;
+
+#[async_trait]
+impl AgentSpawner for ScriptedAgent {
+    async fn is_available(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn spawn(&self, _worktree_path: &Path, _prompt: &str, _options: &AgentOptions) -> Result<SpawnedAgent> {
+        Err(anyhow::anyhow!("not spawned in tests"))
+    }
+
+    async fn get_info(&self) -> Result<AgentInfo> {
+        Ok(AgentInfo::for_agent(self, true, "1.0".to_string(), "Scripted agent".to_string()))
+    }
+
+    fn name(&self) -> &'static str {
+        "scripted"
+    }
+
+    fn capabilities(&self) -> AgentCapabilities {
+        AgentCapabilities {
+            headless: true,
+            model_selection: true,
+            json_output: true,
+            ..AgentCapabilities::default()
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_missing_capabilities_rejected() -> Result<()> {
+    // Test: Options asking for a capability the agent lacks are rejected before spawning
+    // This test ensures requests fail early with a clear error instead of being ignored by the agent
+
+    let mut spawner = SubagentSpawner::new()?;
+    spawner.register_agent(Box::new(CursorCliAgent));
+    spawner.register_agent(Box::new(ScriptedAgent));
+
+    let options: AgentOptions = serde_json::from_value(json!({ "model": "fast", "json_output": true }))?;
+    assert!(spawner.validate_options("scripted", &options).is_ok());
+
+    let error = spawner.validate_options("cursor-cli", &options).unwrap_err().to_string();
+    assert!(error.contains("does not support model selection (requested model 'fast'), JSON output"), "Unexpected error: {}", error);
+    assert!(error.contains("list_agents"), "Error should point to list_agents: {}", error);
+
+    let options: AgentOptions = serde_json::from_value(json!({ "resume_session": "abc" }))?;
+    let error = spawner.validate_options("scripted", &options).unwrap_err().to_string();
+    assert!(error.contains("resumable sessions"), "Unexpected error: {}", error);
+
+    // Spawning checks the same, so callers that skip validation still get the error
+    let (temp_dir, _) = create_temp_git_repo()?;
+    let error = spawner.spawn_agent("scripted", temp_dir.path(), "prompt", &options).await.unwrap_err().to_string();
+    assert!(error.contains("resumable sessions"), "Unexpected error: {}", error);
+
+    assert!(spawner.capabilities("scripted")?.headless);
+    assert!(spawner.capabilities("missing").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_json_output_parsed() {
+    // Test: The result and session of a run are parsed from the JSON lines an agent wrote
+    // This test ensures spawn results report what JSON-output agents did and how to resume them
+
+    let output = concat!(
+        "starting\n",
+        "{\"type\":\"system\",\"session_id\":\"s-1\"}\n",
+        "{\"type\":\"assistant\",\"message\":\"working\"}\n",
+        "{\"type\":\"result\",\"result\":\"Added the tests\",\"session_id\":\"s-1\",\"is_error\":false}\n",
+        "warning on stderr\n",
+    );
+    let parsed = parse_json_output(output).expect("result should be parsed");
+    assert_eq!(parsed, AgentRunResult {
+        result: Some("Added the tests".to_string()),
+        session_id: Some("s-1".to_string()),
+        is_error: false,
+    });
+
+    let failed = parse_json_output("{\"result\":\"Out of budget\",\"is_error\":true}").expect("result should be parsed");
+    assert!(failed.is_error);
+    assert_eq!(failed.session_id, None);
+
+    assert_eq!(parse_json_output("plain text\n{\"type\":\"assistant\"}\n"), None);
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use anyhow::Result;
+use std::path::Path;
+use tempfile::TempDir;
+
+use subagent_worktree_mcp::config::MergeQueueSettings;
+use subagent_worktree_mcp::git_operations::GitWorktreeManager;
+use subagent_worktree_mcp::merge_queue::{MergeQueue, MergeStatus};
+
+use common::create_temp_git_repo;
+
+/// Test helper to run a git command in the repository
+fn git(repo_path: &Path, args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .output()?;
+
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Test helper to create a branch off main with one commit writing `file`
+fn commit_on_branch(repo_path: &Path, branch: &str, file: &str, content: &str) -> Result<()> {
+    git(repo_path, &["checkout", "-b", branch, "main"])?;
+    std::fs::write(repo_path.join(file), content)?;
+    git(repo_path, &["add", file])?;
+    git(repo_path, &["commit", "-m", &format!("Change {} on {}", file, branch)])?;
+    git(repo_path, &["checkout", "main"])?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_merges_branch_and_advances_target() -> Result<()> {
+    // Test: A queued branch that passes verification is merged into the target branch
+    // This test ensures the checked-out target branch and its checkout are fast-forwarded to the merge
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    commit_on_branch(&repo_path, "feature", "feature.txt", "feature\n")?;
+
+    let queue = MergeQueue::load(&temp_dir.path().join("state"))?;
+    queue.enqueue("feature", "main").await?;
+
+    let settings = MergeQueueSettings {
+        verify_command: Some("test -f feature.txt".to_string()),
+        ..Default::default()
+    };
+    let entry = queue.process_next(&manager, &settings).await?.expect("An entry should be processed");
+
+    let MergeStatus::Merged { commit } = &entry.status else {
+        panic!("Branch should be merged: {}", entry.status);
+    };
+    assert_eq!(&git(&repo_path, &["rev-parse", "main"])?, commit);
+    assert!(repo_path.join("feature.txt").exists(), "The main checkout should be updated");
+    assert!(entry.finished_at.is_some());
+
+    assert!(queue.process_next(&manager, &settings).await?.is_none(), "The queue should be empty");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_verification_ejects_branch_with_log() -> Result<()> {
+    // Test: A branch whose merge fails verification is ejected and the target is left unchanged
+    // This test ensures broken branches never reach the target and their output is kept for inspection
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    commit_on_branch(&repo_path, "broken", "broken.txt", "broken\n")?;
+    let main_before = git(&repo_path, &["rev-parse", "main"])?;
+
+    let queue = MergeQueue::load(&temp_dir.path().join("state"))?;
+    queue.enqueue("broken", "main").await?;
+
+    let settings = MergeQueueSettings {
+        verify_command: Some("echo verification output; exit 3".to_string()),
+        ..Default::default()
+    };
+    let entry = queue.process_next(&manager, &settings).await?.expect("An entry should be processed");
+
+    let MergeStatus::Ejected { reason, log_path: Some(log_path) } = &entry.status else {
+        panic!("Branch should be ejected with a log: {}", entry.status);
+    };
+    assert!(reason.contains("exited with code 3"), "Reason should describe the failure: {}", reason);
+    assert!(std::fs::read_to_string(log_path)?.contains("verification output"));
+    assert_eq!(git(&repo_path, &["rev-parse", "main"])?, main_before, "main should not move");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_conflicting_branch_is_ejected() -> Result<()> {
+    // Test: A branch that conflicts with branches merged before it is ejected
+    // This test ensures the queue moves on instead of stopping on a conflict
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    commit_on_branch(&repo_path, "first", "shared.txt", "first\n")?;
+    commit_on_branch(&repo_path, "second", "shared.txt", "second\n")?;
+
+    let queue = MergeQueue::load(&temp_dir.path().join("state"))?;
+    queue.enqueue("first", "main").await?;
+    queue.enqueue("second", "main").await?;
+
+    let settings = MergeQueueSettings::default();
+    let first = queue.process_next(&manager, &settings).await?.expect("first should be processed");
+    assert!(matches!(first.status, MergeStatus::Merged { .. }), "first should merge: {}", first.status);
+
+    let second = queue.process_next(&manager, &settings).await?.expect("second should be processed");
+    let MergeStatus::Ejected { reason, .. } = &second.status else {
+        panic!("second should be ejected: {}", second.status);
+    };
+    assert!(reason.contains("shared.txt"), "Reason should name the conflicting file: {}", reason);
+    assert_eq!(std::fs::read_to_string(repo_path.join("shared.txt"))?, "first\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_queue_persists_across_restarts() -> Result<()> {
+    // Test: Queued branches survive reloading the queue, and duplicates are rejected
+    // This test ensures a server restart does not lose or repeat pending merges
+
+    let temp_dir = TempDir::new()?;
+    let state_dir = temp_dir.path().join("state");
+
+    let queue = MergeQueue::load(&state_dir)?;
+    queue.enqueue("feature", "main").await?;
+    queue.enqueue("other", "main").await?;
+    assert!(queue.enqueue("feature", "main").await.is_err(), "A pending branch should not be queued twice");
+    drop(queue);
+
+    let reloaded = MergeQueue::load(&state_dir)?;
+    let entries = reloaded.entries().await;
+    let branches: Vec<&str> = entries.iter().map(|e| e.branch.as_str()).collect();
+    assert_eq!(branches, vec!["feature", "other"]);
+    assert!(entries.iter().all(|e| e.status == MergeStatus::Queued));
+    assert_eq!(reloaded.pending().await, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_queues_sharing_state_keep_each_others_entries() -> Result<()> {
+    // Test: Two queues loaded from the same state directory both see what the other enqueued
+    // This test ensures servers sharing a repository never overwrite each other's merge queue
+
+    let temp_dir = TempDir::new()?;
+    let state_dir = temp_dir.path().join("state");
+
+    let first = MergeQueue::load(&state_dir)?;
+    let second = MergeQueue::load(&state_dir)?;
+    first.enqueue("feature", "main").await?;
+    second.enqueue("other", "main").await?;
+    assert!(second.enqueue("feature", "main").await.is_err(), "A branch queued by another server should not be queued twice");
+    first.enqueue("third", "main").await?;
+
+    let reloaded = MergeQueue::load(&state_dir)?;
+    let branches: Vec<String> = reloaded.entries().await.into_iter().map(|e| e.branch).collect();
+    assert_eq!(branches, vec!["feature", "other", "third"]);
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+// The daemon is only available on Unix
+#![cfg(unix)]
+
+mod common;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::UnixStream;
+
+use subagent_worktree_mcp::config::CONFIG_FILE_NAME;
+use subagent_worktree_mcp::daemon;
+use subagent_worktree_mcp::progress::{Cancellation, InFlightRequests, ToolCallContext};
+use subagent_worktree_mcp::server::SubagentWorktreeServer;
+
+use common::create_temp_git_repo;
+
+/// Test helper to start a daemon for a repository with a `feature` worktree and a quick and a slow task
+async fn start_daemon(repo_path: std::path::PathBuf, state_dir: &std::path::Path) -> Result<(std::path::PathBuf, Arc<SubagentWorktreeServer>)> {
+    let output = std::process::Command::new("git")
+        .args(["worktree", "add", "-b", "feature", "../feature"])
+        .current_dir(&repo_path)
+        .output()?;
+    assert!(output.status.success(), "Failed to add worktree");
+    std::fs::write(
+        repo_path.join(CONFIG_FILE_NAME),
+        r#"{"commands": {"tasks": {"quick": "true", "slow": "sleep 30"}}}"#,
+    )?;
+    let server = Arc::new(SubagentWorktreeServer::new(repo_path)?);
+    let socket_path = daemon::socket_path(state_dir);
+    let listener = daemon::bind(&socket_path).await?;
+    tokio::spawn(daemon::serve(listener, server.clone(), server.resource_notifier()));
+    Ok((socket_path, server))
+}
+
+/// A client attached to a daemon
+struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: tokio::net::unix::OwnedWriteHalf,
+}
+
+impl Client {
+    async fn attach(socket_path: &std::path::Path) -> Result<Self> {
+        let (reader, writer) = UnixStream::connect(socket_path).await?.into_split();
+        Ok(Self { lines: BufReader::new(reader).lines(), writer })
+    }
+
+    async fn send(&mut self, message: &Value) -> Result<()> {
+        self.writer.write_all(format!("{}\n", message).as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Value> {
+        let line = tokio::time::timeout(Duration::from_secs(5), self.lines.next_line()).await??;
+        Ok(serde_json::from_str(&line.ok_or_else(|| anyhow::anyhow!("Daemon closed the connection"))?)?)
+    }
+}
+
+/// Test helper for a run_in_worktree call of a task in the `feature` worktree
+fn run_task(id: u64, task: &str, progress_token: Option<&str>) -> Value {
+    let mut params = json!({ "name": "run_in_worktree", "arguments": { "worktree_name": "feature", "task": task } });
+    if let Some(token) = progress_token {
+        params["_meta"] = json!({ "progressToken": token });
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "method": "tools/call", "params": params })
+}
+
+#[tokio::test]
+async fn test_run_reports_phase_and_honors_cancellation() -> Result<()> {
+    // Test: A context reports the phase it runs and stops the phase when cancelled
+    // This test ensures cancelled tool calls stop their in-flight work instead of running to completion
+
+    let cancellation = Cancellation::new();
+    let context = ToolCallContext::new(None, cancellation.clone());
+    assert_eq!(context.run("Quick step", async { Ok(1) }).await?, 1);
+    assert!(context.check_cancelled("Quick step").is_ok());
+
+    let canceller = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cancellation.cancel();
+    });
+    let slow = context.run("Slow step", async {
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        Ok(())
+    });
+    let error = tokio::time::timeout(Duration::from_secs(5), slow).await?.unwrap_err().to_string();
+    assert!(error.contains("while slow step"), "Unexpected error: {}", error);
+    assert!(context.check_cancelled("Next step").is_err());
+    canceller.await?;
+
+    // The default context is never cancelled
+    assert!(!ToolCallContext::default().is_cancelled());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_in_flight_requests() -> Result<()> {
+    // Test: Only requests in progress can be cancelled, by the ID they were sent with
+    // This test ensures cancellation notifications reach the call they name and nothing else
+
+    let in_flight = InFlightRequests::new();
+    let cancellation = in_flight.start(&json!(7)).await;
+
+    assert!(!in_flight.cancel(&json!("7")).await, "IDs of different types are different requests");
+    assert!(!cancellation.is_cancelled());
+    assert!(in_flight.cancel(&json!(7)).await);
+    assert!(cancellation.is_cancelled());
+
+    in_flight.finish(&json!(7)).await;
+    assert!(!in_flight.cancel(&json!(7)).await);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_progress_notifications_precede_response() -> Result<()> {
+    // Test: A tool call with a progress token gets progress notifications before its response
+    // This test ensures clients see what a slow tool call is doing while it runs
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let (socket_path, _server) = start_daemon(repo_path, temp_dir.path()).await?;
+    let mut client = Client::attach(&socket_path).await?;
+
+    client.send(&run_task(1, "quick", Some("run-1"))).await?;
+    let progress = client.receive().await?;
+    assert_eq!(progress["method"], "notifications/progress");
+    assert_eq!(progress["params"]["progressToken"], "run-1");
+    assert_eq!(progress["params"]["progress"], 1);
+    assert_eq!(progress["params"]["message"], "Running command");
+
+    let response = client.receive().await?;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["isError"], false, "Unexpected response: {}", response);
+
+    // Without a progress token only the response is sent
+    client.send(&run_task(2, "quick", None)).await?;
+    assert_eq!(client.receive().await?["id"], 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_running_command() -> Result<()> {
+    // Test: A cancellation notification stops a running tool call and kills its command
+    // This test ensures clients can abandon slow tool calls without waiting for them
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let (socket_path, _server) = start_daemon(repo_path, temp_dir.path()).await?;
+    let mut client = Client::attach(&socket_path).await?;
+
+    client.send(&run_task(7, "slow", None)).await?;
+    // Let the command start
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    client.send(&json!({ "jsonrpc": "2.0", "method": "notifications/cancelled", "params": { "requestId": 7, "reason": "user" } })).await?;
+
+    let response = client.receive().await?;
+    assert_eq!(response["id"], 7);
+    assert_eq!(response["result"]["isError"], true);
+    let text = response["result"]["content"][0]["text"].as_str().unwrap_or_default();
+    assert!(text.contains("Cancelled by the client while running command"), "Unexpected result: {}", text);
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+use subagent_worktree_mcp::config::{RepositoryEntry, CONFIG_FILE_NAME};
+use subagent_worktree_mcp::prompts::{builtin_templates, render_prompt, repo_templates, PromptTemplate};
+use subagent_worktree_mcp::registry::{self, SubagentRecord};
+use subagent_worktree_mcp::repositories::RepoContext;
+
+use common::create_temp_git_repo;
+
+/// Test helper to build arguments from pairs
+fn arguments(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+fn test_parse_front_matter() -> Result<()> {
+    // Test: Front matter declares the description and required and optional arguments
+    // This test ensures repository templates are listed with the arguments they expect
+
+    let template = PromptTemplate::parse(
+        "write_tests",
+        "---\ndescription: Add tests\narguments:\n  - worktree_name: Worktree to test\n  - framework?: Test framework\n---\nTest {{branch}} with {{framework}}\n",
+    )?;
+
+    assert_eq!(template.description, "Add tests");
+    let arguments: Vec<(&str, bool)> = template.arguments.iter().map(|a| (a.name.as_str(), a.required)).collect();
+    assert_eq!(arguments, vec![("worktree_name", true), ("framework", false), ("repo", false)]);
+    assert_eq!(template.body, "Test {{branch}} with {{framework}}");
+
+    assert!(PromptTemplate::parse("broken", "---\ndescription: never closed\n").is_err());
+    assert!(PromptTemplate::parse("broken", "---\nauthor: someone\n---\nbody").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_builtin_templates() -> Result<()> {
+    // Test: The server ships templates for delegating, reviewing and resolving conflicts
+    // This test ensures the built-in templates parse and take the arguments their placeholders need
+
+    let templates = builtin_templates()?;
+    let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["delegate_task", "review_subagent_diff", "resolve_integration_conflicts"]);
+
+    for template in &templates {
+        assert!(!template.description.is_empty(), "{} should have a description", template.name);
+        if template.body.contains("{{diff_stat}}") {
+            assert!(template.arguments.iter().any(|a| a.name == "worktree_name" && a.required));
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_review_prompt_filled_from_live_data() -> Result<()> {
+    // Test: The review template is filled with the subagent's prompt and its current diff
+    // This test ensures prompts reflect the worktree's state when they are requested
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let repo = RepoContext::open(&RepositoryEntry::new(repo_path.clone()))?;
+
+    let created = repo.git_manager
+        .create_worktree_with_options("feature", Some("main"), None, &Default::default())
+        .await?;
+    let now = registry::unix_now();
+    repo.registry.lock().await.insert(SubagentRecord {
+        id: registry::generate_id(),
+        branch: "feature".to_string(),
+        worktree_path: created.path.clone(),
+        base_ref: created.base_ref,
+        base_commit: Some(created.base_commit),
+        agent_type: "cursor-cli".to_string(),
+        prompt: "Rewrite the README".to_string(),
+        created_at: now,
+        last_activity: now,
+        last_run: None,
+        agent_pid: None,
+    })?;
+    std::fs::write(created.path.join("README.md"), "# Rewritten {{branch}}\n")?;
+
+    let template = builtin_templates()?.into_iter().find(|t| t.name == "review_subagent_diff").unwrap();
+    let rendered = render_prompt(&repo, &template, &arguments(&[("worktree_name", "feature")])).await?;
+
+    assert!(rendered.contains("branch `feature`"));
+    assert!(rendered.contains("Rewrite the README"));
+    assert!(rendered.contains("+# Rewritten {{branch}}"), "Diff should be included verbatim: {}", rendered);
+    assert!(rendered.contains("Latest verification run: (none)"));
+
+    let error = render_prompt(&repo, &template, &HashMap::new()).await.unwrap_err().to_string();
+    assert!(error.contains("worktree_name"), "Missing arguments should be reported: {}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_repository_templates() -> Result<()> {
+    // Test: Templates in the configured prompts directory are added and can replace built-in ones
+    // This test ensures repositories can ship their own orchestration prompts
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    std::fs::write(repo_path.join(CONFIG_FILE_NAME), r#"{"prompts_dir": ".prompts"}"#)?;
+    std::fs::create_dir(repo_path.join(".prompts"))?;
+    std::fs::write(
+        repo_path.join(".prompts/release_notes.md"),
+        "---\ndescription: Draft release notes\narguments:\n  - version: Version to release\n---\nDraft notes for {{version}} of {{repo}}.",
+    )?;
+    std::fs::write(repo_path.join(".prompts/delegate_task.md"), "Our own delegation prompt: {{task}}")?;
+    std::fs::write(repo_path.join(".prompts/notes.txt"), "not a template")?;
+    let repo = RepoContext::open(&RepositoryEntry::new(repo_path.clone()))?;
+
+    let templates = repo_templates(&repo)?;
+    let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["delegate_task", "review_subagent_diff", "resolve_integration_conflicts", "release_notes"]);
+
+    let release_notes = templates.iter().find(|t| t.name == "release_notes").unwrap();
+    let rendered = render_prompt(&repo, release_notes, &arguments(&[("version", "1.2.0")])).await?;
+    assert_eq!(rendered, "Draft notes for 1.2.0 of test_repo.");
+
+    let delegate = templates.iter().find(|t| t.name == "delegate_task").unwrap();
+    let rendered = render_prompt(&repo, delegate, &arguments(&[("task", "fix it")])).await?;
+    assert_eq!(rendered, "Our own delegation prompt: fix it");
+
+    Ok(())
+}
@@ -0,0 +1,253 @@
+mod common;
+
+use anyhow::Result;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+use subagent_worktree_mcp::config::{AgentDefaults, RepositoryEntry, CONFIG_FILE_NAME};
+use subagent_worktree_mcp::gc::{collect_garbage, GcOptions, GcPolicy};
+use subagent_worktree_mcp::registry::{self, SubagentRecord};
+use subagent_worktree_mcp::repositories::{RepoContext, RepoRegistry};
+
+use common::{create_temp_git_repo, init_git_repo};
+
+#[test]
+fn test_open_loads_repository_config() -> Result<()> {
+    // Test: A repository is named after its directory and its own configuration is loaded
+    // This test ensures per-repository worktree roots and agent defaults come from the repository's config file
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    std::fs::write(
+        repo_path.join(CONFIG_FILE_NAME),
+        r#"{"worktree_root": "../worktrees", "agent_defaults": {"agent_type": "vim"}}"#,
+    )?;
+
+    let repo = RepoContext::open(&RepositoryEntry::new(repo_path.clone()))?;
+
+    assert_eq!(repo.name, "test_repo");
+    assert_eq!(repo.config.agent_defaults.agent_type.as_deref(), Some("vim"));
+    assert_eq!(
+        repo.git_manager.worktree_root()?,
+        repo_path.canonicalize()?.join("../worktrees")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_entry_overrides_repository_config() -> Result<()> {
+    // Test: Settings given when adding a repository override its own configuration
+    // This test ensures the server's repository list can customize repositories it does not own
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    std::fs::write(
+        repo_path.join(CONFIG_FILE_NAME),
+        r#"{"worktree_root": "../worktrees", "agent_defaults": {"agent_type": "vim"}}"#,
+    )?;
+
+    let entry = RepositoryEntry {
+        path: repo_path.clone(),
+        name: Some("backend".to_string()),
+        worktree_root: Some(PathBuf::from(".worktrees")),
+        agent_defaults: Some(AgentDefaults {
+            agent_type: Some("neovim".to_string()),
+            agent_options: None,
+        }),
+    };
+    let repo = RepoContext::open(&entry)?;
+
+    assert_eq!(repo.name, "backend");
+    assert_eq!(repo.config.agent_defaults.agent_type.as_deref(), Some("neovim"));
+    assert_eq!(repo.git_manager.worktree_root()?, repo_path.canonicalize()?.join(".worktrees"));
+
+    Ok(())
+}
+
+#[test]
+fn test_open_rejects_non_repository() -> Result<()> {
+    // Test: Opening a directory that is not a git repository fails
+    // This test ensures add_repository reports bad paths instead of managing them
+
+    let temp_dir = TempDir::new()?;
+
+    let result = RepoContext::open(&RepositoryEntry::new(temp_dir.path().to_path_buf()));
+    assert!(result.is_err(), "A plain directory should not be accepted as a repository");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_worktrees_created_under_worktree_root() -> Result<()> {
+    // Test: New worktrees are created in the configured worktree root
+    // This test ensures worktrees of different repositories can be kept apart
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let mut entry = RepositoryEntry::new(repo_path.clone());
+    entry.worktree_root = Some(PathBuf::from("../worktrees/test_repo"));
+    let repo = RepoContext::open(&entry)?;
+
+    let worktree_path = repo.git_manager.create_worktree("feature", None, None).await?;
+
+    let expected = repo_path.canonicalize()?.join("../worktrees/test_repo/feature");
+    assert_eq!(worktree_path, expected);
+    assert!(worktree_path.join("README.md").exists(), "Worktree should be checked out");
+
+    Ok(())
+}
+
+#[test]
+fn test_resolve_by_name_path_and_default() -> Result<()> {
+    // Test: Repositories are found by name or path, and the first one added is the default
+    // This test ensures tool calls without a repo parameter keep working on the launch repository
+
+    let temp_dir = TempDir::new()?;
+    let first_path = temp_dir.path().join("first");
+    let second_path = temp_dir.path().join("second");
+    init_git_repo(&first_path)?;
+    init_git_repo(&second_path)?;
+
+    let mut repos = RepoRegistry::new();
+    repos.add(RepoContext::open(&RepositoryEntry::new(first_path.clone()))?)?;
+    repos.add(RepoContext::open(&RepositoryEntry::new(second_path.clone()))?)?;
+
+    assert_eq!(repos.resolve(None)?.name, "first");
+    assert_eq!(repos.resolve(Some("second"))?.name, "second");
+    assert_eq!(repos.resolve(Some(second_path.to_str().unwrap()))?.name, "second");
+    assert_eq!(repos.names(), vec!["first".to_string(), "second".to_string()]);
+
+    let error = repos.resolve(Some("third")).unwrap_err().to_string();
+    assert!(error.contains("first, second"), "Error should list the managed repositories: {}", error);
+
+    Ok(())
+}
+
+#[test]
+fn test_resolve_without_repositories() -> Result<()> {
+    // Test: Resolving the default repository fails when none are managed
+    // This test ensures a server started outside a repository explains how to add one
+
+    let repos = RepoRegistry::new();
+
+    let error = repos.resolve(None).unwrap_err().to_string();
+    assert!(error.contains("add_repository"), "Error should point to add_repository: {}", error);
+
+    Ok(())
+}
+
+#[test]
+fn test_add_is_idempotent_and_names_are_unique() -> Result<()> {
+    // Test: Adding the same repository twice keeps one entry, and names cannot be reused
+    // This test ensures two repositories are never confused under one name
+
+    let temp_dir = TempDir::new()?;
+    let first_path = temp_dir.path().join("first");
+    let second_path = temp_dir.path().join("second");
+    init_git_repo(&first_path)?;
+    init_git_repo(&second_path)?;
+
+    let mut repos = RepoRegistry::new();
+    repos.add(RepoContext::open(&RepositoryEntry::new(first_path.clone()))?)?;
+
+    let mut renamed = RepositoryEntry::new(first_path.clone());
+    renamed.name = Some("other".to_string());
+    let existing = repos.add(RepoContext::open(&renamed)?)?;
+    assert_eq!(existing.name, "first", "Re-adding a repository should return the existing entry");
+    assert_eq!(repos.all().len(), 1);
+
+    let mut clashing = RepositoryEntry::new(second_path);
+    clashing.name = Some("first".to_string());
+    let result = repos.add(RepoContext::open(&clashing)?);
+    assert!(result.is_err(), "A name already in use should be rejected");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_linked_worktree_added_as_main_checkout() -> Result<()> {
+    // Test: Adding a linked worktree of a managed repository returns the existing repository
+    // This test ensures one repository's state directory is never shared by two entries
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let mut repos = RepoRegistry::new();
+    let main = repos.add(RepoContext::open(&RepositoryEntry::new(repo_path.clone()))?)?;
+    let worktree_path = main.git_manager.create_worktree("feature", None, None).await?;
+
+    let mut linked = RepositoryEntry::new(worktree_path.clone());
+    linked.name = Some("feature".to_string());
+    let opened = RepoContext::open(&linked)?;
+    assert_eq!(opened.git_manager.repo_path(), repo_path.canonicalize()?, "A linked worktree should open its main checkout");
+
+    let added = repos.add(opened)?;
+    assert_eq!(added.name, main.name, "The managed repository should be returned");
+    assert_eq!(repos.all().len(), 1);
+    assert!(repos.find_by_path(&worktree_path).is_none(), "Only the main checkout is registered");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_worktree_paths_compared_after_resolving() -> Result<()> {
+    // Test: Registered worktree paths match the paths git reports even when they are spelled differently
+    // This test ensures subagents are found, and not reported as stale, when the worktree root has `..` or symlinks
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let mut entry = RepositoryEntry::new(repo_path.clone());
+    entry.worktree_root = Some(PathBuf::from("../worktrees/test_repo"));
+    let repo = RepoContext::open(&entry)?;
+
+    // The path recorded at spawn keeps the `..` of the worktree root
+    let worktree_path = repo.git_manager.create_worktree("feature", None, None).await?;
+    let now = registry::unix_now();
+    let record = SubagentRecord {
+        id: registry::generate_id(),
+        branch: "feature".to_string(),
+        worktree_path: worktree_path.clone(),
+        base_ref: Some("main".to_string()),
+        base_commit: None,
+        agent_type: "cursor-cli".to_string(),
+        prompt: "test prompt".to_string(),
+        created_at: now,
+        last_activity: now,
+        last_run: None,
+        agent_pid: None,
+    };
+    let id = record.id.clone();
+    repo.registry.lock().await.insert(record)?;
+
+    let listed = repo.git_manager.list_worktrees().await?.into_iter()
+        .find(|w| w.branch.as_deref() == Some("feature"))
+        .expect("worktree should be listed");
+    assert!(repo.registry.lock().await.find_by_worktree(&listed.path).is_some(), "Record should be found by the listed path");
+    assert_eq!(repo.resolve_worktree(&id).await?.path, listed.path, "Subagent ID should resolve to its worktree");
+
+    let options = GcOptions { policy: GcPolicy::ReportOnly, idle_ttl_secs: Some(3600), base_branch: None };
+    let report = collect_garbage(&repo.git_manager, &repo.registry, &options).await?;
+    assert!(report.stale_records.is_empty(), "Record should not be stale: {:?}", report.stale_records);
+    assert!(report.actions.is_empty(), "Active subagent should not be collected: {:?}", report.actions);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_background_tasks_run_once_per_repository() -> Result<()> {
+    // Test: Verify only one server runs a repository's background tasks, and another takes over when it stops
+    // This test ensures garbage collection and the merge queue never run twice on the same state directory
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let first = RepoContext::open(&RepositoryEntry::new(repo_path.clone()))?;
+    let second = RepoContext::open(&RepositoryEntry::new(repo_path.clone()))?;
+
+    first.start_background_tasks();
+    second.start_background_tasks();
+    assert!(first.runs_background_tasks(), "The first server should take the lock");
+    assert!(!second.runs_background_tasks(), "The second server should wait for the lock");
+
+    drop(first);
+    let started = std::time::Instant::now();
+    while !second.runs_background_tasks() && started.elapsed().as_secs() < 5 {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert!(second.runs_background_tasks(), "The second server should take over once the first stops");
+
+    Ok(())
+}
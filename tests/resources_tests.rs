@@ -0,0 +1,138 @@
+mod common;
+
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use subagent_worktree_mcp::config::RepositoryEntry;
+use subagent_worktree_mcp::registry::{self, SubagentRecord};
+use subagent_worktree_mcp::repositories::{RepoContext, RepoRegistry};
+use subagent_worktree_mcp::resources::{
+    agent_log_path, capture_agent_output, list_resources, read_resource, spawn_resource_watcher, ResourceNotifier,
+    ResourceUri,
+};
+
+use common::create_temp_git_repo;
+
+/// Test helper to open a repository with one registered subagent worktree on `feature`
+async fn repo_with_subagent(repo_path: &std::path::Path) -> Result<(RepoRegistry, SubagentRecord)> {
+    let mut repos = RepoRegistry::new();
+    let repo = repos.add(RepoContext::open(&RepositoryEntry::new(repo_path.to_path_buf()))?)?;
+
+    let created = repo.git_manager
+        .create_worktree_with_options("feature", Some("main"), None, &Default::default())
+        .await?;
+    let now = registry::unix_now();
+    let record = SubagentRecord {
+        id: registry::generate_id(),
+        branch: "feature".to_string(),
+        worktree_path: created.path,
+        base_ref: created.base_ref,
+        base_commit: Some(created.base_commit),
+        agent_type: "cursor-cli".to_string(),
+        prompt: "test prompt".to_string(),
+        created_at: now,
+        last_activity: now,
+        last_run: None,
+        agent_pid: None,
+    };
+    repo.registry.lock().await.insert(record.clone())?;
+
+    Ok((repos, record))
+}
+
+#[test]
+fn test_resource_uris_round_trip() -> Result<()> {
+    // Test: Resource URIs parse into their kind and format back unchanged
+    // This test ensures clients can subscribe to the URIs the server lists
+
+    for uri in ["worktree://subagent/cursor/fix-bug", "worktree://feature?repo=backend", "agent://1a2b3c4d", "agent://1a2b3c4d/log"] {
+        assert_eq!(ResourceUri::parse(uri)?.to_string(), uri);
+    }
+    assert_eq!(
+        ResourceUri::parse("agent://1a2b3c4d/log")?,
+        ResourceUri::AgentLog { id: "1a2b3c4d".to_string() }
+    );
+    assert!(ResourceUri::parse("file:///etc/passwd").is_err());
+    assert!(ResourceUri::parse("worktree://").is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_list_and_read_worktree_and_agent() -> Result<()> {
+    // Test: Subagent worktrees and agents are listed and read as resources
+    // This test ensures clients can inspect status, diffs and output without calling tools
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let (repos, record) = repo_with_subagent(&repo_path).await?;
+    std::fs::write(record.worktree_path.join("README.md"), "# Changed\n")?;
+
+    let uris: Vec<String> = list_resources(&repos).await?.into_iter().map(|r| r.uri).collect();
+    assert!(uris.contains(&"worktree://feature".to_string()), "Worktree should be listed: {:?}", uris);
+    assert!(uris.contains(&format!("agent://{}", record.id)));
+    assert!(uris.contains(&format!("agent://{}/log", record.id)));
+    assert_eq!(uris.len(), 3, "The main checkout should not be listed: {:?}", uris);
+
+    let worktree: serde_json::Value = serde_json::from_str(&read_resource(&repos, "worktree://feature").await?.text)?;
+    assert_eq!(worktree["subagent_id"], record.id.as_str());
+    assert!(worktree["diff_stat"].as_str().unwrap().contains("README.md"));
+    assert_eq!(worktree["changes"][0], " M README.md");
+
+    let agent: serde_json::Value = serde_json::from_str(&read_resource(&repos, &format!("agent://{}", record.id)).await?.text)?;
+    assert_eq!(agent["record"]["branch"], "feature");
+
+    assert!(read_resource(&repos, "agent://missing").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_agent_output_is_logged_and_published() -> Result<()> {
+    // Test: Agent output is appended to its log and subscribers of the agent's resources are notified
+    // This test ensures clients see agent output as it arrives without polling
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let (repos, record) = repo_with_subagent(&repo_path).await?;
+    let state_dir = repos.resolve(None)?.git_manager.state_dir()?;
+
+    let notifier = Arc::new(ResourceNotifier::new());
+    let log_uri = format!("agent://{}/log", record.id);
+    notifier.subscribe(&log_uri).await?;
+    let mut updates = notifier.updates();
+
+    let output: &'static [u8] = b"working on it\n";
+    capture_agent_output(Box::new(output), agent_log_path(&state_dir, &record.id), record.id.clone(), notifier.clone());
+
+    let updated = tokio::time::timeout(Duration::from_secs(5), updates.recv()).await??;
+    assert_eq!(updated, log_uri, "Only subscribed resources should be published");
+
+    // The update is sent after the write, so the log is complete
+    assert_eq!(read_resource(&repos, &log_uri).await?.text, "working on it\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_watcher_publishes_worktree_changes() -> Result<()> {
+    // Test: A change in a subscribed worktree is published by the watcher
+    // This test ensures worktree subscriptions fire when files change
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let (repos, record) = repo_with_subagent(&repo_path).await?;
+
+    let notifier = Arc::new(ResourceNotifier::new());
+    notifier.subscribe("worktree://feature").await?;
+    let mut updates = notifier.updates();
+    spawn_resource_watcher(Arc::new(RwLock::new(repos)), notifier.clone(), Duration::from_millis(50));
+
+    // Let the watcher record the initial state
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    std::fs::write(record.worktree_path.join("new_file.txt"), "new\n")?;
+
+    let updated = tokio::time::timeout(Duration::from_secs(5), updates.recv()).await??;
+    assert_eq!(updated, "worktree://feature");
+
+    Ok(())
+}
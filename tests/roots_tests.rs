@@ -0,0 +1,245 @@
+// The daemon is only available on Unix
+#![cfg(unix)]
+
+mod common;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::UnixStream;
+
+use subagent_worktree_mcp::daemon;
+use subagent_worktree_mcp::roots::{self, ClientRoots};
+use subagent_worktree_mcp::server::SubagentWorktreeServer;
+use subagent_worktree_mcp::stream_transport;
+
+use common::create_temp_git_repo;
+
+/// Test helper to start a daemon for a repository, returning its socket
+async fn start_daemon(repo_path: std::path::PathBuf, state_dir: &std::path::Path) -> Result<(std::path::PathBuf, Arc<SubagentWorktreeServer>)> {
+    let server = Arc::new(SubagentWorktreeServer::new(repo_path)?);
+    let socket_path = daemon::socket_path(state_dir);
+    let listener = daemon::bind(&socket_path).await?;
+    tokio::spawn(daemon::serve(listener, server.clone(), server.resource_notifier()));
+    Ok((socket_path, server))
+}
+
+/// Test helper to clone a repository next to it under another name
+fn clone_repo(repo_path: &std::path::Path, name: &str) -> Result<std::path::PathBuf> {
+    let clone_path = repo_path.with_file_name(name);
+    let output = std::process::Command::new("git")
+        .args(["clone", "--quiet"])
+        .arg(repo_path)
+        .arg(&clone_path)
+        .output()?;
+    assert!(output.status.success(), "Failed to clone repository");
+    Ok(clone_path)
+}
+
+/// A client attached to a daemon
+struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: tokio::net::unix::OwnedWriteHalf,
+}
+
+impl Client {
+    async fn attach(socket_path: &std::path::Path) -> Result<Self> {
+        let (reader, writer) = UnixStream::connect(socket_path).await?.into_split();
+        Ok(Self { lines: BufReader::new(reader).lines(), writer })
+    }
+
+    async fn send(&mut self, message: &Value) -> Result<()> {
+        self.writer.write_all(format!("{}\n", message).as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Value> {
+        let line = tokio::time::timeout(Duration::from_secs(5), self.lines.next_line()).await??;
+        Ok(serde_json::from_str(&line.ok_or_else(|| anyhow::anyhow!("Daemon closed the connection"))?)?)
+    }
+}
+
+impl Client {
+    /// Initialize as a client supporting roots and answer the server's roots/list request
+    async fn initialize_with_roots(&mut self, roots: &[&std::path::Path]) -> Result<()> {
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": { "protocolVersion": "2025-03-26", "capabilities": { "roots": { "listChanged": true } } },
+        })).await?;
+        assert_eq!(self.receive().await?["id"], 1);
+        self.send(&json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await?;
+        self.answer_roots(roots).await
+    }
+
+    /// Answer the server's next roots/list request
+    async fn answer_roots(&mut self, roots: &[&std::path::Path]) -> Result<()> {
+        let request = self.receive().await?;
+        assert_eq!(request["method"], "roots/list", "Expected a roots request: {}", request);
+        let roots: Vec<Value> = roots.iter()
+            .map(|root| json!({ "uri": format!("file://{}", root.display()), "name": "root" }))
+            .collect();
+        self.send(&json!({ "jsonrpc": "2.0", "id": request["id"], "result": { "roots": roots } })).await
+    }
+
+    /// Call a tool, returning its text and whether it failed
+    async fn call_tool(&mut self, id: u64, name: &str, arguments: Value) -> Result<(String, bool)> {
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": "tools/call", "params": { "name": name, "arguments": arguments } })).await?;
+        let response = self.receive().await?;
+        assert_eq!(response["id"], id, "Unexpected response: {}", response);
+        let text = response["result"]["content"][0]["text"].as_str().unwrap_or_default().to_string();
+        Ok((text, response["result"]["isError"] == true))
+    }
+}
+
+#[test]
+fn test_root_paths() -> Result<()> {
+    // Test: File URIs of roots are turned into local paths
+    // This test ensures roots with escaped characters map to the right directories
+
+    assert_eq!(roots::root_path("file:///home/dev/my%20project")?, std::path::PathBuf::from("/home/dev/my project"));
+    assert_eq!(roots::root_path("file://localhost/srv/repo")?, std::path::PathBuf::from("/srv/repo"));
+    assert!(roots::root_path("https://example.com/repo").is_err());
+    assert!(roots::root_path("file://server/share").is_err());
+
+    let parsed = roots::parse_roots(&json!({ "roots": [{ "uri": "file:///a" }, { "uri": "ftp://b" }, { "name": "no uri" }] }))?;
+    assert_eq!(parsed, vec![std::path::PathBuf::from("/a")]);
+
+    let temp_dir = TempDir::new()?;
+    let roots = vec![temp_dir.path().join("repo")];
+    assert!(roots::is_within(&temp_dir.path().join("repo/src"), &roots));
+    assert!(roots::is_within(&temp_dir.path().join("repo"), &roots));
+    assert!(!roots::is_within(&temp_dir.path().join("repository"), &roots), "Roots are matched by path component");
+    assert!(!roots::is_within(temp_dir.path(), &roots));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_calls_wait_for_requested_roots() -> Result<()> {
+    // Test: Once roots were requested, calls wait for them instead of running unrestricted
+    // This test ensures a tool call sent right after initialization is already scoped to the roots
+
+    let roots = Arc::new(ClientRoots::new());
+    assert_eq!(roots.current().await?, None, "Clients without roots are not restricted");
+
+    roots.requested().await;
+    let setter = roots.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        setter.set(vec![std::path::PathBuf::from("/work")]).await;
+    });
+    assert_eq!(roots.current().await?, Some(vec![std::path::PathBuf::from("/work")]));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_roots_choose_repository() -> Result<()> {
+    // Test: Calls that name no repository use the repository of the client's root, not the launch directory
+    // This test ensures a server started in a fixed directory works on the project the client has open
+
+    let (temp_dir, launch_repo) = create_temp_git_repo()?;
+    let project = clone_repo(&launch_repo, "project")?;
+    std::fs::create_dir(project.join("src"))?;
+    let (socket_path, _server) = start_daemon(launch_repo.clone(), temp_dir.path()).await?;
+
+    let mut client = Client::attach(&socket_path).await?;
+    // A root inside the repository maps to the whole repository
+    client.initialize_with_roots(&[&project.join("src")]).await?;
+
+    let (text, failed) = client.call_tool(2, "list_worktrees", json!({})).await?;
+    assert!(!failed, "Unexpected failure: {}", text);
+    assert!(text.contains(&project.display().to_string()), "Should list the root's repository: {}", text);
+
+    let (text, failed) = client.call_tool(3, "list_worktrees", json!({ "repo": "test_repo" })).await?;
+    assert!(failed);
+    assert!(text.contains("outside the client's roots"), "Unexpected result: {}", text);
+
+    let (text, failed) = client.call_tool(4, "add_repository", json!({ "path": launch_repo })).await?;
+    assert!(failed);
+    assert!(text.contains("outside the client's roots"), "Unexpected result: {}", text);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_roots_changed() -> Result<()> {
+    // Test: The roots are requested again when the client reports that they changed
+    // This test ensures opening another folder in the client makes its repository available
+
+    let (temp_dir, launch_repo) = create_temp_git_repo()?;
+    let project = clone_repo(&launch_repo, "project")?;
+    let (socket_path, _server) = start_daemon(launch_repo.clone(), temp_dir.path()).await?;
+
+    let mut client = Client::attach(&socket_path).await?;
+    client.initialize_with_roots(&[&project]).await?;
+    assert!(client.call_tool(2, "list_worktrees", json!({ "repo": "test_repo" })).await?.1);
+
+    client.send(&json!({ "jsonrpc": "2.0", "method": "notifications/roots/list_changed" })).await?;
+    client.answer_roots(&[&project, &launch_repo]).await?;
+
+    let (text, failed) = client.call_tool(3, "list_worktrees", json!({ "repo": "test_repo" })).await?;
+    assert!(!failed, "Unexpected failure: {}", text);
+
+    // Clients without roots are not restricted
+    let mut other = Client::attach(&socket_path).await?;
+    assert!(!other.call_tool(1, "list_worktrees", json!({ "repo": "project" })).await?.1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_in_process_stdio_requests_roots() -> Result<()> {
+    // Test: A client served in-process over a byte stream is asked for its roots like a daemon client
+    // This test ensures --in-process stdio scopes tool calls to the client's roots too
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let other_path = clone_repo(&repo_path, "other_repo")?;
+    let server = Arc::new(SubagentWorktreeServer::new(repo_path.clone())?);
+    let (client, served) = tokio::io::duplex(64 * 1024);
+    let (server_reader, server_writer) = tokio::io::split(served);
+    tokio::spawn(stream_transport::serve_client(server_reader, server_writer, server.clone(), server.resource_notifier()));
+    let (reader, mut writer) = tokio::io::split(client);
+    let mut lines = BufReader::new(reader).lines();
+
+    let initialize = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": { "protocolVersion": "2025-03-26", "capabilities": { "roots": {} } },
+    });
+    writer.write_all(format!("{}\n", initialize).as_bytes()).await?;
+    let mut receive = async || -> Result<Value> {
+        let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line()).await??;
+        Ok(serde_json::from_str(&line.ok_or_else(|| anyhow::anyhow!("Server closed the stream"))?)?)
+    };
+    assert_eq!(receive().await?["id"], 1);
+    let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+    writer.write_all(format!("{}\n", initialized).as_bytes()).await?;
+    let request = receive().await?;
+    assert_eq!(request["method"], "roots/list", "Expected a roots request: {}", request);
+
+    let root = json!({ "uri": format!("file://{}", other_path.display()), "name": "root" });
+    let answer = json!({ "jsonrpc": "2.0", "id": request["id"], "result": { "roots": [root] } });
+    let call = json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": { "name": "list_worktrees", "arguments": { "repo": "test_repo" } },
+    });
+    writer.write_all(format!("{}\n{}\n", answer, call).as_bytes()).await?;
+
+    let response = receive().await?;
+    assert_eq!(response["id"], 2, "Unexpected response: {}", response);
+    let text = response["result"]["content"][0]["text"].as_str().unwrap_or_default();
+    assert_eq!(response["result"]["isError"], true);
+    assert!(text.contains("outside the client's roots"), "Unexpected result: {}", text);
+
+    Ok(())
+}
@@ -0,0 +1,237 @@
+mod common;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use subagent_worktree_mcp::mcp::types::ToolInputSchema;
+use serde_json::{json, Value};
+use std::path::Path;
+
+use subagent_worktree_mcp::agent_monitor::AgentMonitorConfig;
+use subagent_worktree_mcp::schema;
+use subagent_worktree_mcp::server::{CleanupConfig, GcConfig, SubagentConfig, SubagentWorktreeServer, SyncConfig};
+use subagent_worktree_mcp::subagent_spawner::{
+    AgentInfo, AgentOptions, AgentSpawner, CursorCliAgent, SpawnedAgent, SubagentSpawner,
+};
+
+use common::create_temp_git_repo;
+
+/// Test helper to find the input schema of a tool
+fn tool_schema(tools: &[subagent_worktree_mcp::mcp::types::Tool], name: &str) -> Value {
+    let tool = tools.iter().find(|tool| tool.name == name).expect("tool should exist");
+    let ToolInputSchema(schema) = &tool.input_schema;
+    schema.clone()
+}
+
+/// An agent that is always available and declares its own options
+struct ScriptedAgent;
+
+#[async_trait]
+impl AgentSpawner for ScriptedAgent {
+    async fn is_available(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn spawn(&self, _worktree_path: &Path, _prompt: &str, _options: &AgentOptions) -> Result<SpawnedAgent> {
+        Err(anyhow::anyhow!("not spawned in tests"))
+    }
+
+    async fn get_info(&self) -> Result<AgentInfo> {
+        Err(anyhow::anyhow!("no info in tests"))
+    }
+
+    fn name(&self) -> &'static str {
+        "scripted"
+    }
+
+    fn custom_options_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "script": { "type": "string", "description": "Script to run" },
+                "shell": { "type": "string", "enum": ["sh", "bash"], "default": "sh", "description": "Shell to run it with" }
+            },
+            "additionalProperties": false
+        })
+    }
+}
+
+#[test]
+fn test_config_schemas_derived_from_types() {
+    // Test: Tool schemas are generated from the config types the arguments are parsed into
+    // This test ensures the advertised parameters cannot drift from the fields the server reads
+
+    let spawn = schema::input_schema::<SubagentConfig>();
+    assert_eq!(spawn["type"], "object");
+    assert_eq!(spawn["required"], json!(["prompt"]));
+    for field in ["repo", "branch_name", "base_ref", "prompt", "worktree_dir", "agent_type", "agent_options",
+        "sparse_paths", "sparse_cone", "submodules", "bootstrap", "keep_on_failure"] {
+        assert!(spawn["properties"][field].is_object(), "spawn_subagent schema should have {}", field);
+    }
+    assert_eq!(spawn["properties"]["bootstrap"]["properties"]["on_failure"]["default"], "keep");
+    assert_eq!(spawn["properties"]["agent_options"]["properties"]["new_window"]["default"], true);
+
+    let cleanup = schema::input_schema::<CleanupConfig>();
+    assert_eq!(cleanup["required"], json!(["worktree_name"]));
+    assert_eq!(cleanup["properties"]["force"]["default"], false);
+
+    let monitor = schema::input_schema::<AgentMonitorConfig>();
+    assert!(monitor.get("required").is_none(), "monitor_agents should take no required arguments");
+    let config: AgentMonitorConfig = serde_json::from_value(json!({})).expect("empty arguments should parse");
+    assert!(!config.only_our_agents);
+
+    let sync = schema::input_schema::<SyncConfig>();
+    assert_eq!(sync["required"], json!(["worktree_name"]));
+    assert_eq!(sync["properties"]["strategy"]["type"], "string");
+    assert_eq!(sync["properties"]["strategy"]["enum"], json!(["rebase", "merge"]));
+
+    let gc = schema::input_schema::<GcConfig>();
+    assert!(gc.get("required").is_none(), "gc_worktrees should take no required arguments");
+    assert_eq!(gc["properties"]["policy"]["enum"], json!(["report_only", "archive", "remove"]));
+}
+
+#[test]
+fn test_derived_schemas_are_flat() {
+    // Test: Every tool's schema inlines nested types and describes optional fields without null
+    // This test ensures clients and the documentation generator can read every property directly
+
+    for tool in SubagentWorktreeServer::get_tools() {
+        let ToolInputSchema(schema) = tool.input_schema;
+        let text = schema.to_string();
+        assert!(!text.contains("$ref") && !text.contains("definitions"), "Schema should be inlined: {}", text);
+        assert!(!text.contains("null"), "Schema should not advertise null: {}", text);
+        assert!(schema.get("title").is_none() && schema.get("$schema").is_none());
+
+        for (name, property) in schema["properties"].as_object().expect("schema should have properties") {
+            assert!(property["type"].is_string(), "{} should have a single type", name);
+            let description = property["description"].as_str().unwrap_or_default();
+            assert!(!description.is_empty(), "{} should be described", name);
+            assert!(!description.contains('\n'), "{} description should be one line", name);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_agent_type_lists_available_agents() -> Result<()> {
+    // Test: The advertised agent_type choices are the registered agents available on this system
+    // This test ensures clients are never offered agents the server cannot start
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let server = SubagentWorktreeServer::new(repo_path)?;
+
+    let mut spawner = SubagentSpawner::new()?;
+    spawner.register_agent(Box::new(CursorCliAgent));
+    let available: Vec<&str> = spawner.available_agents().await.iter().map(|agent| agent.name()).collect();
+
+    let tools = server.tools().await;
+    assert_eq!(tools.len(), SubagentWorktreeServer::get_tools().len());
+    let spawn = tool_schema(&tools, "spawn_subagent");
+    let agent_type = &spawn["properties"]["agent_type"];
+    assert_eq!(agent_type["type"], "string");
+    assert!(agent_type["description"].is_string());
+    if available.is_empty() {
+        assert!(agent_type.get("enum").is_none(), "An empty enum would reject every agent");
+    } else {
+        assert_eq!(agent_type["enum"], json!(available));
+    }
+
+    // No agents are hard-coded into the static schema
+    let static_spawn = tool_schema(&SubagentWorktreeServer::get_tools(), "spawn_subagent");
+    assert!(static_spawn["properties"]["agent_type"].get("enum").is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_agents_contribute_options_schemas() -> Result<()> {
+    // Test: Each available agent contributes the schema of its own options
+    // This test ensures agents with different options are all described to clients
+
+    let mut spawner = SubagentSpawner::new()?;
+    spawner.register_agent(Box::new(ScriptedAgent));
+    let agents = spawner.available_agents().await;
+    assert_eq!(agents.len(), 1);
+
+    // The agent's own options take the place of the untyped custom options
+    let options = ScriptedAgent.options_schema();
+    assert_eq!(options["properties"]["new_window"]["type"], "boolean");
+    assert_eq!(options["properties"]["extra_args"]["type"], "array");
+    let custom = &options["properties"]["custom_options"];
+    assert!(custom["description"].is_string());
+    assert_eq!(custom["properties"]["shell"]["enum"], json!(["sh", "bash"]));
+    assert_eq!(custom["additionalProperties"], false);
+
+    let single = schema::agent_options("Agent-specific options", vec![("scripted", ScriptedAgent.options_schema())]);
+    assert_eq!(single["title"], "scripted");
+    assert_eq!(single["description"], "Agent-specific options");
+    assert_eq!(single["properties"]["custom_options"]["properties"]["script"]["type"], "string");
+
+    let both = schema::agent_options("Agent-specific options", vec![
+        ("scripted", ScriptedAgent.options_schema()),
+        ("cursor-cli", CursorCliAgent.options_schema()),
+    ]);
+    assert_eq!(both["type"], "object");
+    let titles: Vec<&str> = both["anyOf"].as_array().expect("options should be alternatives").iter()
+        .filter_map(|alternative| alternative["title"].as_str())
+        .collect();
+    assert_eq!(titles, vec!["scripted", "cursor-cli"]);
+
+    let none = schema::agent_options("Agent-specific options", Vec::new());
+    assert_eq!(none, json!({ "type": "object", "description": "Agent-specific options" }));
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_options_validated() {
+    // Test: Custom options are checked against the schema the agent declares before it is spawned
+    // This test ensures unknown keys and mistyped values are rejected with a clear error instead of becoming arguments
+
+    let options = |custom: Value| AgentOptions {
+        custom_options: serde_json::from_value(custom).expect("options should parse"),
+        ..AgentOptions::default()
+    };
+
+    assert!(CursorCliAgent.validate_options(&AgentOptions::default()).is_ok());
+    assert!(CursorCliAgent.validate_options(&options(json!({ "profile": "agents", "log_level": "debug", "disable_extensions": true }))).is_ok());
+
+    let error = CursorCliAgent.validate_options(&options(json!({ "reuse-window": "1" }))).unwrap_err().to_string();
+    assert!(error.contains("Unknown option 'reuse-window'"), "Unexpected error: {}", error);
+    assert!(error.contains("profile") && error.contains("extra_args"), "Error should name the alternatives: {}", error);
+
+    let error = CursorCliAgent.validate_options(&options(json!({ "disable_extensions": "yes" }))).unwrap_err().to_string();
+    assert!(error.contains("must be of type boolean"), "Unexpected error: {}", error);
+
+    let error = CursorCliAgent.validate_options(&options(json!({ "log_level": "loud" }))).unwrap_err().to_string();
+    assert!(error.contains("expected one of") && error.contains("\"debug\""), "Unexpected error: {}", error);
+
+    // Agents that declare no options accept none
+    let no_options = json!({ "type": "object", "properties": {}, "additionalProperties": false });
+    let custom = options(json!({ "anything": 1 })).custom_options;
+    let error = schema::validate_options("plain", &no_options, &custom).unwrap_err().to_string();
+    assert!(error.contains("takes no custom options"), "Unexpected error: {}", error);
+    assert!(schema::validate_options("open", &json!({ "type": "object" }), &custom).is_ok());
+}
+
+#[test]
+fn test_raw_arguments_need_extra_args() -> Result<()> {
+    // Test: Raw arguments are only passed through the explicit extra_args field
+    // This test ensures arbitrary arguments cannot reach the agent command through custom options
+
+    let options: AgentOptions = serde_json::from_value(json!({
+        "custom_options": { "shell": "bash" },
+        "extra_args": ["--verbose"]
+    }))?;
+    assert!(options.new_window, "Omitted options should keep their defaults");
+    assert_eq!(options.extra_args, vec!["--verbose"]);
+    assert!(ScriptedAgent.validate_options(&options).is_ok());
+
+    let mut spawner = SubagentSpawner::new()?;
+    spawner.register_agent(Box::new(ScriptedAgent));
+    let error = spawner.validate_options("missing", &options).unwrap_err().to_string();
+    assert!(error.contains("not found"), "Unexpected error: {}", error);
+
+    let options: AgentOptions = serde_json::from_value(json!({ "custom_options": { "--verbose": true } }))?;
+    assert!(spawner.validate_options("scripted", &options).is_err());
+
+    Ok(())
+}
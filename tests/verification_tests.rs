@@ -0,0 +1,135 @@
+mod common;
+
+use anyhow::Result;
+use indexmap::IndexMap;
+
+use subagent_worktree_mcp::config::CommandSettings;
+use subagent_worktree_mcp::git_operations::GitWorktreeManager;
+use subagent_worktree_mcp::registry::{self, SubagentRecord, SubagentRegistry};
+use subagent_worktree_mcp::verification::{resolve_command, run_in_worktree, RunTarget};
+
+use common::create_temp_git_repo;
+
+/// Test helper for settings with a `test` and a `fail` task and one allowed command
+fn settings() -> CommandSettings {
+    let mut tasks = IndexMap::new();
+    tasks.insert("test".to_string(), "test -f README.md".to_string());
+    tasks.insert("fail".to_string(), "echo broken >&2; exit 2".to_string());
+    CommandSettings {
+        tasks,
+        allowed_commands: vec!["echo hello".to_string()],
+        timeout_secs: None,
+    }
+}
+
+#[test]
+fn test_only_allow_listed_commands_resolve() -> Result<()> {
+    // Test: Tasks resolve to their command, and raw commands must be allow-listed verbatim
+    // This test ensures run_in_worktree cannot be used to run arbitrary shell commands
+
+    let settings = settings();
+
+    let (task, command) = resolve_command(&settings, &RunTarget::Task("test".to_string()))?;
+    assert_eq!(task.as_deref(), Some("test"));
+    assert_eq!(command, "test -f README.md");
+
+    let (task, command) = resolve_command(&settings, &RunTarget::Command("echo hello".to_string()))?;
+    assert_eq!(task, None);
+    assert_eq!(command, "echo hello");
+
+    let (task, _) = resolve_command(&settings, &RunTarget::Command("test -f README.md".to_string()))?;
+    assert_eq!(task.as_deref(), Some("test"), "A task's command should be attributed to the task");
+
+    assert!(resolve_command(&settings, &RunTarget::Command("echo hello; rm -rf /".to_string())).is_err());
+    assert!(resolve_command(&settings, &RunTarget::Command("rm -rf target".to_string())).is_err());
+
+    let error = resolve_command(&settings, &RunTarget::Task("lint".to_string())).unwrap_err().to_string();
+    assert!(error.contains("test, fail"), "Error should list the configured tasks: {}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_records_status_and_commit() -> Result<()> {
+    // Test: Runs report success or failure with captured output and the commit they ran on
+    // This test ensures an orchestrator can tell whether the current commit has a green run
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    let worktree_path = manager.create_worktree("feature", None, None).await?;
+    let head = manager.resolve_commit(&worktree_path, "HEAD").await?;
+    let settings = settings();
+
+    let passed = run_in_worktree(&manager, &worktree_path, &settings, &RunTarget::Task("test".to_string()), None).await?;
+    assert!(passed.record.success);
+    assert_eq!(passed.record.exit_code, Some(0));
+    assert_eq!(passed.record.commit.as_deref(), Some(head.as_str()));
+    assert!(!passed.record.dirty);
+
+    std::fs::write(worktree_path.join("README.md"), "changed\n")?;
+    let failed = run_in_worktree(&manager, &worktree_path, &settings, &RunTarget::Task("fail".to_string()), None).await?;
+    assert!(!failed.record.success);
+    assert_eq!(failed.record.exit_code, Some(2));
+    assert!(failed.record.dirty, "The run should note uncommitted changes");
+    assert!(failed.output.stderr.contains("broken"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_times_out() -> Result<()> {
+    // Test: A command running past its timeout is killed and reported as failed
+    // This test ensures a hanging test suite cannot block the server
+
+    let (_temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    let worktree_path = manager.create_worktree("feature", None, None).await?;
+    let settings = CommandSettings {
+        allowed_commands: vec!["sleep 10".to_string()],
+        ..Default::default()
+    };
+
+    let run = run_in_worktree(&manager, &worktree_path, &settings, &RunTarget::Command("sleep 10".to_string()), Some(1)).await?;
+    assert!(run.record.timed_out);
+    assert!(!run.record.success);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_is_stored_in_registry() -> Result<()> {
+    // Test: The latest run is stored on the subagent's registry record and survives reloading
+    // This test ensures integration can be gated on a green run after a server restart
+
+    let (temp_dir, repo_path) = create_temp_git_repo()?;
+    let manager = GitWorktreeManager::new(repo_path.clone())?;
+    let worktree_path = manager.create_worktree("feature", None, None).await?;
+    let state_dir = temp_dir.path().join("state");
+
+    let now = registry::unix_now();
+    let id = registry::generate_id();
+    let mut subagents = SubagentRegistry::load(&state_dir)?;
+    subagents.insert(SubagentRecord {
+        id: id.clone(),
+        branch: "feature".to_string(),
+        worktree_path: worktree_path.clone(),
+        base_ref: Some("main".to_string()),
+        base_commit: None,
+        agent_type: "cursor-cli".to_string(),
+        prompt: "test prompt".to_string(),
+        created_at: now,
+        last_activity: now,
+        last_run: None,
+        agent_pid: None,
+    })?;
+
+    let run = run_in_worktree(&manager, &worktree_path, &settings(), &RunTarget::Task("test".to_string()), None).await?;
+    subagents.record_run(&id, run.record)?;
+
+    let reloaded = SubagentRegistry::load(&state_dir)?;
+    let last_run = reloaded.get(&id).and_then(|r| r.last_run.clone()).expect("The run should be recorded");
+    assert!(last_run.success);
+    assert_eq!(last_run.task.as_deref(), Some("test"));
+
+    Ok(())
+}